@@ -3,8 +3,12 @@
 use chrono::{offset::TimeZone as _, Local};
 use clap::{Parser, Subcommand};
 use indicatif::{ProgressBar, ProgressStyle};
-use smb3::FileAllInformation;
-use smb3_client::Result;
+use smb3::{
+    FileAllInformation, FileFsAttributeInformation, FileFsFullSizeInformation, SecurityDescriptor,
+    SecurityDescriptorControl, SecurityInformation,
+};
+use smb3_client::{FileTimes, OpenOptions, Result, UncPath};
+use std::io;
 use std::path::PathBuf;
 use tokio::net::TcpStream;
 
@@ -16,6 +20,9 @@ enum Command {
     Upload {
         local: PathBuf,
         remote: PathBuf,
+        /// Give the remote file the local modification and access times
+        #[arg(short = 't', long)]
+        preserve_times: bool,
     },
     Download {
         remote: PathBuf,
@@ -27,15 +34,40 @@ enum Command {
     Delete {
         remote: PathBuf,
     },
+    Mkdir {
+        remote: PathBuf,
+        /// Create missing parents too
+        #[arg(short, long)]
+        parents: bool,
+    },
+    Rmdir {
+        remote: PathBuf,
+        /// Remove everything inside too
+        #[arg(short, long)]
+        recursive: bool,
+    },
     Rename {
         remote_src: PathBuf,
         remote_target: PathBuf,
     },
+    ListShares,
+    FsInfo,
+    GetSecurity {
+        remote: PathBuf,
+    },
+    SetSecurity {
+        remote: PathBuf,
+        sddl: String,
+    },
+    ListStreams {
+        remote: PathBuf,
+    },
 }
 
 #[derive(Parser)]
 struct Options {
     host: String,
+    /// The share to connect to, by name or as `\\\\server\\share` or `smb://server/share`
     tree_path: String,
     #[clap(long, default_value_t = smb3_client::PORT)]
     port: u16,
@@ -47,13 +79,18 @@ struct Options {
     command: Command,
 }
 
+/// Opening just to read works on read-only shares too.
+fn read_only() -> OpenOptions {
+    OpenOptions::new().read(true)
+}
+
 struct Cli {
     client: smb3_client::Client<TcpStream>,
 }
 
 impl Cli {
     async fn read_dir(&mut self, path: PathBuf) -> Result<()> {
-        let root = self.client.look_up(path).await?;
+        let root = read_only().open_file_id(&mut self.client, path).await?;
         let resp = self.client.query_directory(root).await?;
         for entry in resp {
             let change_str = Local
@@ -67,16 +104,29 @@ impl Cli {
         Ok(())
     }
 
-    async fn upload(&mut self, local: PathBuf, remote: PathBuf) -> Result<()> {
+    async fn upload(
+        &mut self,
+        local: PathBuf,
+        remote: PathBuf,
+        preserve_times: bool,
+    ) -> Result<()> {
         let file_id = self.client.create_file(remote).await?;
         let file = tokio::fs::File::open(local).await?;
-        let progress = ProgressBar::new(file.metadata().await?.len()).with_style(
+        let metadata = file.metadata().await?;
+        let progress = ProgressBar::new(metadata.len()).with_style(
             ProgressStyle::with_template("{wide_bar} {percent}% {binary_bytes_per_sec}").unwrap(),
         );
         self.client
             .write_all(file_id, progress.wrap_async_read(file))
             .await?;
         self.client.flush(file_id).await?;
+        // Set once the writes are done, the server doesn't touch times set explicitly
+        if preserve_times {
+            let times = FileTimes::new()
+                .modified(metadata.modified()?)
+                .accessed(metadata.accessed()?);
+            self.client.set_times(file_id, times).await?;
+        }
         self.client.close(file_id).await?;
         Ok(())
     }
@@ -88,7 +138,7 @@ impl Cli {
             local
         };
 
-        let file_id = self.client.look_up(&remote).await?;
+        let file_id = read_only().open_file_id(&mut self.client, &remote).await?;
 
         let size = 5037662208;
         let progress = ProgressBar::new(size).with_style(
@@ -104,7 +154,7 @@ impl Cli {
     }
 
     async fn query_info(&mut self, remote: PathBuf) -> Result<()> {
-        let file_id = self.client.look_up(&remote).await?;
+        let file_id = read_only().open_file_id(&mut self.client, &remote).await?;
         let info: FileAllInformation = self.client.query_info(file_id).await?;
         println!("{info:#?}");
         self.client.close(file_id).await?;
@@ -116,34 +166,123 @@ impl Cli {
         Ok(())
     }
 
+    async fn mkdir(&mut self, remote: PathBuf, parents: bool) -> Result<()> {
+        if parents {
+            self.client.create_dir_all(remote).await
+        } else {
+            self.client.create_dir(remote).await
+        }
+    }
+
+    async fn rmdir(&mut self, remote: PathBuf, recursive: bool) -> Result<()> {
+        if recursive {
+            self.client.remove_dir_all(remote).await
+        } else {
+            self.client.remove_dir(remote).await
+        }
+    }
+
     async fn rename(&mut self, remote_str: PathBuf, remote_target: PathBuf) -> Result<()> {
         let file_id = self.client.look_up(remote_str).await?;
         self.client.rename(file_id, remote_target).await?;
         self.client.close(file_id).await?;
         Ok(())
     }
+
+    async fn list_shares(&mut self) -> Result<()> {
+        for share in self.client.list_shares().await? {
+            let kind = format!("{:?}", share.kind);
+            println!("{:20} {kind:12} {}", share.name, share.comment);
+        }
+        Ok(())
+    }
+
+    async fn fs_info(&mut self) -> Result<()> {
+        let root = self.client.look_up("/").await?;
+        let size: FileFsFullSizeInformation = self.client.query_fs_info(root).await?;
+        let attributes: FileFsAttributeInformation = self.client.query_fs_info(root).await?;
+        self.client.close(root).await?;
+
+        println!("file system: {}", attributes.file_system_name);
+        println!("total bytes: {}", size.total_bytes());
+        println!("available bytes: {}", size.caller_available_bytes());
+        println!(
+            "maximum name length: {}",
+            attributes.maximum_component_name_length
+        );
+        println!("attributes: {:?}", attributes.file_system_attributes);
+        Ok(())
+    }
+
+    async fn get_security(&mut self, remote: PathBuf) -> Result<()> {
+        let info =
+            SecurityInformation::OWNER | SecurityInformation::GROUP | SecurityInformation::DACL;
+        let descriptor = self.client.get_security(remote, info).await?;
+        println!("{}", descriptor.to_sddl());
+        Ok(())
+    }
+
+    async fn set_security(&mut self, remote: PathBuf, sddl: String) -> Result<()> {
+        let descriptor = SecurityDescriptor::from_sddl(&sddl)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let mut info = SecurityInformation::empty();
+        info.set(SecurityInformation::OWNER, descriptor.owner.is_some());
+        info.set(SecurityInformation::GROUP, descriptor.group.is_some());
+        info.set(
+            SecurityInformation::DACL,
+            descriptor.dacl.is_some()
+                || descriptor
+                    .control
+                    .contains(SecurityDescriptorControl::DACL_PRESENT),
+        );
+        self.client.set_security(remote, &descriptor, info).await?;
+        Ok(())
+    }
+
+    async fn list_streams(&mut self, remote: PathBuf) -> Result<()> {
+        for stream in self.client.list_streams(remote).await? {
+            println!("{:>10} {}", stream.stream_size, stream.stream_name);
+        }
+        Ok(())
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let opts = Options::parse();
 
+    let tree_path = match UncPath::new(&opts.tree_path) {
+        Ok(tree_path) => tree_path,
+        Err(_) => UncPath::new(&format!("\\\\{}\\{}", opts.host, opts.tree_path))?,
+    };
     let transport = TcpStream::connect((opts.host, opts.port)).await?;
-    let client =
-        smb3_client::Client::new(transport, &opts.username, &opts.password, &opts.tree_path)
-            .await?;
+    let mut client =
+        smb3_client::Client::new(transport, &opts.username, &opts.password, tree_path).await?;
+    let port = opts.port;
+    client.set_connector(move |server: &str| TcpStream::connect((server.to_owned(), port)));
 
     let mut cli = Cli { client };
     match opts.command {
         Command::ReadDir { path } => cli.read_dir(path).await?,
-        Command::Upload { local, remote } => cli.upload(local, remote).await?,
+        Command::Upload {
+            local,
+            remote,
+            preserve_times,
+        } => cli.upload(local, remote, preserve_times).await?,
         Command::Download { remote, local } => cli.download(remote, local).await?,
         Command::QueryInfo { remote } => cli.query_info(remote).await?,
         Command::Delete { remote } => cli.delete(remote).await?,
+        Command::Mkdir { remote, parents } => cli.mkdir(remote, parents).await?,
+        Command::Rmdir { remote, recursive } => cli.rmdir(remote, recursive).await?,
         Command::Rename {
             remote_src,
             remote_target,
         } => cli.rename(remote_src, remote_target).await?,
+        Command::ListShares => cli.list_shares().await?,
+        Command::FsInfo => cli.fs_info().await?,
+        Command::GetSecurity { remote } => cli.get_security(remote).await?,
+        Command::SetSecurity { remote, sddl } => cli.set_security(remote, sddl).await?,
+        Command::ListStreams { remote } => cli.list_streams(remote).await?,
     }
 
     Ok(())
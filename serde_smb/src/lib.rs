@@ -3,7 +3,7 @@ use countio::Counter;
 use derive_more::From;
 use serde::de::Visitor;
 use serde::{de, ser, Deserialize, Serialize};
-use std::{collections::BTreeMap, fmt, io};
+use std::{collections::BTreeMap, fmt, io, mem};
 
 pub use serde_smb_derive::{
     DeserializeSmbEnum, DeserializeSmbStruct, SerializeSmbEnum, SerializeSmbStruct,
@@ -530,6 +530,8 @@ pub struct Deserializer<Reader> {
     pending_offset: Option<&'static str>,
     pending_next_entry_offset: Option<&'static str>,
     next_entry_offset: BTreeMap<&'static str, usize>,
+    /// Set when the last struct read had a next entry offset of zero, ending its chain
+    end_of_chain: bool,
 }
 
 impl<Reader: io::Read> Deserializer<Reader> {
@@ -544,6 +546,7 @@ impl<Reader: io::Read> Deserializer<Reader> {
             pending_offset: None,
             pending_next_entry_offset: None,
             next_entry_offset: BTreeMap::new(),
+            end_of_chain: false,
         }
     }
 
@@ -815,6 +818,7 @@ impl<'de, 'a, Reader: io::Read> de::Deserializer<'de> for &'a mut Deserializer<R
             name: "",
             fields: None,
             field: 0,
+            chained: false,
             max_fields: None,
             starting_offset: self.reader.reader_bytes(),
             deserializer: self,
@@ -825,11 +829,13 @@ impl<'de, 'a, Reader: io::Read> de::Deserializer<'de> for &'a mut Deserializer<R
     where
         V: Visitor<'de>,
     {
+        self.end_of_chain = false;
         visitor.visit_seq(SequenceDeserializer {
             name: "",
             max_fields: self.sequence_limit.take(),
             fields: None,
             field: 0,
+            chained: true,
             starting_offset: self.reader.reader_bytes(),
             deserializer: self,
         })
@@ -843,6 +849,7 @@ impl<'de, 'a, Reader: io::Read> de::Deserializer<'de> for &'a mut Deserializer<R
             name: "",
             fields: None,
             field: 0,
+            chained: false,
             max_fields: None,
             starting_offset: self.reader.reader_bytes(),
             deserializer: self,
@@ -863,6 +870,7 @@ impl<'de, 'a, Reader: io::Read> de::Deserializer<'de> for &'a mut Deserializer<R
             name,
             fields: None,
             field: 0,
+            chained: false,
             max_fields: None,
             starting_offset: self.reader.reader_bytes(),
             deserializer: self,
@@ -890,6 +898,7 @@ impl<'de, 'a, Reader: io::Read> de::Deserializer<'de> for &'a mut Deserializer<R
             name,
             fields: Some(fields),
             field: 0,
+            chained: false,
             max_fields: None,
             starting_offset: self.reader.reader_bytes(),
             deserializer: self,
@@ -928,6 +937,8 @@ struct SequenceDeserializer<'a, Reader> {
     name: &'static str,
     fields: Option<&'static [&'static str]>,
     field: usize,
+    /// Whether the elements may be a chain linked by next entry offsets
+    chained: bool,
     max_fields: Option<Count>,
     starting_offset: usize,
 }
@@ -939,6 +950,11 @@ impl<'de, 'a, Reader: io::Read> de::SeqAccess<'de> for SequenceDeserializer<'a,
     where
         T: de::DeserializeSeed<'de>,
     {
+        // A chain of entries linked by next entry offsets ends at the one with offset zero
+        if self.chained && mem::take(&mut self.deserializer.end_of_chain) {
+            return Ok(None);
+        }
+
         if let Some(max_fields) = self.max_fields {
             match max_fields {
                 Count::Elements(v) => {
@@ -971,6 +987,7 @@ impl<'de, 'a, Reader: io::Read> de::SeqAccess<'de> for SequenceDeserializer<'a,
                 if let Some(next_entry_offset) =
                     self.deserializer.next_entry_offset.remove(self.name)
                 {
+                    self.deserializer.end_of_chain = next_entry_offset == 0;
                     while self.starting_offset + next_entry_offset
                         > self.deserializer.reader.reader_bytes()
                     {
@@ -57,9 +57,9 @@ fn serialize_deserialize() {
     assert_eq!(deserialized, f);
 }
 
-#[derive(Debug, PartialEq, SerializeSmbStruct, DeserializeSmbStruct)]
+#[derive(Clone, Debug, PartialEq, SerializeSmbStruct, DeserializeSmbStruct)]
 #[smb(next_entry_offset = 12)]
-struct Element {
+pub struct Element {
     a: u32,
 }
 
@@ -99,6 +99,25 @@ fn collection_with_bytes() {
     assert_eq!(deserialized, f);
 }
 
+#[test]
+fn chain_without_count() {
+    let e = vec![Element { a: 0x33445566 }, Element { a: 0x778899aa }];
+
+    let expected = [
+        0xc, 0x0, 0x0, 0x0, // e[0] next_entry_offset
+        0x66, 0x55, 0x44, 0x33, // e[0] a
+        0x0, 0x0, 0x0, 0x0, // e[0] padding
+        0x0, 0x0, 0x0, 0x0, // e[1] next_entry_offset
+        0xaa, 0x99, 0x88, 0x77, // e[1] a
+        0x0, 0x0, 0x0, 0x0, // trailing data
+    ];
+    assert_bytes_equal(&expected[..20], &serde_smb::to_vec(&e).unwrap());
+
+    // The chain ends at the element with a next entry offset of zero
+    let deserialized: Vec<Element> = serde_smb::from_slice(&expected[..]).unwrap();
+    assert_eq!(deserialized, e);
+}
+
 #[derive(Debug, PartialEq, SerializeSmbStruct, DeserializeSmbStruct)]
 struct StructWithString {
     a: u16,
@@ -280,6 +299,8 @@ pub enum TestEnum {
     Baz,
     #[smb(tag = "Quxl", size = "0")]
     Qux,
+    #[smb(tag = "Varl")]
+    Var(Vec<Element>),
 }
 
 #[test]
@@ -365,3 +386,28 @@ fn test_enum_qux_no_value() {
     let deserialized: TestEnum = serde_smb::from_slice(&expected[..]).unwrap();
     assert_eq!(deserialized, f);
 }
+
+#[test]
+fn test_enum_var_measured_size() {
+    let f = TestEnum::Var(vec![Element { a: 0x11223344 }, Element { a: 0x55667788 }]);
+
+    let actual = serde_smb::to_vec(&f).unwrap();
+
+    let expected = [
+        0x0c, 0x00, // name offset
+        0x04, 0x00, // name length
+        0x00, 0x00, // padding
+        16, 0x00, // data offset
+        0x14, 0x00, 0x00, 0x00, // data length
+        b'V', b'a', b'r', b'l', // name
+        0xc, 0x0, 0x0, 0x0, // data[0] next_entry_offset
+        0x44, 0x33, 0x22, 0x11, // data[0] a
+        0x0, 0x0, 0x0, 0x0, // data[0] padding
+        0x0, 0x0, 0x0, 0x0, // data[1] next_entry_offset
+        0x88, 0x77, 0x66, 0x55, // data[1] a
+    ];
+    assert_bytes_equal(&expected, &actual);
+
+    let deserialized: TestEnum = serde_smb::from_slice(&expected[..]).unwrap();
+    assert_eq!(deserialized, f);
+}
@@ -13,7 +13,8 @@ use syn::{
 struct EnumVariant {
     ident: Ident,
     tag: String,
-    size: usize,
+    /// The size of the data, when missing it is measured from the value
+    size: Option<usize>,
     reserved_value: Option<Type>,
     offset: Option<Expr>,
 }
@@ -44,9 +45,15 @@ pub fn serialize_smb_enum_inner(input: DeriveInput) -> Result<ItemImpl> {
         let name_count = u16::try_from(v.tag.len()).unwrap();
         let data_offset = v.offset.clone().unwrap_or(parse_quote!(0));
         let name = &v.tag;
-        let data_count = u32::try_from(v.size).unwrap();
+        let data_count: Expr = match v.size {
+            Some(size) => {
+                let size = u32::try_from(size).unwrap();
+                parse_quote!(#size)
+            }
+            None => parse_quote!(u32::try_from(::serde_smb::size(f)).unwrap()),
+        };
         let name_offset: Expr = parse_quote!(&(12u16 + #base_offset));
-        let pat: Pat = if v.reserved_value.is_some() || v.size == 0 {
+        let pat: Pat = if v.reserved_value.is_some() || v.size == Some(0) {
             parse_quote!(Self::#ident)
         } else {
             parse_quote!(Self::#ident(f))
@@ -56,16 +63,22 @@ pub fn serialize_smb_enum_inner(input: DeriveInput) -> Result<ItemImpl> {
         } else {
             parse_quote!(&f)
         };
-        let data_offset_expr: Expr = if v.size == 0 {
+        let data_offset_expr: Expr = if v.size == Some(0) {
             parse_quote!(&0u16)
         } else {
             parse_quote!(&u16::try_from(12 + #name_count + #base_offset + #data_offset).unwrap())
         };
-        let data_expr: Option<Expr> = (v.size != 0 || v.reserved_value.is_some()).then_some(
+        let data_expr: Option<Expr> = (v.size != Some(0) || v.reserved_value.is_some()).then_some(
             parse_quote! {
                 ::serde::ser::SerializeStruct::serialize_field(&mut s, "data", #expr)?
             }
         );
+        // Data with a measured size is padded out to its offset here
+        let padding_expr: Option<Expr> = v.size.is_none().then_some(parse_quote! {
+            ::serde::ser::SerializeStruct::serialize_field(
+                &mut s, "padding", &[0u8; #data_offset]
+            )?
+        });
         parse_quote! {
             #pat => {
                 ::serde::ser::SerializeStruct::serialize_field(&mut s, "name$offset", &#name_offset)?;
@@ -74,8 +87,9 @@ pub fn serialize_smb_enum_inner(input: DeriveInput) -> Result<ItemImpl> {
                 ::serde::ser::SerializeStruct::serialize_field(
                     &mut s, "data_offset", #data_offset_expr
                 )?;
-                ::serde::ser::SerializeStruct::serialize_field(&mut s, "data_count", &#data_count)?;
+                ::serde::ser::SerializeStruct::serialize_field(&mut s, "data_count", &(#data_count))?;
                 ::serde::ser::SerializeStruct::serialize_field(&mut s, "name", &(#name.as_bytes()))?;
+                #padding_expr;
                 #data_expr
             }
         }
@@ -132,8 +146,19 @@ pub fn deserialize_smb_enum_inner(input: DeriveInput) -> Result<ItemImpl> {
                     Ok(#self_ident::#ident)
                 }
             }
-        } else if v.size == 0 {
+        } else if v.size == Some(0) {
             parse_quote!(#tag => Ok(#self_ident::#ident))
+        } else if v.size.is_none() {
+            let data_offset = v.offset.clone().unwrap_or(parse_quote!(0));
+            parse_quote! {
+                #tag => {
+                    let _: [u8; #data_offset] = seq.next_element()?
+                        .ok_or(::serde::de::Error::missing_field("padding"))?;
+                    Ok(#self_ident::#ident(
+                        seq.next_element()?.ok_or(::serde::de::Error::missing_field("data"))?
+                    ))
+                }
+            }
         } else {
             parse_quote! {
                 #tag => Ok(#self_ident::#ident(
@@ -194,6 +219,7 @@ pub fn deserialize_smb_enum_inner(input: DeriveInput) -> Result<ItemImpl> {
                     "data_offset",
                     "data_count",
                     "name",
+                    "padding",
                     "data",
                 ];
                 deserializer.deserialize_struct(
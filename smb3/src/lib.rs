@@ -11,8 +11,32 @@ use serde_smb::{
     SerializeSmbStruct,
 };
 use std::fmt;
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Like `impl_serde_for_bitflags!`, but keeps bits without a constant instead of failing, for
+/// flags servers report which newer servers may add to.
+macro_rules! impl_serde_for_bitflags_retain {
+    ($name:ident) => {
+        impl Serialize for $name {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                self.bits().serialize(serializer)
+            }
+        }
 
-#[derive(SerializeWithDiscriminant, DeserializeWithDiscriminant, Copy, Clone, Debug, PartialEq)]
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                Ok(Self::from_bits_retain(Deserialize::deserialize(
+                    deserializer,
+                )?))
+            }
+        }
+    };
+}
+
+#[derive(
+    SerializeWithDiscriminant, DeserializeWithDiscriminant, Copy, Clone, Debug, PartialEq, Eq, Hash,
+)]
 #[repr(u16)]
 pub enum Command {
     Negotiate = 0x0000,
@@ -54,15 +78,30 @@ pub struct HeaderFlags {
     pub unused2: B2,
 }
 
-#[derive(Serialize, Deserialize, Default, Copy, Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Default, Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct MessageId(pub u64);
 
 #[derive(Serialize, Deserialize, Default, Copy, Clone, Debug, PartialEq)]
 pub struct ProcessId(pub u32);
 
-#[derive(Serialize, Deserialize, Default, Copy, Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Default, Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct TreeId(pub u32);
 
+/// Identifies a request the server went on with asynchronously, given in its interim response.
+/// In the header of an async message it takes the place of the process and tree ids.
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct AsyncId(pub u64);
+
+impl AsyncId {
+    pub fn from_ids(process_id: ProcessId, tree_id: TreeId) -> Self {
+        Self(process_id.0 as u64 | (tree_id.0 as u64) << 32)
+    }
+
+    pub fn to_ids(self) -> (ProcessId, TreeId) {
+        (ProcessId(self.0 as u32), TreeId((self.0 >> 32) as u32))
+    }
+}
+
 #[derive(Serialize, Deserialize, Default, Copy, Clone, Debug, PartialEq)]
 pub struct SessionId(pub u64);
 
@@ -92,7 +131,9 @@ pub struct RequestHeader {
 
 pub const HEADER_SIZE: usize = 64;
 
-#[derive(SerializeWithDiscriminant, DeserializeWithDiscriminant, Copy, Clone, Debug, PartialEq)]
+#[derive(
+    SerializeWithDiscriminant, DeserializeWithDiscriminant, Copy, Clone, Debug, PartialEq, Eq, Hash,
+)]
 #[repr(u32)]
 pub enum NtStatus {
     Success = 0x00000000,
@@ -606,10 +647,226 @@ pub enum NtStatus {
     TooManyLinks = 0xc0000265,
     QuotaListInconsistent = 0xc0000266,
     FileIsOffline = 0xc0000267,
+    NotAReparsePoint = 0xc0000275,
+    IoReparseTagInvalid = 0xc0000276,
+    IoReparseTagMismatch = 0xc0000277,
+    IoReparseDataInvalid = 0xc0000278,
+    IoReparseTagNotHandled = 0xc0000279,
     Networksessionexpired = 0xc000035c,
     Toomanyuids = 0xc000205a,
 }
 
+impl NtStatus {
+    /// Whether the status is a failure, rather than success, information or a warning.
+    pub fn is_error(self) -> bool {
+        self as u32 >> 30 == 3
+    }
+
+    /// The description MS-ERREF gives the status, for the ones which commonly come up.
+    pub fn description(self) -> Option<&'static str> {
+        Some(match self {
+            Self::Success => "The operation completed successfully.",
+            Self::Pending => "The operation that was requested is pending completion.",
+            Self::BufferOverflow => "The data was too large to fit into the specified buffer.",
+            Self::NoMoreFiles => "No more files were found which match the file specification.",
+            Self::StoppedOnSymlink => {
+                "The create operation stopped after reaching a symbolic link."
+            }
+            Self::Unsuccessful => "The requested operation was unsuccessful.",
+            Self::NotImplemented => "The requested operation is not implemented.",
+            Self::InvalidInfoClass => {
+                "The specified information class is not a valid information class for the \
+                 specified object."
+            }
+            Self::InfoLengthMismatch => {
+                "The specified information record length does not match the length that is \
+                 required for the specified information class."
+            }
+            Self::InvalidHandle => "An invalid HANDLE was specified.",
+            Self::InvalidParameter => "An invalid parameter was passed to a service or function.",
+            Self::NoSuchDevice => "A device that does not exist was specified.",
+            Self::NoSuchFile => "The file does not exist.",
+            Self::InvalidDeviceRequest => {
+                "The specified request is not a valid operation for the target device."
+            }
+            Self::EndOfFile => {
+                "The end-of-file marker has been reached. There is no valid data in the file \
+                 beyond this marker."
+            }
+            Self::NoMemory => {
+                "Not enough virtual memory or paging file quota is available to complete the \
+                 specified operation."
+            }
+            Self::AccessDenied => {
+                "A process has requested access to an object but has not been granted those \
+                 access rights."
+            }
+            Self::BufferTooSmall => {
+                "The buffer is too small to contain the entry. No information has been written \
+                 to the buffer."
+            }
+            Self::ObjectNameInvalid => "The object name is invalid.",
+            Self::ObjectNameNotFound => "The object name is not found.",
+            Self::ObjectNameCollision => "The object name already exists.",
+            Self::ObjectPathInvalid => "The object path component was not a directory object.",
+            Self::ObjectPathNotFound => "The path does not exist.",
+            Self::ObjectPathSyntaxBad => "The object path syntax is invalid.",
+            Self::SharingViolation => {
+                "A file cannot be opened because the share access flags are incompatible."
+            }
+            Self::FileLockConflict => {
+                "A requested read/write cannot be granted due to a conflicting file lock."
+            }
+            Self::LockNotGranted => {
+                "A requested file lock cannot be granted due to other existing locks."
+            }
+            Self::DeletePending => {
+                "A non-close operation has been requested of a file object that has a delete \
+                 pending."
+            }
+            Self::PrivilegeNotHeld => "A required privilege is not held by the client.",
+            Self::LogonFailure => {
+                "The attempted logon is invalid. This is either due to a bad username or \
+                 authentication information."
+            }
+            Self::PasswordExpired => "The user account password has expired.",
+            Self::AccountDisabled => {
+                "The referenced account is currently disabled and cannot be logged on to."
+            }
+            Self::DiskFull => "There is not enough space on the disk.",
+            Self::InsufficientResources => {
+                "Insufficient system resources exist to complete the API."
+            }
+            Self::PipeBusy => {
+                "The specified pipe is set to complete operations and there are \
+                 current I/O operations queued so that it cannot be changed to queue operations."
+            }
+            Self::PipeDisconnected => "The specified named pipe is in the disconnected state.",
+            Self::PipeClosing => "The specified named pipe is in the closing state.",
+            Self::IoTimeout => {
+                "The specified I/O operation was not completed before the time-out period \
+                 expired."
+            }
+            Self::FileIsADirectory => {
+                "The file that was specified as a target is a directory, and the caller \
+                 specified that it could be anything but a directory."
+            }
+            Self::NotSupported => "The request is not supported.",
+            Self::BadNetworkPath => "The network path cannot be located.",
+            Self::NetworkNameDeleted => "The network name was deleted.",
+            Self::NetworkAccessDenied => "Network access is denied.",
+            Self::BadNetworkName => {
+                "The specified share name cannot be found on the remote server."
+            }
+            Self::DirectoryNotEmpty => "The directory trying to be deleted is not empty.",
+            Self::NotADirectory => "A requested opened file is not a directory.",
+            Self::NameTooLong => "A specified name string is too long for its intended use.",
+            Self::Cancelled => "The I/O request was canceled.",
+            Self::CannotDelete => {
+                "An attempt has been made to remove a file or directory that cannot be deleted."
+            }
+            Self::FileClosed => {
+                "An I/O request other than close and several other special case operations was \
+                 attempted using a file object that had already been closed."
+            }
+            Self::PipeBroken => {
+                "The pipe operation has failed because the other end of the pipe has been closed."
+            }
+            Self::UserSessionDeleted => "The remote user session has been deleted.",
+            Self::PathNotCovered => {
+                "The contacted server does not support the indicated part of the DFS namespace."
+            }
+            Self::NotAReparsePoint => "The NTFS file or directory is not a reparse point.",
+            Self::Networksessionexpired => {
+                "The client's session has expired; so the client must re-authenticate to \
+                 continue accessing the remote resources."
+            }
+            _ => return None,
+        })
+    }
+}
+
+/// Like `ObjectNameNotFound (0xc0000034): The object name is not found.`
+impl fmt::Display for NtStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{self:?} ({:#010x})", *self as u32)?;
+        if let Some(description) = self.description() {
+            write!(f, ": {description}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for NtStatus {}
+
+/// The nearest kind of I/O error, so failures can be handled without listing every status.
+impl From<NtStatus> for std::io::ErrorKind {
+    fn from(status: NtStatus) -> Self {
+        use std::io::ErrorKind;
+        match status {
+            NtStatus::NoSuchFile
+            | NtStatus::ObjectNameNotFound
+            | NtStatus::ObjectPathNotFound
+            | NtStatus::NoSuchDevice
+            | NtStatus::BadNetworkPath
+            | NtStatus::BadNetworkName
+            | NtStatus::NotFound => ErrorKind::NotFound,
+            NtStatus::AccessDenied
+            | NtStatus::NetworkAccessDenied
+            | NtStatus::PrivilegeNotHeld
+            | NtStatus::CannotDelete
+            | NtStatus::DeletePending
+            | NtStatus::LogonFailure
+            | NtStatus::WrongPassword
+            | NtStatus::AccountRestriction
+            | NtStatus::AccountDisabled
+            | NtStatus::AccountExpired
+            | NtStatus::AccountLockedOut
+            | NtStatus::PasswordExpired => ErrorKind::PermissionDenied,
+            NtStatus::ObjectNameCollision => ErrorKind::AlreadyExists,
+            NtStatus::InvalidParameter
+            | NtStatus::InvalidParameterMix
+            | NtStatus::InvalidInfoClass
+            | NtStatus::InvalidLevel
+            | NtStatus::InvalidDeviceRequest
+            | NtStatus::ObjectPathSyntaxBad => ErrorKind::InvalidInput,
+            NtStatus::ObjectNameInvalid | NtStatus::NameTooLong | NtStatus::IllegalCharacter => {
+                ErrorKind::InvalidFilename
+            }
+            NtStatus::NotADirectory | NtStatus::ObjectPathInvalid => ErrorKind::NotADirectory,
+            NtStatus::FileIsADirectory => ErrorKind::IsADirectory,
+            NtStatus::DirectoryNotEmpty => ErrorKind::DirectoryNotEmpty,
+            NtStatus::SharingViolation
+            | NtStatus::FileLockConflict
+            | NtStatus::LockNotGranted
+            | NtStatus::PipeBusy => ErrorKind::ResourceBusy,
+            NtStatus::EndOfFile => ErrorKind::UnexpectedEof,
+            NtStatus::NotSupported | NtStatus::NotImplemented | NtStatus::EasNotSupported => {
+                ErrorKind::Unsupported
+            }
+            NtStatus::DiskFull => ErrorKind::StorageFull,
+            NtStatus::QuotaExceeded => ErrorKind::QuotaExceeded,
+            NtStatus::MediaWriteProtected => ErrorKind::ReadOnlyFilesystem,
+            NtStatus::NotSameDevice => ErrorKind::CrossesDevices,
+            NtStatus::TooManyLinks => ErrorKind::TooManyLinks,
+            NtStatus::IoTimeout | NtStatus::TransactionTimedOut => ErrorKind::TimedOut,
+            NtStatus::PipeBroken | NtStatus::PipeClosing | NtStatus::PipeDisconnected => {
+                ErrorKind::BrokenPipe
+            }
+            NtStatus::ConnectionRefused => ErrorKind::ConnectionRefused,
+            NtStatus::ConnectionReset => ErrorKind::ConnectionReset,
+            NtStatus::ConnectionAborted | NtStatus::RequestAborted => ErrorKind::ConnectionAborted,
+            NtStatus::ConnectionDisconnected | NtStatus::UserSessionDeleted => {
+                ErrorKind::NotConnected
+            }
+            NtStatus::HostUnreachable => ErrorKind::HostUnreachable,
+            NtStatus::NetworkUnreachable => ErrorKind::NetworkUnreachable,
+            NtStatus::NoMemory => ErrorKind::OutOfMemory,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, PartialEq)]
 pub struct ProtocolId([u8; 4]);
 
@@ -650,6 +907,16 @@ pub struct ResponseHeader {
     pub signature: Signature,
 }
 
+impl ResponseHeader {
+    /// The async id of an async response, like the interim one to a request the server goes on
+    /// with in the background.
+    pub fn async_id(&self) -> Option<AsyncId> {
+        self.flags
+            .r#async()
+            .then(|| AsyncId::from_ids(self.process_id, self.tree_id))
+    }
+}
+
 bitflags! {
     #[derive(PartialEq, Eq, Copy, Clone, Debug)]
     pub struct SecurityMode: u8 {
@@ -813,6 +1080,80 @@ impl fmt::Debug for Time {
     }
 }
 
+/// 100-nanosecond intervals between January 1st 1601 and the Unix epoch
+const UNIX_EPOCH_INTERVALS: i64 = 116_444_736_000_000_000;
+
+impl Time {
+    /// Setting a time to this in `FileBasicInformation` leaves it as it is.
+    pub const UNCHANGED: Self = Self { intervals: 0 };
+    /// Setting a time to this in `FileBasicInformation` leaves it as it is, and stops the server
+    /// updating it for the rest of the open.
+    pub const FROZEN: Self = Self { intervals: -1 };
+    /// Setting a time to this in `FileBasicInformation` undoes `FROZEN`.
+    pub const UNFROZEN: Self = Self { intervals: -2 };
+
+    /// The time as a `SystemTime`, or `None` when it is too far from the Unix epoch for one.
+    pub fn to_system_time(&self) -> Option<SystemTime> {
+        let since_epoch = self.intervals.checked_sub(UNIX_EPOCH_INTERVALS)?;
+        let intervals = since_epoch.unsigned_abs();
+        let duration = Duration::new(
+            intervals / 10_000_000,
+            (intervals % 10_000_000) as u32 * 100,
+        );
+        if since_epoch < 0 {
+            UNIX_EPOCH.checked_sub(duration)
+        } else {
+            UNIX_EPOCH.checked_add(duration)
+        }
+    }
+}
+
+impl From<SystemTime> for Time {
+    fn from(time: SystemTime) -> Self {
+        // Times too far out for a `Time` are clamped
+        let intervals =
+            |duration: Duration| i64::try_from(duration.as_nanos() / 100).unwrap_or(i64::MAX);
+        let intervals = match time.duration_since(UNIX_EPOCH) {
+            Ok(after) => UNIX_EPOCH_INTERVALS.saturating_add(intervals(after)),
+            Err(e) => UNIX_EPOCH_INTERVALS.saturating_sub(intervals(e.duration())),
+        };
+        Self { intervals }
+    }
+}
+
+#[test]
+fn time_system_time_round_trip() {
+    let t = Time {
+        intervals: 0x01d9fb8c14a5ee49,
+    };
+    let system_time = t.to_system_time().unwrap();
+    assert_eq!(
+        system_time.duration_since(UNIX_EPOCH).unwrap(),
+        Duration::new(1696950704, 455226500)
+    );
+    assert_eq!(Time::from(system_time), t);
+    assert_eq!(Time::from(UNIX_EPOCH).intervals, UNIX_EPOCH_INTERVALS);
+
+    let before_epoch = Time { intervals: 0 }.to_system_time();
+    assert_eq!(
+        UNIX_EPOCH.duration_since(before_epoch.unwrap()).unwrap(),
+        Duration::from_secs(11_644_473_600)
+    );
+    let far_future = Time {
+        intervals: i64::MAX,
+    };
+    if let Some(system_time) = far_future.to_system_time() {
+        assert_eq!(Time::from(system_time), far_future);
+    }
+    assert_eq!(
+        Time {
+            intervals: i64::MIN
+        }
+        .to_system_time(),
+        None
+    );
+}
+
 #[cfg(feature = "chrono")]
 impl Time {
     pub fn to_date_time(&self) -> chrono::NaiveDateTime {
@@ -1198,6 +1539,9 @@ pub enum CreateContext {
     RequestLease(RequestLease),
     #[smb(tag = "QFid", size = "0", reserved_value = "u32")]
     QueryOnDiskId,
+    /// Extended attributes to give a newly created file
+    #[smb(tag = "ExtA", offset = 4)]
+    ExtendedAttributes(Vec<FileFullEaInformation>),
 }
 
 #[derive(SerializeSmbStruct, DeserializeSmbStruct, Clone, Debug, PartialEq)]
@@ -1251,7 +1595,7 @@ impl HasCommand for CreateRequest {
     }
 }
 
-#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct FileId {
     pub persistent: u64,
     pub volatile: u64,
@@ -1370,6 +1714,73 @@ pub struct FileIdBothDirectoryInformation {
     pub file_name: String,
 }
 
+/// Despite the name this is the layout of `FileIdFullDirectoryInformation`, which is what it
+/// is queried as.
+impl HasFileInformationClass for FileIdBothDirectoryInformation {
+    fn file_information_class() -> FileInformationClass {
+        FileInformationClass::FileIdFullDirectoryInformation
+    }
+}
+
+/// Just the names of the entries of a directory.
+#[derive(SerializeSmbStruct, DeserializeSmbStruct, Clone, Debug, PartialEq)]
+pub struct FileNamesInformation {
+    pub file_index: u32,
+    #[smb(collection(count(int_type = "u32", element_size = 2)))]
+    pub file_name: String,
+}
+
+impl HasFileInformationClass for FileNamesInformation {
+    fn file_information_class() -> FileInformationClass {
+        FileInformationClass::FileNamesInformation
+    }
+}
+
+#[derive(SerializeSmbStruct, DeserializeSmbStruct, Clone, Debug, PartialEq)]
+pub struct FileDirectoryInformation {
+    pub file_index: u32,
+    pub creation_time: Time,
+    pub last_access_time: Time,
+    pub last_write_time: Time,
+    pub change_time: Time,
+    pub end_of_file: i64,
+    pub allocation_size: i64,
+    pub file_attributes: FileAttributes,
+    #[smb(collection(count(int_type = "u32", element_size = 2)))]
+    pub file_name: String,
+}
+
+impl HasFileInformationClass for FileDirectoryInformation {
+    fn file_information_class() -> FileInformationClass {
+        FileInformationClass::FileDirectoryInformation
+    }
+}
+
+/// Like `FileIdBothDirectoryInformation`, with the reparse tag and a 128-bit file id.
+#[derive(SerializeSmbStruct, DeserializeSmbStruct, Clone, Debug, PartialEq)]
+pub struct FileIdExtdDirectoryInformation {
+    pub file_index: u32,
+    pub creation_time: Time,
+    pub last_access_time: Time,
+    pub last_write_time: Time,
+    pub change_time: Time,
+    pub end_of_file: i64,
+    pub allocation_size: i64,
+    pub file_attributes: FileAttributes,
+    pub ea_size: u32,
+    /// Only meaningful when `file_attributes` has `REPARSE_POINT`
+    pub reparse_point_tag: ReparseTag,
+    pub file_id: [u8; 16],
+    #[smb(collection(count(int_type = "u32", after = "file_attributes", element_size = 2)))]
+    pub file_name: String,
+}
+
+impl HasFileInformationClass for FileIdExtdDirectoryInformation {
+    fn file_information_class() -> FileInformationClass {
+        FileInformationClass::FileIdExtdDirectoryInformation
+    }
+}
+
 #[derive(SerializeWithDiscriminant, DeserializeWithDiscriminant, Copy, Clone, Debug, PartialEq)]
 #[repr(u32)]
 pub enum Channel {
@@ -1512,6 +1923,7 @@ pub enum FileInformationClass {
     FileCompressionInformation = 28,
     FileDirectoryInformation = 1,
     FileDispositionInformation = 13,
+    FileDispositionInformationEx = 64,
     FileEaInformation = 7,
     FileEndOfFileInformation = 20,
     FileFullDirectoryInformation = 2,
@@ -1539,6 +1951,7 @@ pub enum FileInformationClass {
     FilePositionInformation = 14,
     FileQuotaInformation = 32,
     FileRenameInformation = 10,
+    FileRenameInformationEx = 65,
     FileReparsePointInformation = 33,
     FileSfioReserveInformation = 44,
     FileSfioVolumeInformation = 45,
@@ -1563,9 +1976,10 @@ impl_serde_for_bitflags!(QueryInfoFlags);
 
 #[derive(SerializeSmbStruct, DeserializeSmbStruct, Clone, Debug, PartialEq)]
 #[smb(size = 41)]
-pub struct QueryInfoRequest {
+pub struct QueryInfoRequest<Class> {
+    /// Must be `Class::info_type()`
     pub info_type: InfoType,
-    pub file_info_class: FileInformationClass,
+    pub file_info_class: Class,
     pub output_buffer_length: u32,
     pub additional_information: u32,
     pub flags: QueryInfoFlags,
@@ -1582,7 +1996,11 @@ pub struct QueryInfoRequest {
     pub buffer: Vec<u8>,
 }
 
-impl HasCommand for QueryInfoRequest {
+/// A `QueryInfoRequest` for file information, what `QueryInfoRequest` was before it took the
+/// information class as a parameter
+pub type FileQueryInfoRequest = QueryInfoRequest<FileInformationClass>;
+
+impl<Class> HasCommand for QueryInfoRequest<Class> {
     fn command() -> Command {
         Command::QueryInfo
     }
@@ -1592,6 +2010,32 @@ pub trait HasFileInformationClass {
     fn file_information_class() -> FileInformationClass;
 }
 
+/// The information classes of one `InfoType`, for the `file_info_class` of `QueryInfoRequest`
+/// and `SetInfoRequest`
+pub trait InfoClass {
+    fn info_type() -> InfoType;
+}
+
+impl InfoClass for FileInformationClass {
+    fn info_type() -> InfoType {
+        InfoType::File
+    }
+}
+
+/// Security information has no classes, which parts of the descriptor to get or set are in the
+/// additional information instead
+#[derive(SerializeWithDiscriminant, DeserializeWithDiscriminant, Copy, Clone, Debug, PartialEq)]
+#[repr(u8)]
+pub enum SecurityInformationClass {
+    None = 0,
+}
+
+impl InfoClass for SecurityInformationClass {
+    fn info_type() -> InfoType {
+        InfoType::Security
+    }
+}
+
 #[derive(SerializeSmbStruct, DeserializeSmbStruct, Clone, Debug, PartialEq)]
 pub struct FileAllocationInformation {
     pub allocation_size: u64,
@@ -1641,6 +2085,97 @@ impl HasFileInformationClass for FileEaInformation {
     }
 }
 
+bitflags! {
+    #[derive(PartialEq, Eq, Copy, Clone, Debug)]
+    pub struct EaFlags: u8 {
+        /// The file can't be understood without this EA
+        const NEED_EA = 0x80;
+    }
+}
+
+impl_serde_for_bitflags!(EaFlags);
+
+/// One extended attribute. A list of them is chained together with next entry offsets, ending at
+/// the entry whose next entry offset is zero.
+#[derive(SerializeSmbStruct, DeserializeSmbStruct, Clone, Debug, PartialEq)]
+#[smb(next_entry_offset = "align_to(9 + self.ea_name.len() + self.ea_value.len(), 4)")]
+pub struct FileFullEaInformation {
+    pub flags: EaFlags,
+    /// The name is ASCII, and compared case-insensitively by the server
+    #[smb(
+        collection(count(int_type = "u8", after = "flags")),
+        insert_reserved(name = "terminator", int_type = "u8", after = true)
+    )]
+    pub ea_name: Vec<u8>,
+    #[smb(collection(count(int_type = "u16", after = "ea_name_count")))]
+    pub ea_value: Vec<u8>,
+}
+
+impl FileFullEaInformation {
+    pub fn new(name: &str, value: impl Into<Vec<u8>>) -> Self {
+        Self {
+            flags: EaFlags::empty(),
+            ea_name: name.into(),
+            ea_value: value.into(),
+        }
+    }
+
+    pub fn name(&self) -> String {
+        String::from_utf8_lossy(&self.ea_name).into()
+    }
+}
+
+/// The information is the whole chain
+impl HasFileInformationClass for Vec<FileFullEaInformation> {
+    fn file_information_class() -> FileInformationClass {
+        FileInformationClass::FileFullEaInformation
+    }
+}
+
+/// Names an extended attribute to query. Like [`FileFullEaInformation`] these are chained.
+#[derive(SerializeSmbStruct, DeserializeSmbStruct, Clone, Debug, PartialEq)]
+#[smb(next_entry_offset = "align_to(6 + self.ea_name.len(), 4)")]
+pub struct FileGetEaInformation {
+    #[smb(
+        collection(count(int_type = "u8")),
+        insert_reserved(name = "terminator", int_type = "u8", after = true)
+    )]
+    pub ea_name: Vec<u8>,
+}
+
+/// One data stream of a file. A file's streams are chained together with next entry offsets.
+#[derive(SerializeSmbStruct, DeserializeSmbStruct, Clone, Debug, PartialEq)]
+#[smb(next_entry_offset = "align_to(24 + self.stream_name.encode_utf16().count() * 2, 8)")]
+pub struct FileStreamInformation {
+    pub stream_size: i64,
+    pub stream_allocation_size: i64,
+    /// Like `:Zone.Identifier:$DATA`, or `::$DATA` for the default stream
+    #[smb(collection(count(int_type = "u32", after = "next_entry_offset", element_size = 2)))]
+    pub stream_name: String,
+}
+
+impl FileStreamInformation {
+    /// The name of the stream without its type, empty for the default stream.
+    pub fn name(&self) -> &str {
+        let name = self
+            .stream_name
+            .strip_prefix(':')
+            .unwrap_or(&self.stream_name);
+        name.rsplit_once(':').map_or(name, |(name, _)| name)
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.name().is_empty()
+    }
+}
+
+/// The information is the whole chain
+impl HasFileInformationClass for Vec<FileStreamInformation> {
+    fn file_information_class() -> FileInformationClass {
+        FileInformationClass::FileStreamInformation
+    }
+}
+
 #[derive(SerializeSmbStruct, DeserializeSmbStruct, Clone, Debug, PartialEq)]
 pub struct FileAccessInformation {
     pub access_flags: AccessMask,
@@ -1745,6 +2280,25 @@ impl HasFileInformationClass for FileNameInformation {
     }
 }
 
+/// The times, sizes and attributes of a file, what most callers want from `FileAllInformation`.
+#[derive(SerializeSmbStruct, DeserializeSmbStruct, Clone, Debug, PartialEq)]
+pub struct FileNetworkOpenInformation {
+    pub creation_time: Time,
+    pub last_access_time: Time,
+    pub last_write_time: Time,
+    pub change_time: Time,
+    pub allocation_size: i64,
+    pub end_of_file: i64,
+    #[smb(insert_reserved(name = "reserved", int_type = "u32", after = true))]
+    pub file_attributes: FileAttributes,
+}
+
+impl HasFileInformationClass for FileNetworkOpenInformation {
+    fn file_information_class() -> FileInformationClass {
+        FileInformationClass::FileNetworkOpenInformation
+    }
+}
+
 #[derive(SerializeSmbStruct, DeserializeSmbStruct, Clone, Debug, PartialEq)]
 pub struct FileStandardInformation {
     pub allocation_size: u64,
@@ -1825,6 +2379,21 @@ impl HasCommand for FlushRequest {
 #[smb(size = 4, insert_reserved(name = "reserved", int_type = "u16"))]
 pub struct FlushResponse;
 
+/// Asks the server to give up on the request with the message id, or async id, in the header.
+/// It has no response, the request cancelled fails with `Cancelled` instead.
+#[derive(SerializeSmbStruct, DeserializeSmbStruct, Clone, Debug, PartialEq)]
+#[smb(
+    size = 4,
+    insert_reserved(name = "reserved", int_type = "u16", after = true)
+)]
+pub struct CancelRequest;
+
+impl HasCommand for CancelRequest {
+    fn command() -> Command {
+        Command::Cancel
+    }
+}
+
 #[derive(SerializeSmbStruct, DeserializeSmbStruct, Clone, Debug, PartialEq)]
 pub struct FileRenameInformation {
     #[smb(insert_reserved(name = "root_directory", int_type = "u64", after = true))]
@@ -1839,11 +2408,167 @@ impl HasFileInformationClass for FileRenameInformation {
     }
 }
 
+bitflags! {
+    #[derive(PartialEq, Eq, Copy, Clone, Debug)]
+    pub struct RenameFlags: u32 {
+        const REPLACE_IF_EXISTS = 0x00000001;
+        /// Replacing a file that is open elsewhere unlinks it right away, its opens keep working
+        const POSIX_SEMANTICS = 0x00000002;
+        const SUPPRESS_PIN_STATE_INHERITANCE = 0x00000004;
+        const SUPPRESS_STORAGE_RESERVE_INHERITANCE = 0x00000008;
+        const NO_INCREASE_AVAILABLE_SPACE = 0x00000010;
+        const NO_DECREASE_AVAILABLE_SPACE = 0x00000020;
+        const IGNORE_READONLY_ATTRIBUTE = 0x00000040;
+    }
+}
+
+impl_serde_for_bitflags!(RenameFlags);
+
+#[derive(SerializeSmbStruct, DeserializeSmbStruct, Clone, Debug, PartialEq)]
+pub struct FileRenameInformationEx {
+    #[smb(insert_reserved(name = "root_directory", int_type = "u64", after = true))]
+    pub flags: RenameFlags,
+    #[smb(collection(count(int_type = "u32", after = "root_directory", element_size = 2)))]
+    pub path: String,
+}
+
+impl HasFileInformationClass for FileRenameInformationEx {
+    fn file_information_class() -> FileInformationClass {
+        FileInformationClass::FileRenameInformationEx
+    }
+}
+
+/// Marks a file to be deleted once its last open is closed, or clears that mark.
+#[derive(SerializeSmbStruct, DeserializeSmbStruct, Clone, Debug, PartialEq)]
+pub struct FileDispositionInformation {
+    pub delete_pending: bool,
+}
+
+impl HasFileInformationClass for FileDispositionInformation {
+    fn file_information_class() -> FileInformationClass {
+        FileInformationClass::FileDispositionInformation
+    }
+}
+
+bitflags! {
+    #[derive(PartialEq, Eq, Copy, Clone, Debug)]
+    pub struct DispositionFlags: u32 {
+        const DELETE = 0x00000001;
+        /// The name goes away as soon as the handle is closed, even while the file is open
+        /// elsewhere
+        const POSIX_SEMANTICS = 0x00000002;
+        const FORCE_IMAGE_SECTION_CHECK = 0x00000004;
+        /// Set or clear delete on close rather than delete pending
+        const ON_CLOSE = 0x00000008;
+        const IGNORE_READONLY_ATTRIBUTE = 0x00000010;
+    }
+}
+
+impl_serde_for_bitflags!(DispositionFlags);
+
+#[derive(SerializeSmbStruct, DeserializeSmbStruct, Clone, Debug, PartialEq)]
+pub struct FileDispositionInformationEx {
+    pub flags: DispositionFlags,
+}
+
+impl HasFileInformationClass for FileDispositionInformationEx {
+    fn file_information_class() -> FileInformationClass {
+        FileInformationClass::FileDispositionInformationEx
+    }
+}
+
+/// Gives a file another name, the new name is another hard link to the same data.
+#[derive(SerializeSmbStruct, DeserializeSmbStruct, Clone, Debug, PartialEq)]
+pub struct FileLinkInformation {
+    #[smb(insert_reserved(name = "root_directory", int_type = "u64", after = true))]
+    pub replace_if_exists: bool,
+    #[smb(collection(count(int_type = "u32", after = "root_directory", element_size = 2)))]
+    pub path: String,
+}
+
+impl HasFileInformationClass for FileLinkInformation {
+    fn file_information_class() -> FileInformationClass {
+        FileInformationClass::FileLinkInformation
+    }
+}
+
+/// One name of a file, given as a name within a parent directory.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileLinkEntryInformation {
+    /// The file id of the directory holding this link, as in `FileInternalInformation`
+    pub parent_file_id: u64,
+    pub file_name: String,
+}
+
+/// Every hard link of a file. Its names are counted in characters rather than bytes, so it has
+/// its own encoding.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileLinksInformation {
+    /// How big the buffer has to be to hold all of the entries
+    pub bytes_needed: u32,
+    pub entries: Vec<FileLinkEntryInformation>,
+}
+
+impl FileLinksInformation {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = self.bytes_needed.to_le_bytes().to_vec();
+        out.extend((self.entries.len() as u32).to_le_bytes());
+        for (i, entry) in self.entries.iter().enumerate() {
+            let name = encode_utf16(&entry.file_name);
+            let next_entry_offset = if i + 1 < self.entries.len() {
+                align_to(20 + name.len(), 8)
+            } else {
+                0
+            };
+            let start = out.len();
+            out.extend((next_entry_offset as u32).to_le_bytes());
+            out.extend([0; 4]);
+            out.extend(entry.parent_file_id.to_le_bytes());
+            out.extend((name.len() as u32 / 2).to_le_bytes());
+            out.extend(name);
+            if next_entry_offset != 0 {
+                out.resize(start + next_entry_offset, 0);
+            }
+        }
+        out
+    }
+
+    pub fn from_bytes(data: &[u8]) -> serde_smb::Result<Self> {
+        let bytes_needed = read_u32_at(data, 0)?;
+        let entries_returned = read_u32_at(data, 4)?;
+
+        let mut entries = vec![];
+        let mut offset = 8;
+        for i in 0..entries_returned {
+            let next_entry_offset = read_u32_at(data, offset)? as usize;
+            let parent_file_id = read_u64_at(data, offset + 8)?;
+            let name_length = read_u32_at(data, offset + 16)? as usize;
+            entries.push(FileLinkEntryInformation {
+                parent_file_id,
+                file_name: read_utf16_at(data, offset + 20, name_length * 2)?,
+            });
+            if next_entry_offset == 0 {
+                if i + 1 < entries_returned {
+                    return Err(malformed("link information: chain ends early"));
+                }
+                break;
+            }
+            offset += next_entry_offset;
+        }
+
+        Ok(Self {
+            bytes_needed,
+            entries,
+        })
+    }
+}
+
 #[derive(SerializeSmbStruct, DeserializeSmbStruct, Clone, Debug, PartialEq)]
 #[smb(size = 33)]
-pub struct SetInfoRequest<Info> {
+pub struct SetInfoRequest<Class, Info> {
+    /// Must be `Class::info_type()`
     pub info_type: InfoType,
-    pub file_info_class: FileInformationClass,
+    pub file_info_class: Class,
     pub additional_information: u32,
     pub file_id: FileId,
     #[smb(collection(
@@ -1857,7 +2582,11 @@ pub struct SetInfoRequest<Info> {
     pub info: Info,
 }
 
-impl<Info> HasCommand for SetInfoRequest<Info> {
+/// A `SetInfoRequest` for file information, what `SetInfoRequest<Info>` was before it took the
+/// information class as a parameter
+pub type FileSetInfoRequest<Info> = SetInfoRequest<FileInformationClass, Info>;
+
+impl<Class, Info> HasCommand for SetInfoRequest<Class, Info> {
     fn command() -> Command {
         Command::SetInfo
     }
@@ -1877,3 +2606,1849 @@ impl HasFileInformationClass for FileEndOfFileInformation {
         FileInformationClass::FileEndOfFileInformation
     }
 }
+
+#[derive(SerializeWithDiscriminant, DeserializeWithDiscriminant, Copy, Clone, Debug, PartialEq)]
+#[repr(u32)]
+pub enum CtlCode {
+    FsctlDfsGetReferrals = 0x00060194,
+    FsctlPipePeek = 0x0011400C,
+    FsctlPipeWait = 0x00110018,
+    FsctlPipeTransceive = 0x0011C017,
+    FsctlSrvCopychunk = 0x001440F2,
+    FsctlSrvEnumerateSnapshots = 0x00144064,
+    FsctlSrvRequestResumeKey = 0x00140078,
+    FsctlSrvReadHash = 0x001441bb,
+    FsctlSrvCopychunkWrite = 0x001480F2,
+    FsctlLmrRequestResiliency = 0x001401D4,
+    FsctlQueryNetworkInterfaceInfo = 0x001401FC,
+    FsctlSetReparsePoint = 0x000900A4,
+    FsctlGetReparsePoint = 0x000900A8,
+    FsctlDfsGetReferralsEx = 0x000601B0,
+    FsctlFileLevelTrim = 0x00098208,
+    FsctlValidateNegotiateInfo = 0x00140204,
+}
+
+bitflags! {
+    #[derive(PartialEq, Eq, Copy, Clone, Debug)]
+    pub struct IoctlFlags: u32 {
+        const IS_FSCTL = 0x00000001;
+    }
+}
+
+impl_serde_for_bitflags!(IoctlFlags);
+
+impl FileId {
+    /// Used for requests which aren't tied to an open file, like FSCTL_PIPE_WAIT
+    pub const UNSPECIFIED: Self = Self {
+        persistent: u64::MAX,
+        volatile: u64::MAX,
+    };
+
+    /// In a related compound request, the file opened by the request before
+    pub const LAST: Self = Self {
+        persistent: u64::MAX,
+        volatile: u64::MAX,
+    };
+}
+
+#[derive(SerializeSmbStruct, DeserializeSmbStruct, Clone, Debug, PartialEq)]
+#[smb(size = 57)]
+pub struct IoctlRequest {
+    #[smb(insert_reserved(name = "reserved", int_type = "u16"))]
+    pub ctl_code: CtlCode,
+    pub file_id: FileId,
+    pub max_input_response: u32,
+    #[smb(insert_reserved(name = "output_offset_and_count", int_type = "(u32, u32)"))]
+    pub max_output_response: u32,
+    #[smb(insert_reserved(name = "reserved2", int_type = "u32", after = true))]
+    pub flags: IoctlFlags,
+    #[smb(collection(
+        count(int_type = "u32", after = "file_id"),
+        offset(int_type = "u32", after = "file_id", value = "HEADER_SIZE + 56")
+    ))]
+    pub input: Vec<u8>,
+}
+
+impl HasCommand for IoctlRequest {
+    fn command() -> Command {
+        Command::Ioctl
+    }
+}
+
+#[derive(SerializeSmbStruct, DeserializeSmbStruct, Clone, Debug, PartialEq)]
+#[smb(size = 49)]
+pub struct IoctlResponse {
+    #[smb(insert_reserved(name = "reserved", int_type = "u16"))]
+    pub ctl_code: CtlCode,
+    pub file_id: FileId,
+    #[smb(insert_reserved(name = "reserved2", int_type = "u32", after = true))]
+    pub flags: IoctlFlags,
+    #[smb(collection(
+        count(int_type = "u32", after = "file_id"),
+        offset(int_type = "u32", after = "file_id", value = "HEADER_SIZE + 48")
+    ))]
+    pub input: Vec<u8>,
+    #[smb(collection(
+        count(int_type = "u32", after = "input_count"),
+        offset(
+            int_type = "u32",
+            after = "input_count",
+            value = "HEADER_SIZE + 48 + align_to(self.input.len(), 8)"
+        )
+    ))]
+    pub output: Vec<u8>,
+}
+
+#[derive(SerializeSmbStruct, DeserializeSmbStruct, Clone, Debug, PartialEq)]
+pub struct FsctlPipeWaitRequest {
+    /// How long to wait in 100-nanosecond intervals, only used if `timeout_specified` is set
+    pub timeout: i64,
+    #[smb(insert_reserved(name = "padding", int_type = "u8", after = true))]
+    pub timeout_specified: bool,
+    #[smb(collection(count(int_type = "u32", after = "timeout", element_size = 2)))]
+    pub name: String,
+}
+
+#[derive(SerializeWithDiscriminant, DeserializeWithDiscriminant, Copy, Clone, Debug, PartialEq)]
+#[repr(u32)]
+pub enum PipeReadMode {
+    ByteStream = 0x00000000,
+    Message = 0x00000001,
+}
+
+#[derive(SerializeWithDiscriminant, DeserializeWithDiscriminant, Copy, Clone, Debug, PartialEq)]
+#[repr(u32)]
+pub enum PipeCompletionMode {
+    Queue = 0x00000000,
+    Complete = 0x00000001,
+}
+
+#[derive(SerializeSmbStruct, DeserializeSmbStruct, Clone, Debug, PartialEq)]
+pub struct FilePipeInformation {
+    pub read_mode: PipeReadMode,
+    pub completion_mode: PipeCompletionMode,
+}
+
+impl HasFileInformationClass for FilePipeInformation {
+    fn file_information_class() -> FileInformationClass {
+        FileInformationClass::FilePipeInformation
+    }
+}
+
+#[derive(SerializeWithDiscriminant, DeserializeWithDiscriminant, Copy, Clone, Debug, PartialEq)]
+#[repr(u8)]
+pub enum FsInformationClass {
+    FileFsVolumeInformation = 1,
+    FileFsLabelInformation = 2,
+    FileFsSizeInformation = 3,
+    FileFsDeviceInformation = 4,
+    FileFsAttributeInformation = 5,
+    FileFsControlInformation = 6,
+    FileFsFullSizeInformation = 7,
+    FileFsObjectIdInformation = 8,
+    FileFsDriverPathInformation = 9,
+    FileFsVolumeFlagsInformation = 10,
+    FileFsSectorSizeInformation = 11,
+}
+
+pub trait HasFsInformationClass {
+    fn fs_information_class() -> FsInformationClass;
+}
+
+impl InfoClass for FsInformationClass {
+    fn info_type() -> InfoType {
+        InfoType::Filesystem
+    }
+}
+
+#[derive(SerializeSmbStruct, DeserializeSmbStruct, Clone, Debug, PartialEq)]
+pub struct FileFsVolumeInformation {
+    pub volume_creation_time: Time,
+    pub volume_serial_number: u32,
+    #[smb(insert_reserved(name = "reserved", int_type = "u8", after = true))]
+    pub supports_objects: bool,
+    #[smb(collection(count(int_type = "u32", after = "volume_serial_number", element_size = 2)))]
+    pub volume_label: String,
+}
+
+impl HasFsInformationClass for FileFsVolumeInformation {
+    fn fs_information_class() -> FsInformationClass {
+        FsInformationClass::FileFsVolumeInformation
+    }
+}
+
+#[derive(SerializeSmbStruct, DeserializeSmbStruct, Clone, Debug, PartialEq)]
+pub struct FileFsSizeInformation {
+    pub total_allocation_units: i64,
+    pub available_allocation_units: i64,
+    pub sectors_per_allocation_unit: u32,
+    pub bytes_per_sector: u32,
+}
+
+impl FileFsSizeInformation {
+    pub fn bytes_per_allocation_unit(&self) -> u64 {
+        u64::from(self.sectors_per_allocation_unit) * u64::from(self.bytes_per_sector)
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.total_allocation_units as u64 * self.bytes_per_allocation_unit()
+    }
+
+    pub fn available_bytes(&self) -> u64 {
+        self.available_allocation_units as u64 * self.bytes_per_allocation_unit()
+    }
+}
+
+impl HasFsInformationClass for FileFsSizeInformation {
+    fn fs_information_class() -> FsInformationClass {
+        FsInformationClass::FileFsSizeInformation
+    }
+}
+
+#[derive(SerializeSmbStruct, DeserializeSmbStruct, Clone, Debug, PartialEq)]
+pub struct FileFsFullSizeInformation {
+    pub total_allocation_units: i64,
+    /// The free units available to the user, which can be fewer because of quotas
+    pub caller_available_allocation_units: i64,
+    pub actual_available_allocation_units: i64,
+    pub sectors_per_allocation_unit: u32,
+    pub bytes_per_sector: u32,
+}
+
+impl FileFsFullSizeInformation {
+    pub fn bytes_per_allocation_unit(&self) -> u64 {
+        u64::from(self.sectors_per_allocation_unit) * u64::from(self.bytes_per_sector)
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.total_allocation_units as u64 * self.bytes_per_allocation_unit()
+    }
+
+    pub fn caller_available_bytes(&self) -> u64 {
+        self.caller_available_allocation_units as u64 * self.bytes_per_allocation_unit()
+    }
+
+    pub fn actual_available_bytes(&self) -> u64 {
+        self.actual_available_allocation_units as u64 * self.bytes_per_allocation_unit()
+    }
+}
+
+impl HasFsInformationClass for FileFsFullSizeInformation {
+    fn fs_information_class() -> FsInformationClass {
+        FsInformationClass::FileFsFullSizeInformation
+    }
+}
+
+#[derive(SerializeWithDiscriminant, DeserializeWithDiscriminant, Copy, Clone, Debug, PartialEq)]
+#[repr(u32)]
+pub enum DeviceType {
+    CdRom = 0x00000002,
+    Disk = 0x00000007,
+}
+
+bitflags! {
+    #[derive(PartialEq, Eq, Copy, Clone, Debug)]
+    pub struct DeviceCharacteristics: u32 {
+        const REMOVABLE_MEDIA                     = 0x00000001;
+        const READ_ONLY_DEVICE                    = 0x00000002;
+        const FLOPPY_DISKETTE                     = 0x00000004;
+        const WRITE_ONCE_MEDIA                    = 0x00000008;
+        const REMOTE_DEVICE                       = 0x00000010;
+        const DEVICE_IS_MOUNTED                   = 0x00000020;
+        const VIRTUAL_VOLUME                      = 0x00000040;
+        const DEVICE_SECURE_OPEN                  = 0x00000100;
+        const CHARACTERISTIC_TS_DEVICE            = 0x00001000;
+        const CHARACTERISTIC_WEBDAV_DEVICE        = 0x00002000;
+        const CHARACTERISTIC_CSV                  = 0x00010000;
+        const DEVICE_ALLOW_APPCONTAINER_TRAVERSAL = 0x00020000;
+        const PORTABLE_DEVICE                     = 0x00040000;
+    }
+}
+
+impl_serde_for_bitflags_retain!(DeviceCharacteristics);
+
+#[derive(SerializeSmbStruct, DeserializeSmbStruct, Clone, Debug, PartialEq)]
+pub struct FileFsDeviceInformation {
+    pub device_type: DeviceType,
+    pub characteristics: DeviceCharacteristics,
+}
+
+impl HasFsInformationClass for FileFsDeviceInformation {
+    fn fs_information_class() -> FsInformationClass {
+        FsInformationClass::FileFsDeviceInformation
+    }
+}
+
+bitflags! {
+    #[derive(PartialEq, Eq, Copy, Clone, Debug)]
+    pub struct FileSystemAttributes: u32 {
+        const CASE_SENSITIVE_SEARCH        = 0x00000001;
+        const CASE_PRESERVED_NAMES         = 0x00000002;
+        const UNICODE_ON_DISK              = 0x00000004;
+        const PERSISTENT_ACLS              = 0x00000008;
+        const FILE_COMPRESSION             = 0x00000010;
+        const VOLUME_QUOTAS                = 0x00000020;
+        const SUPPORTS_SPARSE_FILES        = 0x00000040;
+        const SUPPORTS_REPARSE_POINTS      = 0x00000080;
+        const SUPPORTS_REMOTE_STORAGE      = 0x00000100;
+        const RETURNS_CLEANUP_RESULT_INFO  = 0x00000200;
+        const SUPPORTS_POSIX_UNLINK_RENAME = 0x00000400;
+        const SUPPORTS_BYPASS_IO           = 0x00000800;
+        const SUPPORTS_STREAM_SNAPSHOTS    = 0x00001000;
+        const SUPPORTS_CASE_SENSITIVE_DIRS = 0x00002000;
+        const VOLUME_IS_COMPRESSED         = 0x00008000;
+        const SUPPORTS_OBJECT_IDS          = 0x00010000;
+        const SUPPORTS_ENCRYPTION          = 0x00020000;
+        const NAMED_STREAMS                = 0x00040000;
+        const READ_ONLY_VOLUME             = 0x00080000;
+        const SEQUENTIAL_WRITE_ONCE        = 0x00100000;
+        const SUPPORTS_TRANSACTIONS        = 0x00200000;
+        const SUPPORTS_HARD_LINKS          = 0x00400000;
+        const SUPPORTS_EXTENDED_ATTRIBUTES = 0x00800000;
+        const SUPPORTS_OPEN_BY_FILE_ID     = 0x01000000;
+        const SUPPORTS_USN_JOURNAL         = 0x02000000;
+        const SUPPORTS_INTEGRITY_STREAMS   = 0x04000000;
+        const SUPPORTS_BLOCK_REFCOUNTING   = 0x08000000;
+        const SUPPORTS_SPARSE_VDL          = 0x10000000;
+        const DAX_VOLUME                   = 0x20000000;
+        const SUPPORTS_GHOSTING            = 0x40000000;
+    }
+}
+
+impl_serde_for_bitflags_retain!(FileSystemAttributes);
+
+#[derive(SerializeSmbStruct, DeserializeSmbStruct, Clone, Debug, PartialEq)]
+pub struct FileFsAttributeInformation {
+    pub file_system_attributes: FileSystemAttributes,
+    /// The longest file name component the file system allows, in characters
+    pub maximum_component_name_length: u32,
+    #[smb(collection(count(int_type = "u32", element_size = 2)))]
+    pub file_system_name: String,
+}
+
+impl HasFsInformationClass for FileFsAttributeInformation {
+    fn fs_information_class() -> FsInformationClass {
+        FsInformationClass::FileFsAttributeInformation
+    }
+}
+
+bitflags! {
+    #[derive(PartialEq, Eq, Copy, Clone, Debug)]
+    pub struct SectorSizeInformationFlags: u32 {
+        const ALIGNMENT_DETECTED          = 0x00000001;
+        const PARTITION_ALIGNED_ON_DEVICE = 0x00000002;
+        const NO_SEEK_PENALTY             = 0x00000004;
+        const TRIM_ENABLED                = 0x00000008;
+    }
+}
+
+impl_serde_for_bitflags_retain!(SectorSizeInformationFlags);
+
+#[derive(SerializeSmbStruct, DeserializeSmbStruct, Clone, Debug, PartialEq)]
+pub struct FileFsSectorSizeInformation {
+    pub logical_bytes_per_sector: u32,
+    pub physical_bytes_per_sector_for_atomicity: u32,
+    pub physical_bytes_per_sector_for_performance: u32,
+    pub file_system_effective_physical_bytes_per_sector_for_atomicity: u32,
+    pub flags: SectorSizeInformationFlags,
+    pub byte_offset_for_sector_alignment: u32,
+    pub byte_offset_for_partition_alignment: u32,
+}
+
+impl HasFsInformationClass for FileFsSectorSizeInformation {
+    fn fs_information_class() -> FsInformationClass {
+        FsInformationClass::FileFsSectorSizeInformation
+    }
+}
+
+/// The highest referral version this crate can parse
+pub const DFS_MAX_REFERRAL_LEVEL: u16 = 4;
+
+fn malformed(what: &str) -> serde_smb::Error {
+    serde_smb::Error::Custom(format!("malformed {what}"))
+}
+
+fn encode_null_terminated(out: &mut Vec<u8>, s: &str) {
+    for c in s.encode_utf16().chain([0]) {
+        out.extend(c.to_le_bytes());
+    }
+}
+
+fn read_u16_at(data: &[u8], offset: usize) -> serde_smb::Result<u16> {
+    let bytes = data
+        .get(offset..offset + 2)
+        .ok_or_else(|| malformed("data: unexpected end"))?;
+    Ok(u16::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn read_u32_at(data: &[u8], offset: usize) -> serde_smb::Result<u32> {
+    let bytes = data
+        .get(offset..offset + 4)
+        .ok_or_else(|| malformed("data: unexpected end"))?;
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn read_u64_at(data: &[u8], offset: usize) -> serde_smb::Result<u64> {
+    let bytes = data
+        .get(offset..offset + 8)
+        .ok_or_else(|| malformed("data: unexpected end"))?;
+    Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn read_null_terminated_at(data: &[u8], offset: usize) -> serde_smb::Result<String> {
+    let mut chars = vec![];
+    let mut position = offset;
+    loop {
+        let c = read_u16_at(data, position)?;
+        if c == 0 {
+            break;
+        }
+        chars.push(c);
+        position += 2;
+    }
+    Ok(char::decode_utf16(chars)
+        .map(|r| r.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect())
+}
+
+/// REQ_GET_DFS_REFERRAL, the input of FSCTL_DFS_GET_REFERRALS
+///
+/// DFS structures use null-terminated strings and offsets relative to each entry, so unlike
+/// most of the types here they are encoded by hand instead of with serde_smb.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DfsGetReferralsRequest {
+    pub max_referral_level: u16,
+    /// The path to resolve, like `\server\share\link`
+    pub request_file_name: String,
+}
+
+impl DfsGetReferralsRequest {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = self.max_referral_level.to_le_bytes().to_vec();
+        encode_null_terminated(&mut out, &self.request_file_name);
+        out
+    }
+}
+
+bitflags! {
+    #[derive(PartialEq, Eq, Copy, Clone, Debug)]
+    pub struct DfsGetReferralsExFlags: u16 {
+        const SITE_NAME = 0x0001;
+    }
+}
+
+/// REQ_GET_DFS_REFERRAL_EX, the input of FSCTL_DFS_GET_REFERRALS_EX
+#[derive(Clone, Debug, PartialEq)]
+pub struct DfsGetReferralsExRequest {
+    pub max_referral_level: u16,
+    pub request_file_name: String,
+    /// Asks for referrals as if the client was in this site
+    pub site_name: Option<String>,
+}
+
+impl DfsGetReferralsExRequest {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut request_data = vec![];
+        let mut file_name = vec![];
+        encode_null_terminated(&mut file_name, &self.request_file_name);
+        request_data.extend((file_name.len() as u16).to_le_bytes());
+        request_data.extend(file_name);
+        let mut flags = DfsGetReferralsExFlags::empty();
+        if let Some(site_name) = &self.site_name {
+            flags |= DfsGetReferralsExFlags::SITE_NAME;
+            let mut site = vec![];
+            encode_null_terminated(&mut site, site_name);
+            request_data.extend((site.len() as u16).to_le_bytes());
+            request_data.extend(site);
+        }
+
+        let mut out = self.max_referral_level.to_le_bytes().to_vec();
+        out.extend(flags.bits().to_le_bytes());
+        out.extend((request_data.len() as u32).to_le_bytes());
+        out.extend(request_data);
+        out
+    }
+}
+
+bitflags! {
+    #[derive(PartialEq, Eq, Copy, Clone, Debug)]
+    pub struct DfsReferralHeaderFlags: u32 {
+        const REFERRAL_SERVERS = 0x00000001;
+        const STORAGE_SERVERS  = 0x00000002;
+        const TARGET_FAILBACK  = 0x00000004;
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[repr(u16)]
+pub enum DfsServerType {
+    NonRoot = 0x0000,
+    Root = 0x0001,
+}
+
+bitflags! {
+    #[derive(PartialEq, Eq, Copy, Clone, Debug)]
+    pub struct DfsReferralEntryFlags: u16 {
+        const NAME_LIST_REFERRAL  = 0x0002;
+        const TARGET_SET_BOUNDARY = 0x0004;
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DfsReferralTarget {
+    /// The DFS path `dfs_path` is served at `network_address`, like `\server\share\path`
+    Path {
+        dfs_path: String,
+        dfs_alternate_path: String,
+        network_address: String,
+    },
+    /// A domain or domain controller referral
+    NameList {
+        special_name: String,
+        expanded_names: Vec<String>,
+    },
+}
+
+/// One DFS_REFERRAL_V1 to DFS_REFERRAL_V4 entry
+#[derive(Clone, Debug, PartialEq)]
+pub struct DfsReferralEntry {
+    pub version: u16,
+    pub server_type: DfsServerType,
+    pub flags: DfsReferralEntryFlags,
+    /// How long the entry may be cached in seconds, always zero for version 1
+    pub time_to_live: u32,
+    pub target: DfsReferralTarget,
+}
+
+impl DfsReferralEntry {
+    fn from_bytes(entry: &[u8]) -> serde_smb::Result<Self> {
+        let version = read_u16_at(entry, 0)?;
+        let server_type = match read_u16_at(entry, 4)? {
+            0 => DfsServerType::NonRoot,
+            1 => DfsServerType::Root,
+            _ => return Err(malformed("DFS referral: unknown server type")),
+        };
+        let flags = DfsReferralEntryFlags::from_bits_retain(read_u16_at(entry, 6)?);
+        let string_at = |offset_position| -> serde_smb::Result<String> {
+            read_null_terminated_at(entry, read_u16_at(entry, offset_position)?.into())
+        };
+
+        let (time_to_live, target) = match version {
+            1 => {
+                let share_name = read_null_terminated_at(entry, 8)?;
+                let target = DfsReferralTarget::Path {
+                    dfs_path: String::new(),
+                    dfs_alternate_path: String::new(),
+                    network_address: share_name,
+                };
+                (0, target)
+            }
+            2 => {
+                let target = DfsReferralTarget::Path {
+                    dfs_path: string_at(16)?,
+                    dfs_alternate_path: string_at(18)?,
+                    network_address: string_at(20)?,
+                };
+                (read_u32_at(entry, 12)?, target)
+            }
+            3 | 4 if flags.contains(DfsReferralEntryFlags::NAME_LIST_REFERRAL) => {
+                let special_name = string_at(12)?;
+                let count = read_u16_at(entry, 14)?;
+                let mut offset = usize::from(read_u16_at(entry, 16)?);
+                let mut expanded_names = vec![];
+                for _ in 0..count {
+                    let name = read_null_terminated_at(entry, offset)?;
+                    offset += (name.encode_utf16().count() + 1) * 2;
+                    expanded_names.push(name);
+                }
+                let target = DfsReferralTarget::NameList {
+                    special_name,
+                    expanded_names,
+                };
+                (read_u32_at(entry, 8)?, target)
+            }
+            3 | 4 => {
+                let target = DfsReferralTarget::Path {
+                    dfs_path: string_at(12)?,
+                    dfs_alternate_path: string_at(14)?,
+                    network_address: string_at(16)?,
+                };
+                (read_u32_at(entry, 8)?, target)
+            }
+            _ => return Err(malformed("DFS referral: unknown version")),
+        };
+
+        Ok(Self {
+            version,
+            server_type,
+            flags,
+            time_to_live,
+            target,
+        })
+    }
+}
+
+/// RESP_GET_DFS_REFERRAL, the output of FSCTL_DFS_GET_REFERRALS(_EX)
+#[derive(Clone, Debug, PartialEq)]
+pub struct DfsReferralResponse {
+    /// How many bytes of the requested path the referrals cover
+    pub path_consumed: u16,
+    pub flags: DfsReferralHeaderFlags,
+    pub entries: Vec<DfsReferralEntry>,
+}
+
+impl DfsReferralResponse {
+    pub fn from_bytes(data: &[u8]) -> serde_smb::Result<Self> {
+        let path_consumed = read_u16_at(data, 0)?;
+        let number_of_referrals = read_u16_at(data, 2)?;
+        let flags = DfsReferralHeaderFlags::from_bits_retain(read_u32_at(data, 4)?);
+
+        let mut entries = vec![];
+        let mut offset = 8;
+        for _ in 0..number_of_referrals {
+            let size = usize::from(read_u16_at(data, offset + 2)?);
+            if size == 0 {
+                return Err(malformed("DFS referral: empty entry"));
+            }
+            // String offsets are relative to the entry, but may point past its end
+            entries.push(DfsReferralEntry::from_bytes(&data[offset..])?);
+            offset += size;
+        }
+
+        Ok(Self {
+            path_consumed,
+            flags,
+            entries,
+        })
+    }
+}
+
+fn read_utf16_at(data: &[u8], offset: usize, length: usize) -> serde_smb::Result<String> {
+    let bytes = data
+        .get(offset..offset + length)
+        .ok_or_else(|| malformed("data: unexpected end"))?;
+    let chars = bytes
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]));
+    Ok(char::decode_utf16(chars)
+        .map(|r| r.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect())
+}
+
+fn encode_utf16(s: &str) -> Vec<u8> {
+    s.encode_utf16().flat_map(u16::to_le_bytes).collect()
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ReparseTag(pub u32);
+
+impl ReparseTag {
+    pub const MOUNT_POINT: Self = Self(0xA0000003);
+    pub const SYMLINK: Self = Self(0xA000000C);
+    pub const NFS: Self = Self(0x80000014);
+    pub const LX_SYMLINK: Self = Self(0xA000001D);
+}
+
+bitflags! {
+    #[derive(PartialEq, Eq, Copy, Clone, Debug)]
+    pub struct SymbolicLinkFlags: u32 {
+        /// The substitute name is relative to the directory containing the link
+        const RELATIVE = 0x00000001;
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SymbolicLinkReparseData {
+    pub substitute_name: String,
+    pub print_name: String,
+    pub flags: SymbolicLinkFlags,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MountPointReparseData {
+    pub substitute_name: String,
+    pub print_name: String,
+}
+
+/// REPARSE_DATA_BUFFER, the output of FSCTL_GET_REPARSE_POINT and input of
+/// FSCTL_SET_REPARSE_POINT
+///
+/// The names are found through offsets relative to the path buffer, so this is encoded by hand.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ReparseData {
+    SymbolicLink(SymbolicLinkReparseData),
+    MountPoint(MountPointReparseData),
+    Other { tag: ReparseTag, data: Vec<u8> },
+}
+
+/// Reads the substitute and print names which follow their offsets and lengths at `offset`.
+fn read_reparse_names(
+    data: &[u8],
+    offset: usize,
+    path_buffer: usize,
+) -> serde_smb::Result<(String, String)> {
+    let substitute_offset = usize::from(read_u16_at(data, offset)?);
+    let substitute_length = usize::from(read_u16_at(data, offset + 2)?);
+    let print_offset = usize::from(read_u16_at(data, offset + 4)?);
+    let print_length = usize::from(read_u16_at(data, offset + 6)?);
+    Ok((
+        read_utf16_at(data, path_buffer + substitute_offset, substitute_length)?,
+        read_utf16_at(data, path_buffer + print_offset, print_length)?,
+    ))
+}
+
+/// Writes offsets and lengths for the two names followed by the path buffer holding them.
+fn write_reparse_names(
+    out: &mut Vec<u8>,
+    substitute_name: &str,
+    print_name: &str,
+    flags: Option<SymbolicLinkFlags>,
+    null_terminate: bool,
+) {
+    let substitute = encode_utf16(substitute_name);
+    let print = encode_utf16(print_name);
+    let terminator = if null_terminate { 2 } else { 0 };
+    let print_offset = substitute.len() + terminator;
+
+    out.extend(0u16.to_le_bytes());
+    out.extend((substitute.len() as u16).to_le_bytes());
+    out.extend((print_offset as u16).to_le_bytes());
+    out.extend((print.len() as u16).to_le_bytes());
+    if let Some(flags) = flags {
+        out.extend(flags.bits().to_le_bytes());
+    }
+    out.extend(substitute);
+    out.resize(out.len() + terminator, 0);
+    out.extend(print);
+    out.resize(out.len() + terminator, 0);
+}
+
+impl ReparseData {
+    pub fn tag(&self) -> ReparseTag {
+        match self {
+            Self::SymbolicLink(_) => ReparseTag::SYMLINK,
+            Self::MountPoint(_) => ReparseTag::MOUNT_POINT,
+            Self::Other { tag, .. } => *tag,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = vec![];
+        match self {
+            Self::SymbolicLink(link) => write_reparse_names(
+                &mut data,
+                &link.substitute_name,
+                &link.print_name,
+                Some(link.flags),
+                false,
+            ),
+            Self::MountPoint(mount) => write_reparse_names(
+                &mut data,
+                &mount.substitute_name,
+                &mount.print_name,
+                None,
+                true,
+            ),
+            Self::Other { data: other, .. } => data.extend(other),
+        }
+
+        let mut out = self.tag().0.to_le_bytes().to_vec();
+        out.extend((data.len() as u16).to_le_bytes());
+        out.extend(0u16.to_le_bytes());
+        out.extend(data);
+        out
+    }
+
+    pub fn from_bytes(data: &[u8]) -> serde_smb::Result<Self> {
+        let tag = ReparseTag(read_u32_at(data, 0)?);
+        let length = usize::from(read_u16_at(data, 4)?);
+        let data = data
+            .get(..8 + length)
+            .ok_or_else(|| malformed("reparse data: unexpected end"))?;
+        Ok(match tag {
+            ReparseTag::SYMLINK => {
+                let (substitute_name, print_name) = read_reparse_names(data, 8, 20)?;
+                Self::SymbolicLink(SymbolicLinkReparseData {
+                    substitute_name,
+                    print_name,
+                    flags: SymbolicLinkFlags::from_bits_retain(read_u32_at(data, 16)?),
+                })
+            }
+            ReparseTag::MOUNT_POINT => {
+                let (substitute_name, print_name) = read_reparse_names(data, 8, 16)?;
+                Self::MountPoint(MountPointReparseData {
+                    substitute_name,
+                    print_name,
+                })
+            }
+            tag => Self::Other {
+                tag,
+                data: data[8..].to_vec(),
+            },
+        })
+    }
+}
+
+const SYMLINK_ERROR_TAG: u32 = 0x4C4D5953;
+
+/// Symbolic Link Error Response, sent with STATUS_STOPPED_ON_SYMLINK when opening a path which
+/// goes through a symbolic link.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SymbolicLinkErrorResponse {
+    /// How many bytes at the end of the opened path come after the link
+    pub unparsed_path_length: u16,
+    pub substitute_name: String,
+    pub print_name: String,
+    pub flags: SymbolicLinkFlags,
+}
+
+impl SymbolicLinkErrorResponse {
+    pub fn from_bytes(data: &[u8]) -> serde_smb::Result<Self> {
+        if read_u32_at(data, 4)? != SYMLINK_ERROR_TAG {
+            return Err(malformed("symbolic link error response: bad tag"));
+        }
+        if ReparseTag(read_u32_at(data, 8)?) != ReparseTag::SYMLINK {
+            return Err(malformed("symbolic link error response: bad reparse tag"));
+        }
+        let (substitute_name, print_name) = read_reparse_names(data, 16, 28)?;
+        Ok(Self {
+            unparsed_path_length: read_u16_at(data, 14)?,
+            substitute_name,
+            print_name,
+            flags: SymbolicLinkFlags::from_bits_retain(read_u32_at(data, 24)?),
+        })
+    }
+}
+
+/// The body of a response whose status is an error
+#[derive(SerializeSmbStruct, DeserializeSmbStruct, Clone, Debug, PartialEq)]
+#[smb(size = 9)]
+pub struct ErrorResponse {
+    #[smb(insert_reserved(name = "reserved", int_type = "u8", after = true))]
+    pub error_context_count: u8,
+    #[smb(collection(count(int_type = "u32")))]
+    pub error_data: Vec<u8>,
+}
+
+/// The ErrorId of an error context telling the client to connect to the share elsewhere
+const SHARE_REDIRECT_ERROR_ID: u32 = 0x72645253;
+
+const MOVE_DST_IPADDR_V4: u32 = 1;
+const MOVE_DST_IPADDR_V6: u32 = 2;
+
+/// Sent with STATUS_BAD_NETWORK_NAME when the share has moved to another server
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShareRedirectErrorContext {
+    pub resource_name: String,
+    pub ip_addresses: Vec<IpAddr>,
+}
+
+impl ShareRedirectErrorContext {
+    pub fn from_bytes(data: &[u8]) -> serde_smb::Result<Self> {
+        let resource_name_offset = read_u32_at(data, 8)? as usize;
+        let resource_name_length = read_u32_at(data, 12)? as usize;
+        let ip_address_count = read_u32_at(data, 20)? as usize;
+
+        let mut ip_addresses = vec![];
+        for i in 0..ip_address_count {
+            let offset = 24 + i * 24;
+            let address = data
+                .get(offset + 8..offset + 24)
+                .ok_or_else(|| malformed("share redirect: unexpected end"))?;
+            match read_u32_at(data, offset)? {
+                MOVE_DST_IPADDR_V4 => {
+                    let octets: [u8; 4] = address[..4].try_into().unwrap();
+                    ip_addresses.push(IpAddr::from(octets));
+                }
+                MOVE_DST_IPADDR_V6 => {
+                    let octets: [u8; 16] = address.try_into().unwrap();
+                    ip_addresses.push(IpAddr::from(octets));
+                }
+                _ => return Err(malformed("share redirect: unknown address type")),
+            }
+        }
+
+        Ok(Self {
+            resource_name: read_utf16_at(data, resource_name_offset, resource_name_length)?,
+            ip_addresses,
+        })
+    }
+}
+
+/// Extra information a server sends along with an error status
+#[derive(Clone, Debug, PartialEq)]
+pub enum ErrorContext {
+    /// Sent with STATUS_STOPPED_ON_SYMLINK
+    SymbolicLink(SymbolicLinkErrorResponse),
+    /// Sent with STATUS_BUFFER_TOO_SMALL, the buffer size needed for the request to succeed
+    BufferTooSmall(u32),
+    /// Sent with STATUS_BAD_NETWORK_NAME
+    ShareRedirect(ShareRedirectErrorContext),
+    Other {
+        error_id: u32,
+        data: Vec<u8>,
+    },
+}
+
+impl ErrorResponse {
+    /// Each error id and its data. Before SMB 3.1.1 there is only the default one, unwrapped.
+    fn raw_contexts(&self) -> serde_smb::Result<Vec<(u32, &[u8])>> {
+        if self.error_context_count == 0 {
+            if self.error_data.is_empty() {
+                return Ok(vec![]);
+            }
+            return Ok(vec![(0, &self.error_data[..])]);
+        }
+        // A list of SMB2_ERROR_CONTEXT_RESPONSE, each aligned to 8 bytes
+        let mut contexts = vec![];
+        let mut offset = 0;
+        for _ in 0..self.error_context_count {
+            let length = read_u32_at(&self.error_data, offset)? as usize;
+            let error_id = read_u32_at(&self.error_data, offset + 4)?;
+            let context = self
+                .error_data
+                .get(offset + 8..offset + 8 + length)
+                .ok_or_else(|| malformed("error context: unexpected end"))?;
+            contexts.push((error_id, context));
+            offset = align_to(offset + 8 + length, 8);
+        }
+        Ok(contexts)
+    }
+
+    /// Parses the error contexts. What the default context holds depends on `status`, the status
+    /// of the response.
+    pub fn contexts(&self, status: NtStatus) -> serde_smb::Result<Vec<ErrorContext>> {
+        let mut contexts = vec![];
+        for (error_id, data) in self.raw_contexts()? {
+            contexts.push(match (error_id, status) {
+                (0, NtStatus::StoppedOnSymlink) => {
+                    ErrorContext::SymbolicLink(SymbolicLinkErrorResponse::from_bytes(data)?)
+                }
+                (0, NtStatus::BufferTooSmall) => {
+                    ErrorContext::BufferTooSmall(read_u32_at(data, 0)?)
+                }
+                (SHARE_REDIRECT_ERROR_ID, _) => {
+                    ErrorContext::ShareRedirect(ShareRedirectErrorContext::from_bytes(data)?)
+                }
+                (error_id, _) => ErrorContext::Other {
+                    error_id,
+                    data: data.into(),
+                },
+            });
+        }
+        Ok(contexts)
+    }
+}
+
+bitflags! {
+    /// Which parts of a security descriptor to query or set
+    #[derive(PartialEq, Eq, Copy, Clone, Debug)]
+    pub struct SecurityInformation: u32 {
+        const OWNER     = 0x00000001;
+        const GROUP     = 0x00000002;
+        const DACL      = 0x00000004;
+        const SACL      = 0x00000008;
+        const LABEL     = 0x00000010;
+        const ATTRIBUTE = 0x00000020;
+        const SCOPE     = 0x00000040;
+        const BACKUP    = 0x00010000;
+    }
+}
+
+/// An SDDL string which couldn't be parsed
+#[derive(Clone, Debug, PartialEq)]
+pub struct SddlError(pub String);
+
+impl fmt::Display for SddlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid SDDL: {}", self.0)
+    }
+}
+
+impl std::error::Error for SddlError {}
+
+fn sddl_error(what: impl fmt::Display) -> SddlError {
+    SddlError(what.to_string())
+}
+
+/// The two letter SDDL names for well-known SIDs
+const SID_ALIASES: &[(&str, &str)] = &[
+    ("AN", "S-1-5-7"),
+    ("AO", "S-1-5-32-548"),
+    ("AU", "S-1-5-11"),
+    ("BA", "S-1-5-32-544"),
+    ("BG", "S-1-5-32-546"),
+    ("BO", "S-1-5-32-551"),
+    ("BU", "S-1-5-32-545"),
+    ("CG", "S-1-3-1"),
+    ("CO", "S-1-3-0"),
+    ("HI", "S-1-16-12288"),
+    ("IU", "S-1-5-4"),
+    ("LS", "S-1-5-19"),
+    ("LW", "S-1-16-4096"),
+    ("ME", "S-1-16-8192"),
+    ("NS", "S-1-5-20"),
+    ("NU", "S-1-5-2"),
+    ("PU", "S-1-5-32-547"),
+    ("RD", "S-1-5-32-555"),
+    ("SI", "S-1-16-16384"),
+    ("SO", "S-1-5-32-549"),
+    ("SU", "S-1-5-6"),
+    ("SY", "S-1-5-18"),
+    ("WD", "S-1-1-0"),
+];
+
+/// A security identifier, naming a user, group or other principal
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Sid {
+    pub identifier_authority: u64,
+    pub sub_authorities: Vec<u32>,
+}
+
+impl Sid {
+    pub fn new(identifier_authority: u64, sub_authorities: &[u32]) -> Self {
+        Self {
+            identifier_authority,
+            sub_authorities: sub_authorities.into(),
+        }
+    }
+
+    fn size(&self) -> usize {
+        8 + self.sub_authorities.len() * 4
+    }
+
+    fn write_to(&self, out: &mut Vec<u8>) {
+        out.push(1);
+        out.push(self.sub_authorities.len() as u8);
+        out.extend(&self.identifier_authority.to_be_bytes()[2..]);
+        for sub_authority in &self.sub_authorities {
+            out.extend(sub_authority.to_le_bytes());
+        }
+    }
+
+    fn read_at(data: &[u8], offset: usize) -> serde_smb::Result<Self> {
+        let header = data
+            .get(offset..offset + 8)
+            .ok_or_else(|| malformed("SID: unexpected end"))?;
+        if header[0] != 1 {
+            return Err(malformed("SID: unknown revision"));
+        }
+        let mut authority = [0; 8];
+        authority[2..].copy_from_slice(&header[2..8]);
+        let sub_authorities = (0..usize::from(header[1]))
+            .map(|i| read_u32_at(data, offset + 8 + i * 4))
+            .collect::<serde_smb::Result<_>>()?;
+        Ok(Self {
+            identifier_authority: u64::from_be_bytes(authority),
+            sub_authorities,
+        })
+    }
+
+    /// Formats the SID for SDDL, using the alias for well-known SIDs.
+    fn to_sddl(&self) -> String {
+        let s = self.to_string();
+        SID_ALIASES
+            .iter()
+            .find(|(_, sid)| *sid == s)
+            .map(|(alias, _)| alias.to_string())
+            .unwrap_or(s)
+    }
+}
+
+impl fmt::Display for Sid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.identifier_authority < 1 << 32 {
+            write!(f, "S-1-{}", self.identifier_authority)?;
+        } else {
+            write!(f, "S-1-0x{:012X}", self.identifier_authority)?;
+        }
+        for sub_authority in &self.sub_authorities {
+            write!(f, "-{sub_authority}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for Sid {
+    type Err = SddlError;
+
+    /// Parses a SID like "S-1-5-32-544", or one of the SDDL aliases like "BA".
+    fn from_str(s: &str) -> Result<Self, SddlError> {
+        let s = SID_ALIASES
+            .iter()
+            .find(|(alias, _)| *alias == s)
+            .map(|(_, sid)| *sid)
+            .unwrap_or(s);
+        let mut parts = s.split('-');
+        if parts.next() != Some("S") || parts.next() != Some("1") {
+            return Err(sddl_error(format!("bad SID {s:?}")));
+        }
+        let authority = parts
+            .next()
+            .ok_or_else(|| sddl_error(format!("bad SID {s:?}")))?;
+        let identifier_authority = match authority.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16),
+            None => authority.parse(),
+        }
+        .map_err(|_| sddl_error(format!("bad SID {s:?}")))?;
+        let sub_authorities = parts
+            .map(|p| p.parse())
+            .collect::<Result<_, _>>()
+            .map_err(|_| sddl_error(format!("bad SID {s:?}")))?;
+        Ok(Self {
+            identifier_authority,
+            sub_authorities,
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AceType {
+    AccessAllowed,
+    AccessDenied,
+    SystemAudit,
+    SystemMandatoryLabel,
+}
+
+/// Each ACE type with its binary value and SDDL name
+const ACE_TYPES: &[(AceType, u8, &str)] = &[
+    (AceType::AccessAllowed, 0x00, "A"),
+    (AceType::AccessDenied, 0x01, "D"),
+    (AceType::SystemAudit, 0x02, "AU"),
+    (AceType::SystemMandatoryLabel, 0x11, "ML"),
+];
+
+bitflags! {
+    #[derive(PartialEq, Eq, Copy, Clone, Debug)]
+    pub struct AceFlags: u8 {
+        const OBJECT_INHERIT       = 0x01;
+        const CONTAINER_INHERIT    = 0x02;
+        const NO_PROPAGATE_INHERIT = 0x04;
+        const INHERIT_ONLY         = 0x08;
+        const INHERITED            = 0x10;
+        const SUCCESSFUL_ACCESS    = 0x40;
+        const FAILED_ACCESS        = 0x80;
+    }
+}
+
+const ACE_FLAG_NAMES: &[(AceFlags, &str)] = &[
+    (AceFlags::OBJECT_INHERIT, "OI"),
+    (AceFlags::CONTAINER_INHERIT, "CI"),
+    (AceFlags::NO_PROPAGATE_INHERIT, "NP"),
+    (AceFlags::INHERIT_ONLY, "IO"),
+    (AceFlags::INHERITED, "ID"),
+    (AceFlags::SUCCESSFUL_ACCESS, "SA"),
+    (AceFlags::FAILED_ACCESS, "FA"),
+];
+
+/// SDDL names for access rights. Only the first eight are used when formatting, and only when
+/// they match the whole mask.
+const ACCESS_RIGHT_NAMES: &[(u32, &str)] = &[
+    (0x001f01ff, "FA"),
+    (0x00120089, "FR"),
+    (0x00120116, "FW"),
+    (0x001200a0, "FX"),
+    (0x10000000, "GA"),
+    (0x80000000, "GR"),
+    (0x40000000, "GW"),
+    (0x20000000, "GX"),
+    (0x00020000, "RC"),
+    (0x00010000, "SD"),
+    (0x00040000, "WD"),
+    (0x00080000, "WO"),
+    (0x00000001, "NW"),
+    (0x00000002, "NR"),
+    (0x00000004, "NX"),
+];
+
+/// SDDL names for ACE types `AceType` doesn't cover
+const OTHER_ACE_TYPE_NAMES: &[(u8, &str)] = &[
+    (0x03, "AL"),
+    (0x05, "OA"),
+    (0x06, "OD"),
+    (0x07, "OU"),
+    (0x08, "OL"),
+    (0x09, "XA"),
+    (0x0a, "XD"),
+    (0x0b, "ZA"),
+    (0x0d, "XU"),
+    (0x12, "RA"),
+    (0x13, "SP"),
+];
+
+/// Whether ACEs of type `code` are object ACEs, with GUIDs between the access mask and the SID.
+fn is_object_ace(code: u8) -> bool {
+    matches!(code, 0x05..=0x08 | 0x0b | 0x0c | 0x0f | 0x10)
+}
+
+fn sddl_ace_flags(flags: AceFlags) -> String {
+    ACE_FLAG_NAMES
+        .iter()
+        .filter(|(f, _)| flags.contains(*f))
+        .map(|(_, name)| *name)
+        .collect()
+}
+
+fn sddl_rights(mask: u32) -> String {
+    ACCESS_RIGHT_NAMES[..8]
+        .iter()
+        .find(|(m, _)| *m == mask)
+        .map(|(_, name)| name.to_string())
+        .unwrap_or_else(|| format!("0x{mask:x}"))
+}
+
+/// Formats the GUID at `offset` the way SDDL does.
+fn sddl_guid(data: &[u8], offset: usize) -> serde_smb::Result<String> {
+    let bytes = data
+        .get(offset + 8..offset + 16)
+        .ok_or_else(|| malformed("GUID: unexpected end"))?;
+    let hex = |bytes: &[u8]| -> String { bytes.iter().map(|b| format!("{b:02x}")).collect() };
+    Ok(format!(
+        "{:08x}-{:04x}-{:04x}-{}-{}",
+        read_u32_at(data, offset)?,
+        read_u16_at(data, offset + 4)?,
+        read_u16_at(data, offset + 6)?,
+        hex(&bytes[..2]),
+        hex(&bytes[2..]),
+    ))
+}
+
+/// Takes each two letter token from `s` and looks it up with `lookup`.
+fn parse_sddl_tokens<T>(
+    s: &str,
+    mut lookup: impl FnMut(&str) -> Option<T>,
+) -> Result<Vec<T>, SddlError> {
+    if s.len() % 2 != 0 || !s.is_ascii() {
+        return Err(sddl_error(format!("bad flags {s:?}")));
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| lookup(&s[i..i + 2]).ok_or_else(|| sddl_error(format!("bad flags {s:?}"))))
+        .collect()
+}
+
+/// An access control entry, allowing, denying or auditing some access for a SID
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ace {
+    pub ace_type: AceType,
+    pub flags: AceFlags,
+    pub mask: AccessMask,
+    pub sid: Sid,
+    /// Anything after the SID when the ACE was read with a larger size, written back unchanged
+    pub padding: Vec<u8>,
+}
+
+impl Ace {
+    fn size(&self) -> usize {
+        8 + self.sid.size() + self.padding.len()
+    }
+
+    fn write_to(&self, out: &mut Vec<u8>) {
+        let (_, code, _) = ACE_TYPES
+            .iter()
+            .find(|(t, ..)| *t == self.ace_type)
+            .unwrap();
+        out.push(*code);
+        out.push(self.flags.bits());
+        out.extend((self.size() as u16).to_le_bytes());
+        out.extend(self.mask.bits().to_le_bytes());
+        self.sid.write_to(out);
+        out.extend(&self.padding);
+    }
+
+    /// Reads the ACE at `offset`, returning it and its size.
+    fn read_at(data: &[u8], offset: usize) -> serde_smb::Result<(Self, usize)> {
+        let [code, flags] = read_u16_at(data, offset)?.to_le_bytes();
+        let (ace_type, ..) = ACE_TYPES
+            .iter()
+            .find(|(_, c, _)| *c == code)
+            .ok_or_else(|| malformed("ACE: unsupported type"))?;
+        let size: usize = read_u16_at(data, offset + 2)?.into();
+        let sid = Sid::read_at(data, offset + 8)?;
+        let sid_end = 8 + sid.size();
+        if size < sid_end {
+            return Err(malformed("ACE: too small"));
+        }
+        let padding = data
+            .get(offset + sid_end..offset + size)
+            .ok_or_else(|| malformed("ACE: unexpected end"))?;
+        let ace = Self {
+            ace_type: *ace_type,
+            flags: AceFlags::from_bits_retain(flags),
+            mask: AccessMask::from_bits_retain(read_u32_at(data, offset + 4)?),
+            sid,
+            padding: padding.into(),
+        };
+        Ok((ace, size))
+    }
+
+    fn to_sddl(&self) -> String {
+        let (.., ace_type) = ACE_TYPES
+            .iter()
+            .find(|(t, ..)| *t == self.ace_type)
+            .unwrap();
+        format!(
+            "({ace_type};{};{};;;{})",
+            sddl_ace_flags(self.flags),
+            sddl_rights(self.mask.bits()),
+            self.sid.to_sddl()
+        )
+    }
+
+    fn from_sddl(s: &str) -> Result<Self, SddlError> {
+        let fields: Vec<&str> = s.split(';').collect();
+        let [ace_type, flags, rights, object_guid, inherit_object_guid, sid] = fields[..] else {
+            return Err(sddl_error(format!("bad ACE {s:?}")));
+        };
+        if !object_guid.is_empty() || !inherit_object_guid.is_empty() {
+            return Err(sddl_error("object ACEs aren't supported"));
+        }
+        let (ace_type, ..) = ACE_TYPES
+            .iter()
+            .find(|(.., name)| *name == ace_type)
+            .ok_or_else(|| sddl_error(format!("bad ACE type {ace_type:?}")))?;
+        let flags = parse_sddl_tokens(flags, |token| {
+            ACE_FLAG_NAMES
+                .iter()
+                .find(|(_, name)| *name == token)
+                .map(|(f, _)| *f)
+        })?
+        .into_iter()
+        .fold(AceFlags::empty(), |a, b| a | b);
+        let mask = if let Some(hex) = rights.strip_prefix("0x") {
+            u32::from_str_radix(hex, 16)
+                .map_err(|_| sddl_error(format!("bad rights {rights:?}")))?
+        } else if rights.starts_with(|c: char| c.is_ascii_digit()) {
+            rights
+                .parse()
+                .map_err(|_| sddl_error(format!("bad rights {rights:?}")))?
+        } else {
+            parse_sddl_tokens(rights, |token| {
+                ACCESS_RIGHT_NAMES
+                    .iter()
+                    .find(|(_, name)| *name == token)
+                    .map(|(m, _)| *m)
+            })?
+            .into_iter()
+            .fold(0, |a, b| a | b)
+        };
+        Ok(Self {
+            ace_type: *ace_type,
+            flags,
+            mask: AccessMask::from_bits_retain(mask),
+            sid: sid.parse()?,
+            padding: vec![],
+        })
+    }
+}
+
+/// An entry of an `Acl`
+#[derive(Clone, Debug, PartialEq)]
+pub enum AclEntry {
+    Ace(Ace),
+    /// An ACE of a type `AceType` doesn't cover, like the object and callback ACEs, kept as it was
+    /// read so that setting the descriptor back leaves it unchanged
+    Other {
+        ace_type: u8,
+        flags: AceFlags,
+        /// Everything after the type, flags and size
+        body: Vec<u8>,
+    },
+}
+
+impl From<Ace> for AclEntry {
+    fn from(ace: Ace) -> Self {
+        Self::Ace(ace)
+    }
+}
+
+impl AclEntry {
+    fn size(&self) -> usize {
+        match self {
+            Self::Ace(ace) => ace.size(),
+            Self::Other { body, .. } => 4 + body.len(),
+        }
+    }
+
+    fn write_to(&self, out: &mut Vec<u8>) {
+        match self {
+            Self::Ace(ace) => ace.write_to(out),
+            Self::Other {
+                ace_type,
+                flags,
+                body,
+            } => {
+                out.push(*ace_type);
+                out.push(flags.bits());
+                out.extend((self.size() as u16).to_le_bytes());
+                out.extend(body);
+            }
+        }
+    }
+
+    /// Reads the entry at `offset`, returning it and its size.
+    fn read_at(data: &[u8], offset: usize) -> serde_smb::Result<(Self, usize)> {
+        let [code, flags] = read_u16_at(data, offset)?.to_le_bytes();
+        if ACE_TYPES.iter().any(|(_, c, _)| *c == code) {
+            let (ace, size) = Ace::read_at(data, offset)?;
+            return Ok((Self::Ace(ace), size));
+        }
+        let size: usize = read_u16_at(data, offset + 2)?.into();
+        if size < 4 {
+            return Err(malformed("ACE: too small"));
+        }
+        let body = data
+            .get(offset + 4..offset + size)
+            .ok_or_else(|| malformed("ACE: unexpected end"))?;
+        let entry = Self::Other {
+            ace_type: code,
+            flags: AceFlags::from_bits_retain(flags),
+            body: body.into(),
+        };
+        Ok((entry, size))
+    }
+
+    /// Formats the entry for SDDL. Other ACEs are formatted as far as they can be, everything
+    /// after the SID is left out.
+    fn to_sddl(&self) -> String {
+        let (ace_type, flags, body) = match self {
+            Self::Ace(ace) => return ace.to_sddl(),
+            Self::Other {
+                ace_type,
+                flags,
+                body,
+            } => (*ace_type, *flags, body),
+        };
+        let name = OTHER_ACE_TYPE_NAMES
+            .iter()
+            .find(|(c, _)| *c == ace_type)
+            .map(|(_, name)| name.to_string())
+            .unwrap_or_else(|| format!("0x{ace_type:x}"));
+        let rights = read_u32_at(body, 0).map(sddl_rights).unwrap_or_default();
+        let mut guids = [String::new(), String::new()];
+        let mut sid_offset = 4;
+        if is_object_ace(ace_type) {
+            let object_flags = read_u32_at(body, 4).unwrap_or(0);
+            sid_offset = 8;
+            for (i, guid) in guids.iter_mut().enumerate() {
+                if object_flags & (1 << i) != 0 {
+                    *guid = sddl_guid(body, sid_offset).unwrap_or_default();
+                    sid_offset += 16;
+                }
+            }
+        }
+        let sid = Sid::read_at(body, sid_offset)
+            .map(|sid| sid.to_sddl())
+            .unwrap_or_default();
+        format!(
+            "({name};{};{rights};{};{};{sid})",
+            sddl_ace_flags(flags),
+            guids[0],
+            guids[1]
+        )
+    }
+}
+
+/// An access control list, the entries are checked in order
+#[derive(Clone, Debug, PartialEq)]
+pub struct Acl {
+    pub revision: u8,
+    pub aces: Vec<AclEntry>,
+}
+
+impl Acl {
+    pub const REVISION: u8 = 2;
+
+    pub fn new(aces: Vec<AclEntry>) -> Self {
+        Self {
+            revision: Self::REVISION,
+            aces,
+        }
+    }
+
+    fn size(&self) -> usize {
+        8 + self.aces.iter().map(AclEntry::size).sum::<usize>()
+    }
+
+    fn write_to(&self, out: &mut Vec<u8>) {
+        out.push(self.revision);
+        out.push(0);
+        out.extend((self.size() as u16).to_le_bytes());
+        out.extend((self.aces.len() as u16).to_le_bytes());
+        out.extend([0, 0]);
+        for ace in &self.aces {
+            ace.write_to(out);
+        }
+    }
+
+    fn read_at(data: &[u8], offset: usize) -> serde_smb::Result<Self> {
+        let revision = *data
+            .get(offset)
+            .ok_or_else(|| malformed("ACL: unexpected end"))?;
+        let ace_count = read_u16_at(data, offset + 4)?;
+        let mut aces = vec![];
+        let mut position = offset + 8;
+        for _ in 0..ace_count {
+            let (ace, size) = AclEntry::read_at(data, position)?;
+            aces.push(ace);
+            position += size;
+        }
+        Ok(Self { revision, aces })
+    }
+}
+
+bitflags! {
+    #[derive(PartialEq, Eq, Copy, Clone, Debug)]
+    pub struct SecurityDescriptorControl: u16 {
+        const OWNER_DEFAULTED                    = 0x0001;
+        const GROUP_DEFAULTED                    = 0x0002;
+        const DACL_PRESENT                       = 0x0004;
+        const DACL_DEFAULTED                     = 0x0008;
+        const SACL_PRESENT                       = 0x0010;
+        const SACL_DEFAULTED                     = 0x0020;
+        const DACL_TRUSTED                       = 0x0040;
+        const SERVER_SECURITY                    = 0x0080;
+        const DACL_COMPUTED_INHERITANCE_REQUIRED = 0x0100;
+        const SACL_COMPUTED_INHERITANCE_REQUIRED = 0x0200;
+        const DACL_AUTO_INHERITED                = 0x0400;
+        const SACL_AUTO_INHERITED                = 0x0800;
+        const DACL_PROTECTED                     = 0x1000;
+        const SACL_PROTECTED                     = 0x2000;
+        const RM_CONTROL_VALID                   = 0x4000;
+        const SELF_RELATIVE                      = 0x8000;
+    }
+}
+
+/// The owner, group and ACLs of a file. An ACL which is present but `None` is a NULL ACL, for
+/// the DACL this means everyone has full access.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SecurityDescriptor {
+    pub control: SecurityDescriptorControl,
+    pub owner: Option<Sid>,
+    pub group: Option<Sid>,
+    pub sacl: Option<Acl>,
+    pub dacl: Option<Acl>,
+}
+
+impl SecurityDescriptor {
+    /// Encodes the descriptor in the self-relative format.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut control = self.control | SecurityDescriptorControl::SELF_RELATIVE;
+        if self.sacl.is_some() {
+            control |= SecurityDescriptorControl::SACL_PRESENT;
+        }
+        if self.dacl.is_some() {
+            control |= SecurityDescriptorControl::DACL_PRESENT;
+        }
+
+        let mut data = vec![0; 20];
+        data[0] = 1;
+        data[2..4].copy_from_slice(&control.bits().to_le_bytes());
+        let record_offset = |data: &mut Vec<u8>, field: usize| {
+            let offset = data.len() as u32;
+            data[field..field + 4].copy_from_slice(&offset.to_le_bytes());
+        };
+        if let Some(owner) = &self.owner {
+            record_offset(&mut data, 4);
+            owner.write_to(&mut data);
+        }
+        if let Some(group) = &self.group {
+            record_offset(&mut data, 8);
+            group.write_to(&mut data);
+        }
+        if let Some(sacl) = &self.sacl {
+            record_offset(&mut data, 12);
+            sacl.write_to(&mut data);
+        }
+        if let Some(dacl) = &self.dacl {
+            record_offset(&mut data, 16);
+            dacl.write_to(&mut data);
+        }
+        data
+    }
+
+    /// Decodes a descriptor in the self-relative format.
+    pub fn from_bytes(data: &[u8]) -> serde_smb::Result<Self> {
+        if data.first() != Some(&1) {
+            return Err(malformed("security descriptor: unknown revision"));
+        }
+        let control = SecurityDescriptorControl::from_bits_retain(read_u16_at(data, 2)?);
+        let at = |field| -> serde_smb::Result<Option<usize>> {
+            let offset = read_u32_at(data, field)? as usize;
+            Ok((offset != 0).then_some(offset))
+        };
+        Ok(Self {
+            control,
+            owner: at(4)?.map(|o| Sid::read_at(data, o)).transpose()?,
+            group: at(8)?.map(|o| Sid::read_at(data, o)).transpose()?,
+            sacl: at(12)?.map(|o| Acl::read_at(data, o)).transpose()?,
+            dacl: at(16)?.map(|o| Acl::read_at(data, o)).transpose()?,
+        })
+    }
+
+    fn acl_to_sddl(
+        &self,
+        acl: Option<&Acl>,
+        present: SecurityDescriptorControl,
+        protected: SecurityDescriptorControl,
+        auto_inherited: SecurityDescriptorControl,
+        inheritance_required: SecurityDescriptorControl,
+    ) -> String {
+        let mut s = String::new();
+        for (flag, name) in [
+            (protected, "P"),
+            (auto_inherited, "AI"),
+            (inheritance_required, "AR"),
+        ] {
+            if self.control.contains(flag) {
+                s += name;
+            }
+        }
+        match acl {
+            Some(acl) => s.extend(acl.aces.iter().map(AclEntry::to_sddl)),
+            None if self.control.contains(present) => s += "NO_ACCESS_CONTROL",
+            None => {}
+        }
+        s
+    }
+
+    /// Formats the descriptor in the Security Descriptor Definition Language, like
+    /// "O:BAG:BAD:(A;;FA;;;SY)".
+    pub fn to_sddl(&self) -> String {
+        use SecurityDescriptorControl as C;
+        let mut s = String::new();
+        if let Some(owner) = &self.owner {
+            s += &format!("O:{}", owner.to_sddl());
+        }
+        if let Some(group) = &self.group {
+            s += &format!("G:{}", group.to_sddl());
+        }
+        if self.dacl.is_some() || self.control.contains(C::DACL_PRESENT) {
+            let dacl = self.acl_to_sddl(
+                self.dacl.as_ref(),
+                C::DACL_PRESENT,
+                C::DACL_PROTECTED,
+                C::DACL_AUTO_INHERITED,
+                C::DACL_COMPUTED_INHERITANCE_REQUIRED,
+            );
+            s += &format!("D:{dacl}");
+        }
+        if self.sacl.is_some() || self.control.contains(C::SACL_PRESENT) {
+            let sacl = self.acl_to_sddl(
+                self.sacl.as_ref(),
+                C::SACL_PRESENT,
+                C::SACL_PROTECTED,
+                C::SACL_AUTO_INHERITED,
+                C::SACL_COMPUTED_INHERITANCE_REQUIRED,
+            );
+            s += &format!("S:{sacl}");
+        }
+        s
+    }
+
+    /// Parses the Security Descriptor Definition Language, the opposite of [`Self::to_sddl`].
+    pub fn from_sddl(sddl: &str) -> Result<Self, SddlError> {
+        use SecurityDescriptorControl as C;
+        let mut descriptor = Self {
+            control: C::SELF_RELATIVE,
+            owner: None,
+            group: None,
+            sacl: None,
+            dacl: None,
+        };
+        let mut rest = sddl.trim();
+        while !rest.is_empty() {
+            let (key, tail) = rest
+                .split_once(':')
+                .ok_or_else(|| sddl_error(format!("expected a section in {rest:?}")))?;
+            let end = sddl_section_end(tail);
+            let value = &tail[..end];
+            rest = &tail[end..];
+            match key {
+                "O" => descriptor.owner = Some(value.parse()?),
+                "G" => descriptor.group = Some(value.parse()?),
+                "D" => {
+                    let (acl, control) = parse_sddl_acl(
+                        value,
+                        [
+                            C::DACL_PRESENT,
+                            C::DACL_PROTECTED,
+                            C::DACL_AUTO_INHERITED,
+                            C::DACL_COMPUTED_INHERITANCE_REQUIRED,
+                        ],
+                    )?;
+                    descriptor.dacl = acl;
+                    descriptor.control |= control;
+                }
+                "S" => {
+                    let (acl, control) = parse_sddl_acl(
+                        value,
+                        [
+                            C::SACL_PRESENT,
+                            C::SACL_PROTECTED,
+                            C::SACL_AUTO_INHERITED,
+                            C::SACL_COMPUTED_INHERITANCE_REQUIRED,
+                        ],
+                    )?;
+                    descriptor.sacl = acl;
+                    descriptor.control |= control;
+                }
+                _ => return Err(sddl_error(format!("unknown section {key:?}"))),
+            }
+        }
+        Ok(descriptor)
+    }
+}
+
+/// Finds where the SDDL section starting at `s` ends, which is where the next "X:" appears
+/// outside of an ACE.
+fn sddl_section_end(s: &str) -> usize {
+    let bytes = s.as_bytes();
+    let mut depth = 0;
+    for (i, c) in bytes.iter().enumerate() {
+        match c {
+            b'(' => depth += 1,
+            b')' => depth -= 1,
+            b'O' | b'G' | b'D' | b'S' if depth == 0 && bytes.get(i + 1) == Some(&b':') => {
+                return i;
+            }
+            _ => {}
+        }
+    }
+    s.len()
+}
+
+/// Parses the ACL part of a "D:" or "S:" section. `flags` are the present, protected,
+/// auto-inherited and inheritance-required control flags for this ACL.
+fn parse_sddl_acl(
+    s: &str,
+    flags: [SecurityDescriptorControl; 4],
+) -> Result<(Option<Acl>, SecurityDescriptorControl), SddlError> {
+    let [present, protected, auto_inherited, inheritance_required] = flags;
+    let (acl_flags, mut aces) = s.split_at(s.find('(').unwrap_or(s.len()));
+    let (acl_flags, null_acl) = match acl_flags.strip_suffix("NO_ACCESS_CONTROL") {
+        Some(acl_flags) => (acl_flags, true),
+        None => (acl_flags, false),
+    };
+
+    let mut control = present;
+    for flag in parse_sddl_acl_flags(acl_flags)? {
+        control |= match flag {
+            "P" => protected,
+            "AI" => auto_inherited,
+            _ => inheritance_required,
+        };
+    }
+    if null_acl {
+        if !aces.is_empty() {
+            return Err(sddl_error("ACEs in a NULL ACL"));
+        }
+        return Ok((None, control));
+    }
+
+    let mut acl = Acl::new(vec![]);
+    while !aces.is_empty() {
+        let (ace, tail) = aces
+            .strip_prefix('(')
+            .and_then(|a| a.split_once(')'))
+            .ok_or_else(|| sddl_error(format!("bad ACE in {aces:?}")))?;
+        acl.aces.push(Ace::from_sddl(ace)?.into());
+        aces = tail;
+    }
+    Ok((Some(acl), control))
+}
+
+fn parse_sddl_acl_flags(mut s: &str) -> Result<Vec<&'static str>, SddlError> {
+    let mut flags = vec![];
+    while !s.is_empty() {
+        let flag = ["P", "AI", "AR"]
+            .into_iter()
+            .find(|f| s.starts_with(f))
+            .ok_or_else(|| sddl_error(format!("bad ACL flags {s:?}")))?;
+        flags.push(flag);
+        s = &s[flag.len()..];
+    }
+    Ok(flags)
+}
+
+#[test]
+fn sid_display_and_parse() {
+    let sid = Sid::new(5, &[21, 1004336348, 1177238915, 682003330, 512]);
+    assert_eq!(
+        sid.to_string(),
+        "S-1-5-21-1004336348-1177238915-682003330-512"
+    );
+    assert_eq!(sid.to_string().parse::<Sid>().unwrap(), sid);
+    assert_eq!("BA".parse::<Sid>().unwrap(), Sid::new(5, &[32, 544]));
+    assert_eq!(Sid::new(1, &[0]).to_sddl(), "WD");
+    assert!("S-2-5".parse::<Sid>().is_err());
+}
+
+#[test]
+fn sddl_round_trip() {
+    let sddl = "O:BAG:SYD:PAI(A;OICI;FA;;;SY)(A;OICIID;0x1301bf;;;AU)(D;;FW;;;WD)S:(AU;FA;FR;;;WD)";
+    let descriptor = SecurityDescriptor::from_sddl(sddl).unwrap();
+    assert_eq!(descriptor.owner, Some(Sid::new(5, &[32, 544])));
+    assert!(descriptor
+        .control
+        .contains(SecurityDescriptorControl::DACL_PROTECTED));
+    let dacl = descriptor.dacl.as_ref().unwrap();
+    assert_eq!(dacl.aces.len(), 3);
+    assert_eq!(
+        dacl.aces[1],
+        AclEntry::Ace(Ace {
+            ace_type: AceType::AccessAllowed,
+            flags: AceFlags::OBJECT_INHERIT | AceFlags::CONTAINER_INHERIT | AceFlags::INHERITED,
+            mask: AccessMask::from_bits_retain(0x1301bf),
+            sid: Sid::new(5, &[11]),
+            padding: vec![],
+        })
+    );
+    assert_eq!(descriptor.to_sddl(), sddl);
+
+    let null_dacl = SecurityDescriptor::from_sddl("D:NO_ACCESS_CONTROL").unwrap();
+    assert_eq!(null_dacl.dacl, None);
+    assert_eq!(null_dacl.to_sddl(), "D:NO_ACCESS_CONTROL");
+
+    assert!(SecurityDescriptor::from_sddl("D:(A;;FA;;;SY").is_err());
+    assert!(SecurityDescriptor::from_sddl("X:BA").is_err());
+}
+
+#[test]
+fn other_aces_round_trip() {
+    let body = [
+        [0x00, 0x01, 0x00, 0x00].as_slice(), // mask
+        &[0x01, 0x00, 0x00, 0x00],           // object type present
+        &[0x70, 0x95, 0x29, 0x00, 0x6d, 0x24, 0xd0, 0x11],
+        &[0xa7, 0x68, 0x00, 0xaa, 0x00, 0x6e, 0x05, 0x29],
+        &[0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01], // S-1-1
+        &[0x00, 0x00, 0x00, 0x00],                         // -0
+    ]
+    .concat();
+    let descriptor = SecurityDescriptor {
+        control: SecurityDescriptorControl::SELF_RELATIVE | SecurityDescriptorControl::DACL_PRESENT,
+        owner: None,
+        group: None,
+        sacl: None,
+        dacl: Some(Acl::new(vec![
+            AclEntry::Other {
+                ace_type: 0x05,
+                flags: AceFlags::CONTAINER_INHERIT,
+                body,
+            },
+            AclEntry::Other {
+                ace_type: 0x42,
+                flags: AceFlags::empty(),
+                body: vec![1, 2, 3],
+            },
+        ])),
+    };
+    assert_eq!(
+        SecurityDescriptor::from_bytes(&descriptor.to_bytes()).unwrap(),
+        descriptor
+    );
+    assert_eq!(
+        descriptor.to_sddl(),
+        "D:(OA;CI;0x100;00299570-246d-11d0-a768-00aa006e0529;;WD)(0x42;;;;;)"
+    );
+}
@@ -548,3 +548,887 @@ fn create_response() {
 
     assert_eq!(deserialized, (header, res), "actual != expected");
 }
+
+#[test]
+fn ioctl_request() {
+    let header = RequestHeader {
+        protocol_id: ProtocolId::new(),
+        header_length: 64,
+        credit_charge: Credits(1),
+        channel_sequence: 0,
+        command: Command::Ioctl,
+        credits_requested: Credits(64),
+        flags: HeaderFlags::new(),
+        chain_offset: 0,
+        message_id: MessageId(7),
+        process_id: ProcessId(0),
+        tree_id: TreeId(0x2d3e4f50),
+        session_id: SessionId(0x3a7db10a),
+        signature: Signature([0; 16]),
+    };
+    let req = IoctlRequest {
+        ctl_code: CtlCode::FsctlPipeTransceive,
+        file_id: FileId {
+            persistent: 0x00000000a0b1c2d3,
+            volatile: 0x00000000e4f50617,
+        },
+        max_input_response: 0,
+        max_output_response: 4280,
+        flags: IoctlFlags::IS_FSCTL,
+        input: vec![0x05, 0x00, 0x00, 0x03, 0x10, 0x00, 0x00, 0x00],
+    };
+
+    let actual = serde_smb::to_vec(&(&header, &req)).unwrap();
+
+    let expected = [
+        0xfe, 0x53, 0x4d, 0x42, 0x40, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0b, 0x00, 0x40,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x07, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x50, 0x4f, 0x3e, 0x2d, 0x0a, 0xb1, 0x7d, 0x3a, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x39, 0x00, 0x00, 0x00, 0x17, 0xc0, 0x11, 0x00, 0xd3, 0xc2, 0xb1,
+        0xa0, 0x00, 0x00, 0x00, 0x00, 0x17, 0x06, 0xf5, 0xe4, 0x00, 0x00, 0x00, 0x00, 0x78, 0x00,
+        0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0xb8, 0x10, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x05, 0x00, 0x00, 0x03, 0x10, 0x00, 0x00, 0x00,
+    ];
+    assert_bytes_equal(&expected, &actual);
+
+    let deserialized: (RequestHeader, IoctlRequest) = serde_smb::from_slice(&expected[..]).unwrap();
+    assert_eq!(deserialized, (header, req));
+}
+
+#[test]
+fn ioctl_response() {
+    let header = ResponseHeader {
+        protocol_id: ProtocolId::new(),
+        header_length: 64,
+        credit_charge: Credits(1),
+        nt_status: NtStatus::BufferOverflow,
+        command: Command::Ioctl,
+        credits_granted: Credits(64),
+        flags: HeaderFlags::new().with_response(true),
+        chain_offset: 0,
+        message_id: MessageId(7),
+        process_id: ProcessId(0),
+        tree_id: TreeId(0x2d3e4f50),
+        session_id: SessionId(0x3a7db10a),
+        signature: Signature([0; 16]),
+    };
+    let res = IoctlResponse {
+        ctl_code: CtlCode::FsctlPipeTransceive,
+        file_id: FileId {
+            persistent: 0x00000000a0b1c2d3,
+            volatile: 0x00000000e4f50617,
+        },
+        flags: IoctlFlags::empty(),
+        input: vec![],
+        output: vec![0x05, 0x00, 0x02, 0x03, 0x10, 0x00, 0x00, 0x00, 0xaa, 0xbb],
+    };
+
+    let actual = serde_smb::to_vec(&(&header, &res)).unwrap();
+
+    let expected = [
+        0xfe, 0x53, 0x4d, 0x42, 0x40, 0x00, 0x01, 0x00, 0x05, 0x00, 0x00, 0x80, 0x0b, 0x00, 0x40,
+        0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x07, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x50, 0x4f, 0x3e, 0x2d, 0x0a, 0xb1, 0x7d, 0x3a, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x31, 0x00, 0x00, 0x00, 0x17, 0xc0, 0x11, 0x00, 0xd3, 0xc2, 0xb1,
+        0xa0, 0x00, 0x00, 0x00, 0x00, 0x17, 0x06, 0xf5, 0xe4, 0x00, 0x00, 0x00, 0x00, 0x70, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x70, 0x00, 0x00, 0x00, 0x0a, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x00, 0x02, 0x03, 0x10, 0x00, 0x00, 0x00,
+        0xaa, 0xbb,
+    ];
+    assert_bytes_equal(&expected, &actual);
+
+    let deserialized: (ResponseHeader, IoctlResponse) =
+        serde_smb::from_slice(&expected[..]).unwrap();
+    assert_eq!(deserialized, (header, res));
+}
+
+#[test]
+fn fsctl_pipe_wait_request() {
+    let req = FsctlPipeWaitRequest {
+        timeout: 50_000_000,
+        timeout_specified: true,
+        name: "srvsvc".into(),
+    };
+
+    let actual = serde_smb::to_vec(&req).unwrap();
+
+    let expected = [
+        0x80, 0xf0, 0xfa, 0x02, 0x00, 0x00, 0x00, 0x00, // timeout
+        0x0c, 0x00, 0x00, 0x00, // name length
+        0x01, // timeout specified
+        0x00, // padding
+        0x73, 0x00, 0x72, 0x00, 0x76, 0x00, 0x73, 0x00, 0x76, 0x00, 0x63, 0x00, // name
+    ];
+    assert_bytes_equal(&expected, &actual);
+
+    let deserialized: FsctlPipeWaitRequest = serde_smb::from_slice(&expected[..]).unwrap();
+    assert_eq!(deserialized, req);
+}
+
+#[test]
+fn dfs_get_referrals_request() {
+    let req = DfsGetReferralsRequest {
+        max_referral_level: 4,
+        request_file_name: "\\srv\\dfs\\link".into(),
+    };
+
+    let expected = [
+        0x04, 0x00, // max referral level
+        0x5c, 0x00, 0x73, 0x00, 0x72, 0x00, 0x76, 0x00, // request file name
+        0x5c, 0x00, 0x64, 0x00, 0x66, 0x00, 0x73, 0x00, // ..
+        0x5c, 0x00, 0x6c, 0x00, 0x69, 0x00, 0x6e, 0x00, // ..
+        0x6b, 0x00, 0x00, 0x00, // ..
+    ];
+    assert_bytes_equal(&expected, &req.to_bytes());
+}
+
+#[test]
+fn dfs_get_referrals_ex_request() {
+    let req = DfsGetReferralsExRequest {
+        max_referral_level: 4,
+        request_file_name: "\\srv\\dfs\\link".into(),
+        site_name: Some("Site".into()),
+    };
+
+    let expected = [
+        0x04, 0x00, // max referral level
+        0x01, 0x00, // flags (site name)
+        0x2a, 0x00, 0x00, 0x00, // request data length
+        0x1c, 0x00, // request file name length
+        0x5c, 0x00, 0x73, 0x00, 0x72, 0x00, 0x76, 0x00, // request file name
+        0x5c, 0x00, 0x64, 0x00, 0x66, 0x00, 0x73, 0x00, // ..
+        0x5c, 0x00, 0x6c, 0x00, 0x69, 0x00, 0x6e, 0x00, // ..
+        0x6b, 0x00, 0x00, 0x00, // ..
+        0x0a, 0x00, // site name length
+        0x53, 0x00, 0x69, 0x00, 0x74, 0x00, 0x65, 0x00, // site name
+        0x00, 0x00, // ..
+    ];
+    assert_bytes_equal(&expected, &req.to_bytes());
+}
+
+#[test]
+fn dfs_referral_response() {
+    let data = [
+        0x1a, 0x00, // path consumed
+        0x02, 0x00, // number of referrals
+        0x02, 0x00, 0x00, 0x00, // flags (storage servers)
+        0x03, 0x00, 0x22, 0x00, // [0] version, size
+        0x00, 0x00, 0x00, 0x00, // [0] server type (non-root), flags
+        0x2c, 0x01, 0x00, 0x00, // [0] time to live
+        0x44, 0x00, 0x44, 0x00, 0x60, 0x00, // [0] path offsets
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // [0] service site guid
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // ..
+        0x03, 0x00, 0x22, 0x00, // [1] version, size
+        0x00, 0x00, 0x00, 0x00, // [1] server type (non-root), flags
+        0x2c, 0x01, 0x00, 0x00, // [1] time to live
+        0x22, 0x00, 0x22, 0x00, 0x50, 0x00, // [1] path offsets
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // [1] service site guid
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // ..
+        0x5c, 0x00, 0x73, 0x00, 0x72, 0x00, 0x76, 0x00, // dfs path
+        0x5c, 0x00, 0x64, 0x00, 0x66, 0x00, 0x73, 0x00, // ..
+        0x5c, 0x00, 0x6c, 0x00, 0x69, 0x00, 0x6e, 0x00, // ..
+        0x6b, 0x00, 0x00, 0x00, // ..
+        0x5c, 0x00, 0x61, 0x00, 0x5c, 0x00, 0x73, 0x00, // [0] network address
+        0x68, 0x00, 0x61, 0x00, 0x72, 0x00, 0x65, 0x00, // ..
+        0x00, 0x00, // ..
+        0x5c, 0x00, 0x62, 0x00, 0x5c, 0x00, 0x73, 0x00, // [1] network address
+        0x68, 0x00, 0x61, 0x00, 0x72, 0x00, 0x65, 0x00, // ..
+        0x00, 0x00, // ..
+    ];
+
+    let target = |network_address: &str| DfsReferralEntry {
+        version: 3,
+        server_type: DfsServerType::NonRoot,
+        flags: DfsReferralEntryFlags::empty(),
+        time_to_live: 300,
+        target: DfsReferralTarget::Path {
+            dfs_path: "\\srv\\dfs\\link".into(),
+            dfs_alternate_path: "\\srv\\dfs\\link".into(),
+            network_address: network_address.into(),
+        },
+    };
+    let expected = DfsReferralResponse {
+        path_consumed: 26,
+        flags: DfsReferralHeaderFlags::STORAGE_SERVERS,
+        entries: vec![target("\\a\\share"), target("\\b\\share")],
+    };
+    assert_eq!(DfsReferralResponse::from_bytes(&data).unwrap(), expected);
+}
+
+#[test]
+fn symbolic_link_reparse_data() {
+    let data = ReparseData::SymbolicLink(SymbolicLinkReparseData {
+        substitute_name: "..\\target".into(),
+        print_name: "..\\target".into(),
+        flags: SymbolicLinkFlags::RELATIVE,
+    });
+
+    let expected = [
+        0x0c, 0x00, 0x00, 0xa0, // reparse tag (symlink)
+        0x30, 0x00, 0x00, 0x00, // reparse data length, reserved
+        0x00, 0x00, 0x12, 0x00, // substitute name offset, length
+        0x12, 0x00, 0x12, 0x00, // print name offset, length
+        0x01, 0x00, 0x00, 0x00, // flags (relative)
+        0x2e, 0x00, 0x2e, 0x00, 0x5c, 0x00, 0x74, 0x00, // substitute name
+        0x61, 0x00, 0x72, 0x00, 0x67, 0x00, 0x65, 0x00, // ..
+        0x74, 0x00, // ..
+        0x2e, 0x00, 0x2e, 0x00, 0x5c, 0x00, 0x74, 0x00, // print name
+        0x61, 0x00, 0x72, 0x00, 0x67, 0x00, 0x65, 0x00, // ..
+        0x74, 0x00, // ..
+    ];
+    assert_bytes_equal(&expected, &data.to_bytes());
+    assert_eq!(ReparseData::from_bytes(&expected).unwrap(), data);
+}
+
+#[test]
+fn mount_point_reparse_data() {
+    let data = ReparseData::MountPoint(MountPointReparseData {
+        substitute_name: "\\??\\C:\\dir".into(),
+        print_name: "C:\\dir".into(),
+    });
+
+    let expected = [
+        0x03, 0x00, 0x00, 0xa0, // reparse tag (mount point)
+        0x2c, 0x00, 0x00, 0x00, // reparse data length, reserved
+        0x00, 0x00, 0x14, 0x00, // substitute name offset, length
+        0x16, 0x00, 0x0c, 0x00, // print name offset, length
+        0x5c, 0x00, 0x3f, 0x00, 0x3f, 0x00, 0x5c, 0x00, // substitute name
+        0x43, 0x00, 0x3a, 0x00, 0x5c, 0x00, 0x64, 0x00, // ..
+        0x69, 0x00, 0x72, 0x00, 0x00, 0x00, // ..
+        0x43, 0x00, 0x3a, 0x00, 0x5c, 0x00, 0x64, 0x00, // print name
+        0x69, 0x00, 0x72, 0x00, 0x00, 0x00, // ..
+    ];
+    assert_bytes_equal(&expected, &data.to_bytes());
+    assert_eq!(ReparseData::from_bytes(&expected).unwrap(), data);
+}
+
+#[test]
+fn stopped_on_symlink_error_response() {
+    let data = [
+        0x09, 0x00, // structure size
+        0x01, 0x00, // error context count, reserved
+        0x3c, 0x00, 0x00, 0x00, // byte count
+        0x34, 0x00, 0x00, 0x00, // error data length
+        0x00, 0x00, 0x00, 0x00, // error id (default)
+        0x30, 0x00, 0x00, 0x00, // symlink length
+        0x53, 0x59, 0x4d, 0x4c, // symlink error tag
+        0x0c, 0x00, 0x00, 0xa0, // reparse tag (symlink)
+        0x24, 0x00, 0x0a, 0x00, // reparse data length, unparsed path length
+        0x00, 0x00, 0x0c, 0x00, // substitute name offset, length
+        0x0c, 0x00, 0x0c, 0x00, // print name offset, length
+        0x01, 0x00, 0x00, 0x00, // flags (relative)
+        0x74, 0x00, 0x61, 0x00, 0x72, 0x00, 0x67, 0x00, // substitute name
+        0x65, 0x00, 0x74, 0x00, // ..
+        0x74, 0x00, 0x61, 0x00, 0x72, 0x00, 0x67, 0x00, // print name
+        0x65, 0x00, 0x74, 0x00, // ..
+    ];
+
+    let response: ErrorResponse = serde_smb::from_slice(&data[..]).unwrap();
+    assert_eq!(response.error_context_count, 1);
+    assert_eq!(
+        response.contexts(NtStatus::StoppedOnSymlink).unwrap(),
+        vec![ErrorContext::SymbolicLink(SymbolicLinkErrorResponse {
+            unparsed_path_length: 10,
+            substitute_name: "target".into(),
+            print_name: "target".into(),
+            flags: SymbolicLinkFlags::RELATIVE,
+        })]
+    );
+    assert_bytes_equal(&data, &serde_smb::to_vec(&response).unwrap());
+}
+
+#[test]
+fn buffer_too_small_error_response() {
+    let data = [
+        0x09, 0x00, // structure size
+        0x00, 0x00, // error context count, reserved
+        0x04, 0x00, 0x00, 0x00, // byte count
+        0x34, 0x12, 0x00, 0x00, // required buffer size
+    ];
+
+    let response: ErrorResponse = serde_smb::from_slice(&data[..]).unwrap();
+    assert_eq!(
+        response.contexts(NtStatus::BufferTooSmall).unwrap(),
+        vec![ErrorContext::BufferTooSmall(0x1234)]
+    );
+    assert_bytes_equal(&data, &serde_smb::to_vec(&response).unwrap());
+}
+
+#[test]
+fn share_redirect_error_response() {
+    let data = [
+        0x09, 0x00, // structure size
+        0x01, 0x00, // error context count, reserved
+        0x70, 0x00, 0x00, 0x00, // byte count
+        0x62, 0x00, 0x00, 0x00, // error data length
+        0x53, 0x52, 0x64, 0x72, // error id (share redirect)
+        0x30, 0x00, 0x00, 0x00, // structure size
+        0x03, 0x00, 0x00, 0x00, // notification type
+        0x48, 0x00, 0x00, 0x00, // resource name offset
+        0x1a, 0x00, 0x00, 0x00, // resource name length
+        0x00, 0x00, 0x00, 0x00, // flags, target type
+        0x02, 0x00, 0x00, 0x00, // ip address count
+        0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // type (v4), reserved
+        0x0a, 0x00, 0x00, 0x07, 0x00, 0x00, 0x00, 0x00, // address
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // ..
+        0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // type (v6), reserved
+        0xfe, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // address
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, // ..
+        0x5c, 0x00, 0x5c, 0x00, 0x6f, 0x00, 0x74, 0x00, // resource name
+        0x68, 0x00, 0x65, 0x00, 0x72, 0x00, 0x5c, 0x00, // ..
+        0x73, 0x00, 0x68, 0x00, 0x61, 0x00, 0x72, 0x00, // ..
+        0x65, 0x00, // ..
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // padding
+    ];
+
+    let response: ErrorResponse = serde_smb::from_slice(&data[..]).unwrap();
+    assert_eq!(
+        response.contexts(NtStatus::BadNetworkName).unwrap(),
+        vec![ErrorContext::ShareRedirect(ShareRedirectErrorContext {
+            resource_name: "\\\\other\\share".into(),
+            ip_addresses: vec!["10.0.0.7".parse().unwrap(), "fe80::1".parse().unwrap(),],
+        })]
+    );
+    assert_bytes_equal(&data, &serde_smb::to_vec(&response).unwrap());
+}
+
+#[test]
+fn security_descriptor() {
+    let data = [
+        0x01, 0x00, 0x04, 0x94, // revision, sbz1, control
+        0x14, 0x00, 0x00, 0x00, // owner offset
+        0x24, 0x00, 0x00, 0x00, // group offset
+        0x00, 0x00, 0x00, 0x00, // sacl offset
+        0x30, 0x00, 0x00, 0x00, // dacl offset
+        0x01, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, // owner (BA)
+        0x20, 0x00, 0x00, 0x00, // ..
+        0x20, 0x02, 0x00, 0x00, // ..
+        0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, // group (SY)
+        0x12, 0x00, 0x00, 0x00, // ..
+        0x02, 0x00, 0x34, 0x00, 0x02, 0x00, 0x00,
+        0x00, // revision, sbz1, acl size, ace count, sbz2
+        0x00, 0x03, 0x14, 0x00, // ace type (allowed), flags (OI CI), size
+        0xff, 0x01, 0x1f, 0x00, // mask (FA)
+        0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, // sid (SY)
+        0x12, 0x00, 0x00, 0x00, // ..
+        0x00, 0x00, 0x18, 0x00, // ace type (allowed), flags, size
+        0xa9, 0x00, 0x12, 0x00, // mask
+        0x01, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, // sid (BU)
+        0x20, 0x00, 0x00, 0x00, // ..
+        0x21, 0x02, 0x00, 0x00, // ..
+    ];
+
+    let descriptor = SecurityDescriptor::from_bytes(&data[..]).unwrap();
+    assert_eq!(
+        descriptor,
+        SecurityDescriptor {
+            control: SecurityDescriptorControl::SELF_RELATIVE
+                | SecurityDescriptorControl::DACL_PROTECTED
+                | SecurityDescriptorControl::DACL_AUTO_INHERITED
+                | SecurityDescriptorControl::DACL_PRESENT,
+            owner: Some(Sid::new(5, &[32, 544])),
+            group: Some(Sid::new(5, &[18])),
+            sacl: None,
+            dacl: Some(Acl::new(vec![
+                Ace {
+                    ace_type: AceType::AccessAllowed,
+                    flags: AceFlags::OBJECT_INHERIT | AceFlags::CONTAINER_INHERIT,
+                    mask: AccessMask::from_bits_retain(0x1f01ff),
+                    sid: Sid::new(5, &[18]),
+                    padding: vec![],
+                }
+                .into(),
+                Ace {
+                    ace_type: AceType::AccessAllowed,
+                    flags: AceFlags::empty(),
+                    mask: AccessMask::from_bits_retain(0x1200a9),
+                    sid: Sid::new(5, &[32, 545]),
+                    padding: vec![],
+                }
+                .into(),
+            ])),
+        }
+    );
+    assert_eq!(
+        descriptor.to_sddl(),
+        "O:BAG:SYD:PAI(A;OICI;FA;;;SY)(A;;0x1200a9;;;BU)"
+    );
+    assert_bytes_equal(&data, &descriptor.to_bytes());
+}
+
+#[test]
+fn security_descriptor_with_padded_ace() {
+    let data = [
+        0x01, 0x00, 0x04, 0x80, // revision, sbz1, control
+        0x00, 0x00, 0x00, 0x00, // owner offset
+        0x00, 0x00, 0x00, 0x00, // group offset
+        0x00, 0x00, 0x00, 0x00, // sacl offset
+        0x14, 0x00, 0x00, 0x00, // dacl offset
+        0x02, 0x00, 0x20, 0x00, 0x01, 0x00, 0x00,
+        0x00, // revision, sbz1, acl size, ace count, sbz2
+        0x00, 0x00, 0x18, 0x00, // ace type (allowed), flags, size
+        0xff, 0x01, 0x1f, 0x00, // mask (FA)
+        0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, // sid (WD)
+        0x00, 0x00, 0x00, 0x00, // ..
+        0xaa, 0xbb, 0xcc, 0xdd, // padding
+    ];
+
+    let descriptor = SecurityDescriptor::from_bytes(&data[..]).unwrap();
+    let Some(AclEntry::Ace(ace)) = descriptor.dacl.as_ref().and_then(|a| a.aces.first()) else {
+        panic!("expected an ACE");
+    };
+    assert_eq!(ace.sid, Sid::new(1, &[0]));
+    assert_eq!(ace.padding, [0xaa, 0xbb, 0xcc, 0xdd]);
+    assert_bytes_equal(&data, &descriptor.to_bytes());
+}
+
+#[test]
+fn file_fs_volume_information() {
+    let data = [
+        0x49, 0xee, 0xa5, 0x14, 0x8c, 0xfb, 0xd9, 0x01, // volume creation time
+        0x78, 0x56, 0x34, 0x12, // volume serial number
+        0x0a, 0x00, 0x00, 0x00, // volume label length
+        0x00, 0x00, // supports objects, reserved
+        0x66, 0x00, 0x69, 0x00, 0x6c, 0x00, 0x65, 0x00, // volume label
+        0x73, 0x00, // ..
+    ];
+
+    let info: FileFsVolumeInformation = serde_smb::from_slice(&data[..]).unwrap();
+    assert_eq!(
+        info,
+        FileFsVolumeInformation {
+            volume_creation_time: Time {
+                intervals: 0x01d9fb8c14a5ee49
+            },
+            volume_serial_number: 0x12345678,
+            supports_objects: false,
+            volume_label: "files".into(),
+        }
+    );
+    assert_bytes_equal(&data, &serde_smb::to_vec(&info).unwrap());
+}
+
+#[test]
+fn file_fs_attribute_information() {
+    let data = [
+        0x4f, 0x00, 0x04, 0x00, // file system attributes
+        0xff, 0x00, 0x00, 0x00, // maximum component name length
+        0x08, 0x00, 0x00, 0x00, // file system name length
+        0x4e, 0x00, 0x54, 0x00, 0x46, 0x00, 0x53, 0x00, // file system name
+    ];
+
+    let info: FileFsAttributeInformation = serde_smb::from_slice(&data[..]).unwrap();
+    assert_eq!(
+        info,
+        FileFsAttributeInformation {
+            file_system_attributes: FileSystemAttributes::CASE_SENSITIVE_SEARCH
+                | FileSystemAttributes::CASE_PRESERVED_NAMES
+                | FileSystemAttributes::UNICODE_ON_DISK
+                | FileSystemAttributes::PERSISTENT_ACLS
+                | FileSystemAttributes::SUPPORTS_SPARSE_FILES
+                | FileSystemAttributes::NAMED_STREAMS,
+            maximum_component_name_length: 255,
+            file_system_name: "NTFS".into(),
+        }
+    );
+    assert_bytes_equal(&data, &serde_smb::to_vec(&info).unwrap());
+}
+
+#[test]
+fn file_fs_attribute_information_unknown_bits() {
+    let data = [
+        0x07, 0x04, 0x00, 0x80, // file system attributes
+        0xff, 0x00, 0x00, 0x00, // maximum component name length
+        0x08, 0x00, 0x00, 0x00, // file system name length
+        0x65, 0x00, 0x78, 0x00, 0x74, 0x00, 0x34, 0x00, // file system name
+    ];
+
+    let info: FileFsAttributeInformation = serde_smb::from_slice(&data[..]).unwrap();
+    assert!(info
+        .file_system_attributes
+        .contains(FileSystemAttributes::SUPPORTS_POSIX_UNLINK_RENAME));
+    assert_eq!(info.file_system_attributes.bits(), 0x80000407);
+    assert_bytes_equal(&data, &serde_smb::to_vec(&info).unwrap());
+}
+
+#[test]
+fn file_full_ea_information() {
+    let data = [
+        0x14, 0x00, 0x00, 0x00, // next entry offset
+        0x00, 0x06, 0x03, 0x00, // flags, ea name length, ea value length
+        0x75, 0x73, 0x65, 0x72, 0x2e, 0x61, 0x00, // ea name
+        0x78, 0x79, 0x7a, // ea value
+        0x00, 0x00, // padding
+        0x00, 0x00, 0x00, 0x00, // next entry offset
+        0x80, 0x01, 0x02, 0x00, // flags, ea name length, ea value length
+        0x62, 0x00, // ea name
+        0x31, 0x32, // ea value
+    ];
+
+    let eas: Vec<FileFullEaInformation> = serde_smb::from_slice(&data[..]).unwrap();
+    assert_eq!(
+        eas,
+        vec![
+            FileFullEaInformation::new("user.a", "xyz"),
+            FileFullEaInformation {
+                flags: EaFlags::NEED_EA,
+                ea_name: b"b".to_vec(),
+                ea_value: b"12".to_vec(),
+            },
+        ]
+    );
+    assert_eq!(eas[0].name(), "user.a");
+    assert_bytes_equal(&data, &serde_smb::to_vec(&eas).unwrap());
+
+    // The same chain as the answer to a query, sized by the response rather than an entry count
+    let header = ResponseHeader {
+        protocol_id: ProtocolId::new(),
+        header_length: 64,
+        credit_charge: Credits(1),
+        nt_status: NtStatus::Success,
+        command: Command::QueryInfo,
+        credits_granted: Credits(1),
+        flags: HeaderFlags::new().with_response(true),
+        chain_offset: 0,
+        message_id: MessageId(12),
+        process_id: ProcessId(0xfeff),
+        tree_id: TreeId(1),
+        session_id: SessionId(0x13abe4e9),
+        signature: Signature([0; 16]),
+    };
+    let response = QueryInfoResponse { info: eas };
+    let bytes = serde_smb::to_vec(&(&header, &response)).unwrap();
+    assert_bytes_equal(&data, &bytes[72..]);
+    let deserialized: (
+        ResponseHeader,
+        QueryInfoResponse<Vec<FileFullEaInformation>>,
+    ) = serde_smb::from_slice(&bytes[..]).unwrap();
+    assert_eq!(deserialized, (header, response));
+}
+
+#[test]
+fn extended_attributes_create_context() {
+    let data = [
+        0x00, 0x00, 0x00, 0x00, // next entry offset
+        0x10, 0x00, 0x04, 0x00, // name offset, name length
+        0x00, 0x00, 0x18, 0x00, // reserved, data offset
+        0x12, 0x00, 0x00, 0x00, // data length
+        0x45, 0x78, 0x74, 0x41, // name
+        0x00, 0x00, 0x00, 0x00, // padding
+        0x00, 0x00, 0x00, 0x00, // next entry offset
+        0x00, 0x06, 0x03, 0x00, // flags, ea name length, ea value length
+        0x75, 0x73, 0x65, 0x72, 0x2e, 0x61, 0x00, // ea name
+        0x78, 0x79, 0x7a, // ea value
+    ];
+
+    let contexts: Vec<CreateContextEntry> = serde_smb::from_slice(&data[..]).unwrap();
+    assert_eq!(
+        contexts,
+        vec![
+            CreateContext::ExtendedAttributes(vec![FileFullEaInformation::new("user.a", "xyz")])
+                .into()
+        ]
+    );
+    assert_bytes_equal(&data, &serde_smb::to_vec(&contexts).unwrap());
+}
+
+#[test]
+fn file_stream_information() {
+    let data = [
+        0x28, 0x00, 0x00, 0x00, // next entry offset
+        0x0e, 0x00, 0x00, 0x00, // stream name length
+        0x0a, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // stream size
+        0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // stream allocation size
+        0x3a, 0x00, 0x3a, 0x00, 0x24, 0x00, 0x44, 0x00, // stream name
+        0x41, 0x00, 0x54, 0x00, 0x41, 0x00, // ..
+        0x00, 0x00, // padding
+        0x00, 0x00, 0x00, 0x00, // next entry offset
+        0x2c, 0x00, 0x00, 0x00, // stream name length
+        0x1a, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // stream size
+        0x20, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // stream allocation size
+        0x3a, 0x00, 0x5a, 0x00, 0x6f, 0x00, 0x6e, 0x00, // stream name
+        0x65, 0x00, 0x2e, 0x00, 0x49, 0x00, 0x64, 0x00, // ..
+        0x65, 0x00, 0x6e, 0x00, 0x74, 0x00, 0x69, 0x00, // ..
+        0x66, 0x00, 0x69, 0x00, 0x65, 0x00, 0x72, 0x00, // ..
+        0x3a, 0x00, 0x24, 0x00, 0x44, 0x00, 0x41, 0x00, // ..
+        0x54, 0x00, 0x41, 0x00, // ..
+    ];
+
+    let streams: Vec<FileStreamInformation> = serde_smb::from_slice(&data[..]).unwrap();
+    assert_eq!(
+        streams,
+        vec![
+            FileStreamInformation {
+                stream_size: 10,
+                stream_allocation_size: 16,
+                stream_name: "::$DATA".into(),
+            },
+            FileStreamInformation {
+                stream_size: 26,
+                stream_allocation_size: 32,
+                stream_name: ":Zone.Identifier:$DATA".into(),
+            },
+        ]
+    );
+    assert!(streams[0].is_default());
+    assert_eq!(streams[1].name(), "Zone.Identifier");
+    assert_bytes_equal(&data, &serde_smb::to_vec(&streams).unwrap());
+}
+
+#[test]
+fn file_links_information() {
+    let data = [
+        0x50, 0x00, 0x00, 0x00, // bytes needed
+        0x02, 0x00, 0x00, 0x00, // entries returned
+        0x28, 0x00, 0x00, 0x00, // next entry offset
+        0x00, 0x00, 0x00, 0x00, // padding
+        0x2a, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // parent file id
+        0x07, 0x00, 0x00, 0x00, // file name length
+        0x61, 0x00, 0x5f, 0x00, 0x66, 0x00, 0x69, 0x00, // file name
+        0x6c, 0x00, 0x65, 0x00, 0x31, 0x00, // ..
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // padding
+        0x00, 0x00, 0x00, 0x00, // next entry offset
+        0x00, 0x00, 0x00, 0x00, // padding
+        0x2b, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // parent file id
+        0x06, 0x00, 0x00, 0x00, // file name length
+        0x62, 0x00, 0x5f, 0x00, 0x6c, 0x00, 0x69, 0x00, // file name
+        0x6e, 0x00, 0x6b, 0x00, // ..
+    ];
+
+    let info = FileLinksInformation::from_bytes(&data).unwrap();
+    assert_eq!(
+        info,
+        FileLinksInformation {
+            bytes_needed: 80,
+            entries: vec![
+                FileLinkEntryInformation {
+                    parent_file_id: 0x2a,
+                    file_name: "a_file1".into(),
+                },
+                FileLinkEntryInformation {
+                    parent_file_id: 0x2b,
+                    file_name: "b_link".into(),
+                },
+            ],
+        }
+    );
+    assert_bytes_equal(&data, &info.to_bytes());
+}
+
+#[test]
+fn file_link_information() {
+    let data = [
+        0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // replace if exists, reserved
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // root directory
+        0x0a, 0x00, 0x00, 0x00, // file name length
+        0x61, 0x00, 0x5c, 0x00, 0x62, 0x00, 0x5f, 0x00, // file name
+        0x6c, 0x00, // ..
+    ];
+
+    let info: FileLinkInformation = serde_smb::from_slice(&data[..]).unwrap();
+    assert_eq!(
+        info,
+        FileLinkInformation {
+            replace_if_exists: true,
+            path: "a\\b_l".into(),
+        }
+    );
+    assert_bytes_equal(&data, &serde_smb::to_vec(&info).unwrap());
+}
+
+#[test]
+fn file_rename_information_ex() {
+    let data = [
+        0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // flags, reserved
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // root directory
+        0x06, 0x00, 0x00, 0x00, // file name length
+        0x61, 0x00, 0x5c, 0x00, 0x62, 0x00, // file name
+    ];
+
+    let info: FileRenameInformationEx = serde_smb::from_slice(&data[..]).unwrap();
+    assert_eq!(
+        info,
+        FileRenameInformationEx {
+            flags: RenameFlags::REPLACE_IF_EXISTS | RenameFlags::POSIX_SEMANTICS,
+            path: "a\\b".into(),
+        }
+    );
+    assert_bytes_equal(&data, &serde_smb::to_vec(&info).unwrap());
+
+    let info = FileDispositionInformationEx {
+        flags: DispositionFlags::DELETE | DispositionFlags::POSIX_SEMANTICS,
+    };
+    assert_bytes_equal(
+        &[0x03, 0x00, 0x00, 0x00],
+        &serde_smb::to_vec(&info).unwrap(),
+    );
+}
+
+#[test]
+fn file_names_information() {
+    let data = [
+        0x10, 0x00, 0x00, 0x00, // next entry offset
+        0x00, 0x00, 0x00, 0x00, // file index
+        0x02, 0x00, 0x00, 0x00, // file name length
+        0x61, 0x00, // file name
+        0x00, 0x00, // padding
+        0x00, 0x00, 0x00, 0x00, // next entry offset
+        0x00, 0x00, 0x00, 0x00, // file index
+        0x04, 0x00, 0x00, 0x00, // file name length
+        0x62, 0x00, 0x63, 0x00, // file name
+    ];
+
+    let entries: Vec<QueryDirectoryEntry<FileNamesInformation>> =
+        serde_smb::from_slice(&data[..]).unwrap();
+    assert_eq!(
+        entries,
+        vec![
+            FileNamesInformation {
+                file_index: 0,
+                file_name: "a".into(),
+            }
+            .into(),
+            FileNamesInformation {
+                file_index: 0,
+                file_name: "bc".into(),
+            }
+            .into(),
+        ]
+    );
+    assert_bytes_equal(&data, &serde_smb::to_vec(&entries).unwrap());
+}
+
+#[test]
+fn file_id_extd_directory_information() {
+    let data = [
+        0x00, 0x00, 0x00, 0x00, // next entry offset
+        0x00, 0x00, 0x00, 0x00, // file index
+        0x49, 0xee, 0xa5, 0x14, 0x8c, 0xfb, 0xd9, 0x01, // creation time
+        0x49, 0xee, 0xa5, 0x14, 0x8c, 0xfb, 0xd9, 0x01, // last access time
+        0x49, 0xee, 0xa5, 0x14, 0x8c, 0xfb, 0xd9, 0x01, // last write time
+        0x49, 0xee, 0xa5, 0x14, 0x8c, 0xfb, 0xd9, 0x01, // change time
+        0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // end of file
+        0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // allocation size
+        0x20, 0x04, 0x00, 0x00, // file attributes
+        0x06, 0x00, 0x00, 0x00, // file name length
+        0x00, 0x00, 0x00, 0x00, // ea size
+        0x0c, 0x00, 0x00, 0xa0, // reparse point tag
+        0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, // file id
+        0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f, 0x10, // ..
+        0x6c, 0x00, 0x6e, 0x00, 0x6b, 0x00, // file name
+    ];
+
+    let time = Time {
+        intervals: 0x01d9fb8c14a5ee49,
+    };
+    let entries: Vec<QueryDirectoryEntry<FileIdExtdDirectoryInformation>> =
+        serde_smb::from_slice(&data[..]).unwrap();
+    assert_eq!(
+        entries,
+        vec![FileIdExtdDirectoryInformation {
+            file_index: 0,
+            creation_time: time.clone(),
+            last_access_time: time.clone(),
+            last_write_time: time.clone(),
+            change_time: time,
+            end_of_file: 5,
+            allocation_size: 8,
+            file_attributes: FileAttributes::ARCHIVE | FileAttributes::REPARSE_POINT,
+            ea_size: 0,
+            reparse_point_tag: ReparseTag::SYMLINK,
+            file_id: [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16],
+            file_name: "lnk".into(),
+        }
+        .into()]
+    );
+    assert_bytes_equal(&data, &serde_smb::to_vec(&entries).unwrap());
+}
+
+#[test]
+fn file_network_open_information() {
+    let data = [
+        0x49, 0xee, 0xa5, 0x14, 0x8c, 0xfb, 0xd9, 0x01, // creation time
+        0x4a, 0xee, 0xa5, 0x14, 0x8c, 0xfb, 0xd9, 0x01, // last access time
+        0x4b, 0xee, 0xa5, 0x14, 0x8c, 0xfb, 0xd9, 0x01, // last write time
+        0x4c, 0xee, 0xa5, 0x14, 0x8c, 0xfb, 0xd9, 0x01, // change time
+        0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // allocation size
+        0x0b, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // end of file
+        0x20, 0x00, 0x00, 0x00, // file attributes
+        0x00, 0x00, 0x00, 0x00, // reserved
+    ];
+
+    let info: FileNetworkOpenInformation = serde_smb::from_slice(&data[..]).unwrap();
+    assert_eq!(
+        info,
+        FileNetworkOpenInformation {
+            creation_time: Time {
+                intervals: 0x01d9fb8c14a5ee49
+            },
+            last_access_time: Time {
+                intervals: 0x01d9fb8c14a5ee4a
+            },
+            last_write_time: Time {
+                intervals: 0x01d9fb8c14a5ee4b
+            },
+            change_time: Time {
+                intervals: 0x01d9fb8c14a5ee4c
+            },
+            allocation_size: 4096,
+            end_of_file: 11,
+            file_attributes: FileAttributes::ARCHIVE,
+        }
+    );
+    assert_bytes_equal(&data, &serde_smb::to_vec(&info).unwrap());
+}
+
+#[test]
+fn cancel_request() {
+    let (process_id, tree_id) = AsyncId(0x1_0000_0008).to_ids();
+    let header = RequestHeader {
+        protocol_id: ProtocolId::new(),
+        header_length: 64,
+        credit_charge: Credits(0),
+        channel_sequence: 0,
+        command: Command::Cancel,
+        credits_requested: Credits(0),
+        flags: HeaderFlags::new().with_async(true).with_signing(true),
+        chain_offset: 0,
+        message_id: MessageId(5),
+        process_id,
+        tree_id,
+        session_id: SessionId(0x1234),
+        signature: Signature([0; 16]),
+    };
+
+    let actual = serde_smb::to_vec(&(&header, &CancelRequest)).unwrap();
+
+    let expected = [
+        0xfe, 0x53, 0x4d, 0x42, // protocol id
+        0x40, 0x00, // header length
+        0x00, 0x00, // credit charge
+        0x00, 0x00, 0x00, 0x00, // channel sequence
+        0x0c, 0x00, // command
+        0x00, 0x00, // credits requested
+        0x0a, 0x00, 0x00, 0x00, // flags (async, signed)
+        0x00, 0x00, 0x00, 0x00, // chain offset
+        0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // message id
+        0x08, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, // async id
+        0x34, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // session id
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // signature
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // ..
+        0x04, 0x00, // structure size
+        0x00, 0x00, // reserved
+    ];
+    assert_bytes_equal(&expected, &actual);
+
+    let deserialized: (RequestHeader, CancelRequest) =
+        serde_smb::from_slice(&expected[..]).unwrap();
+    assert_eq!(deserialized, (header, CancelRequest));
+    assert_eq!(
+        AsyncId::from_ids(deserialized.0.process_id, deserialized.0.tree_id),
+        AsyncId(0x1_0000_0008)
+    );
+}
@@ -0,0 +1,186 @@
+use super::{path_str, Client, Error, OpenOptions, Result, Transport};
+use crate::trace::trace;
+use serde::{de::DeserializeOwned, Serialize};
+use smb3::{
+    AccessMask, CloseFlags, CloseRequest, CloseResponse, Command, CreateRequest, CreateResponse,
+    Credits, ErrorContext, FileId, FileRenameInformation, HasCommand, HasFileInformationClass,
+    InfoType, NtStatus, QueryInfoFlags, QueryInfoRequest, QueryInfoResponse, RequestHeader,
+    ResponseHeader, SetInfoRequest, SetInfoResponse,
+};
+use std::fmt;
+use std::path::Path;
+
+type SerializeFn = Box<dyn FnOnce(&RequestHeader) -> serde_smb::Result<Vec<u8>>>;
+
+/// Requests sent to the server in one message. Each after the first is related to the one before,
+/// so it can use `FileId::LAST` for the file that one opened.
+pub(crate) struct Compound {
+    pub(crate) requests: Vec<(Command, SerializeFn)>,
+}
+
+impl Compound {
+    pub(crate) fn new() -> Self {
+        Self { requests: vec![] }
+    }
+
+    pub(crate) fn push<T: Serialize + HasCommand + fmt::Debug + 'static>(&mut self, request: T) {
+        self.requests.push((
+            T::command(),
+            Box::new(move |header| {
+                trace!(target: crate::trace::MESSAGES, ?header, ?request, "request");
+                serde_smb::to_vec(&(header, request))
+            }),
+        ));
+    }
+}
+
+/// Separates a failure to talk to the server, the outer error, from the server failing the
+/// request, the inner one.
+pub(crate) fn request_result<R>(result: Result<(ResponseHeader, R)>) -> Result<Result<R>> {
+    match result {
+        Ok((_, response)) => Ok(Ok(response)),
+        Err(e @ (Error::NtStatus(_) | Error::Request(_))) => Ok(Err(e)),
+        Err(e) => Err(e),
+    }
+}
+
+/// Whether opening failed in a way only following a DFS referral or symbolic link gets past,
+/// which takes more than one round trip.
+fn needs_resolving(error: &Error) -> bool {
+    error.nt_status() == Some(NtStatus::PathNotCovered)
+        || error
+            .error_contexts()
+            .iter()
+            .any(|c| matches!(c, ErrorContext::SymbolicLink(_)))
+}
+
+impl<TransportT: Transport> Client<TransportT> {
+    /// Opens a file with `create`, sends `request(file_id)` for it and closes it again. When the
+    /// path doesn't need resolving this is done with one compound request, in one round trip.
+    async fn open_request_close<T, R>(
+        &mut self,
+        create: CreateRequest,
+        request: impl Fn(FileId) -> T,
+    ) -> Result<R>
+    where
+        T: Serialize + HasCommand + fmt::Debug + 'static,
+        R: DeserializeOwned + fmt::Debug,
+    {
+        let tree = self.tree;
+        if self.dfs_path(tree, &create.name).is_none() {
+            let mut compound = Compound::new();
+            compound.push(create.clone());
+            compound.push(request(FileId::LAST));
+            compound.push(CloseRequest {
+                flags: CloseFlags::empty(),
+                file_id: FileId::LAST,
+            });
+            let connection = self.connection(tree);
+            let message_ids = connection
+                .send_compound(tree.tree_id, Credits(64), compound)
+                .await?;
+            let created: Result<CreateResponse> =
+                request_result(connection.receive(message_ids[0]).await)?;
+            let response: Result<R> = request_result(connection.receive(message_ids[1]).await)?;
+            let closed: Result<CloseResponse> =
+                request_result(connection.receive(message_ids[2]).await)?;
+            match created {
+                Ok(created) => {
+                    // Servers may skip the close if the request before it failed
+                    if closed.is_err() {
+                        self.close_on(tree, created.file_id).await?;
+                    }
+                    return response.map_err(|e| e.with_path(&create.name));
+                }
+                Err(e) if needs_resolving(&e) => {}
+                Err(e) => return Err(e.with_path(&create.name)),
+            }
+        }
+
+        let (_, name, created) = self.create_resolved(create).await?;
+        let (tree, file_id) = self.route(created.file_id);
+        let response = self
+            .connection(tree)
+            .request(
+                Some(tree.tree_id),
+                Credits(1),
+                Credits(64),
+                request(file_id),
+            )
+            .await;
+        self.close(created.file_id).await?;
+        Ok(response.map_err(|e| e.with_path(&name))?.1)
+    }
+
+    /// Queries `Info` of the file or directory at `path`, opening and closing it along the way
+    /// in one round trip.
+    pub async fn query_info_path<Info: DeserializeOwned + fmt::Debug + HasFileInformationClass>(
+        &mut self,
+        path: impl AsRef<Path>,
+    ) -> Result<Info> {
+        let options = OpenOptions::new();
+        let create = options.to_create_request(&path)?;
+        let result: Result<QueryInfoResponse<Info>> = self
+            .open_request_close(create, |file_id| QueryInfoRequest {
+                info_type: InfoType::File,
+                file_info_class: Info::file_information_class(),
+                output_buffer_length: 8293,
+                additional_information: 0,
+                flags: QueryInfoFlags::empty(),
+                file_id,
+                buffer: vec![],
+            })
+            .await;
+        match result {
+            Ok(response) => Ok(response.info),
+            // Growing the buffer takes another request, on a file which is still open
+            Err(e) if matches!(e.error_contexts(), [ErrorContext::BufferTooSmall(_)]) => {
+                let file_id = options.open_file_id(self, path).await?;
+                let info = self.query_info(file_id).await;
+                self.close(file_id).await?;
+                info
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Sets `info` on the file or directory at `path`, opened with `desired_access`, in one
+    /// round trip.
+    async fn set_info_path<
+        Info: Serialize + fmt::Debug + HasFileInformationClass + Clone + 'static,
+    >(
+        &mut self,
+        path: impl AsRef<Path>,
+        desired_access: AccessMask,
+        info: Info,
+    ) -> Result<()> {
+        let create = OpenOptions::new()
+            .access(desired_access)
+            .to_create_request(path)?;
+        let _response: SetInfoResponse = self
+            .open_request_close(create, |file_id| SetInfoRequest {
+                info_type: InfoType::File,
+                file_info_class: Info::file_information_class(),
+                additional_information: 0,
+                file_id,
+                info: info.clone(),
+            })
+            .await?;
+        Ok(())
+    }
+
+    /// Renames the file or directory at `from` to `to` in one round trip, replacing what is at
+    /// `to` if `replace` is set.
+    pub async fn rename_path(
+        &mut self,
+        from: impl AsRef<Path>,
+        to: impl AsRef<Path>,
+        replace: bool,
+    ) -> Result<()> {
+        let info = FileRenameInformation {
+            replace_if_exists: replace,
+            path: path_str(to)?,
+        };
+        self.set_info_path(from, AccessMask::DELETE, info).await
+    }
+}
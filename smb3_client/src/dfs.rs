@@ -0,0 +1,154 @@
+//! Following DFS referrals, see MS-DFSC.
+
+use smb3::{DfsReferralResponse, DfsReferralTarget};
+use std::future::Future;
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+use tokio::io;
+
+/// Opens transports to other servers, needed when a DFS referral points somewhere other than
+/// the server the client is connected to.
+pub trait Connector<TransportT> {
+    fn connect<'a>(
+        &'a mut self,
+        server: &'a str,
+    ) -> Pin<Box<dyn Future<Output = io::Result<TransportT>> + 'a>>;
+}
+
+impl<TransportT, F, Fut> Connector<TransportT> for F
+where
+    F: FnMut(&str) -> Fut,
+    Fut: Future<Output = io::Result<TransportT>> + 'static,
+{
+    fn connect<'a>(
+        &'a mut self,
+        server: &'a str,
+    ) -> Pin<Box<dyn Future<Output = io::Result<TransportT>> + 'a>> {
+        Box::pin(self(server))
+    }
+}
+
+struct CacheEntry {
+    dfs_path: String,
+    targets: Vec<String>,
+    expires: Instant,
+}
+
+/// Remembers which DFS paths are served where, for as long as the server allows.
+#[derive(Default)]
+pub struct ReferralCache {
+    entries: Vec<CacheEntry>,
+}
+
+/// Returns the rest of `path` if it is `prefix` or below it, ignoring case.
+fn strip_path_prefix<'a>(path: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = path.get(..prefix.len())?;
+    if !head.eq_ignore_ascii_case(prefix) {
+        return None;
+    }
+    let rest = &path[prefix.len()..];
+    (rest.is_empty() || rest.starts_with('\\')).then_some(rest)
+}
+
+/// DFS paths are sent with one leading backslash, but servers sometimes return two.
+fn normalize(path: &str) -> String {
+    format!("\\{}", path.trim_start_matches('\\').trim_end_matches('\\'))
+}
+
+/// The part of `path` a referral covers, given as a count of UTF-16 bytes like the server's
+/// PathConsumed.
+fn consumed_prefix(path: &str, path_consumed: u16) -> Option<&str> {
+    let path_consumed = usize::from(path_consumed);
+    let mut consumed = 0;
+    for (i, c) in path.char_indices() {
+        if consumed == path_consumed {
+            return Some(&path[..i]);
+        }
+        consumed += c.len_utf16() * 2;
+    }
+    (consumed == path_consumed).then_some(path)
+}
+
+impl ReferralCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the targets from `response` to a referral request for `path`, replacing any previous
+    /// entries for the same DFS paths. Entries with a time to live of zero can still be resolved
+    /// with the same `now`.
+    pub fn insert(&mut self, path: &str, response: &DfsReferralResponse, now: Instant) {
+        // The referrals cover as much of the requested path as the server says they do, falling
+        // back to the DFS path of each entry when that doesn't make sense.
+        let consumed = consumed_prefix(path, response.path_consumed)
+            .map(normalize)
+            .filter(|p| p.len() > 1);
+        let mut fresh: Vec<CacheEntry> = vec![];
+        for entry in &response.entries {
+            let DfsReferralTarget::Path {
+                dfs_path,
+                network_address,
+                ..
+            } = &entry.target
+            else {
+                continue;
+            };
+            let dfs_path = consumed.clone().unwrap_or_else(|| normalize(dfs_path));
+            let target = normalize(network_address);
+            let expires = now + Duration::from_secs(entry.time_to_live.into());
+            match fresh
+                .iter_mut()
+                .find(|e| e.dfs_path.eq_ignore_ascii_case(&dfs_path))
+            {
+                Some(e) => {
+                    e.targets.push(target);
+                    e.expires = e.expires.min(expires);
+                }
+                None => fresh.push(CacheEntry {
+                    dfs_path,
+                    targets: vec![target],
+                    expires,
+                }),
+            }
+        }
+
+        for entry in fresh {
+            self.entries
+                .retain(|e| !e.dfs_path.eq_ignore_ascii_case(&entry.dfs_path));
+            self.entries.push(entry);
+        }
+    }
+
+    /// Rewrites `path` (like `\server\share\dir\file`) for each target of the longest cached DFS
+    /// path covering it, in the order the server listed them.
+    pub fn resolve(&self, path: &str, now: Instant) -> Vec<String> {
+        let path = normalize(path);
+        let best = self
+            .entries
+            .iter()
+            .filter(|e| e.expires >= now)
+            .filter_map(|e| Some((e, strip_path_prefix(&path, &e.dfs_path)?)))
+            .max_by_key(|(e, _)| e.dfs_path.len());
+        let Some((entry, rest)) = best else {
+            return vec![];
+        };
+        entry
+            .targets
+            .iter()
+            .map(|target| format!("{target}{rest}"))
+            .collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+/// Splits a path like `\server\share\dir\file` into the server, share and the path within the
+/// share.
+pub(crate) fn split_path(path: &str) -> Option<(&str, &str, &str)> {
+    let mut parts = path.trim_start_matches('\\').splitn(3, '\\');
+    let server = parts.next().filter(|s| !s.is_empty())?;
+    let share = parts.next().filter(|s| !s.is_empty())?;
+    Some((server, share, parts.next().unwrap_or("")))
+}
@@ -0,0 +1,144 @@
+use super::{Client, Result, Transport, IO_SIZE};
+use serde::de::DeserializeOwned;
+use smb3::{
+    Credits, FileId, HasFileInformationClass, NtStatus, QueryDirectoryFlags, QueryDirectoryRequest,
+    QueryDirectoryResponse,
+};
+use std::collections::VecDeque;
+use std::fmt;
+
+/// How to list a directory with [`Client::query_directory_entries`].
+#[derive(Clone, Debug)]
+pub struct QueryDirectoryOptions {
+    pattern: String,
+    flags: QueryDirectoryFlags,
+    file_index: u32,
+    output_buffer_length: u32,
+}
+
+impl Default for QueryDirectoryOptions {
+    fn default() -> Self {
+        Self {
+            pattern: "*".into(),
+            flags: QueryDirectoryFlags::empty(),
+            file_index: 0,
+            output_buffer_length: IO_SIZE as u32,
+        }
+    }
+}
+
+impl QueryDirectoryOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only list names matching `pattern`, which may use the `*` and `?` wildcards. The default
+    /// is `*`.
+    pub fn pattern(mut self, pattern: impl Into<String>) -> Self {
+        self.pattern = pattern.into();
+        self
+    }
+
+    /// Flags for the first request. Later requests continue the same listing, so only
+    /// `RETURN_SINGLE_ENTRY` is kept for them.
+    pub fn flags(mut self, flags: QueryDirectoryFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Resume after the entry with this index, sets `INDEX_SPECIFIED`.
+    pub fn file_index(mut self, file_index: u32) -> Self {
+        self.file_index = file_index;
+        self.flags |= QueryDirectoryFlags::INDEX_SPECIFIED;
+        self
+    }
+
+    /// How many bytes of entries the server may send back at once.
+    pub fn output_buffer_length(mut self, output_buffer_length: u32) -> Self {
+        self.output_buffer_length = output_buffer_length;
+        self
+    }
+}
+
+/// The entries of a directory, fetched from the server a buffer at a time as they are read.
+pub struct DirectoryEntries<'client, TransportT, Info> {
+    client: &'client mut Client<TransportT>,
+    file_id: FileId,
+    options: QueryDirectoryOptions,
+    entries: VecDeque<Info>,
+    done: bool,
+}
+
+impl<
+        'client,
+        TransportT: Transport,
+        Info: DeserializeOwned + fmt::Debug + HasFileInformationClass,
+    > DirectoryEntries<'client, TransportT, Info>
+{
+    pub(crate) fn new(
+        client: &'client mut Client<TransportT>,
+        file_id: FileId,
+        options: QueryDirectoryOptions,
+    ) -> Self {
+        Self {
+            client,
+            file_id,
+            options,
+            entries: VecDeque::new(),
+            done: false,
+        }
+    }
+
+    /// The next entry, or `None` once the listing is over.
+    pub async fn next_entry(&mut self) -> Result<Option<Info>> {
+        while self.entries.is_empty() && !self.done {
+            self.fetch().await?;
+        }
+        Ok(self.entries.pop_front())
+    }
+
+    async fn fetch(&mut self) -> Result<()> {
+        let (tree, file_id) = self.client.route(self.file_id);
+        let options = &mut self.options;
+        // Each response moves the listing on, so one which may have been lost to a timeout isn't
+        // asked for again
+        let result = self
+            .client
+            .request_retrying(
+                tree,
+                Credits(1),
+                Credits(64),
+                QueryDirectoryRequest {
+                    file_information_class: Info::file_information_class(),
+                    flags: options.flags,
+                    file_index: options.file_index,
+                    file_id,
+                    output_buffer_length: options.output_buffer_length,
+                    search_pattern: options.pattern.clone(),
+                },
+                false,
+            )
+            .await;
+        options.flags &= QueryDirectoryFlags::RETURN_SINGLE_ENTRY;
+        options.file_index = 0;
+
+        match result {
+            Ok((_, response)) => {
+                let response: QueryDirectoryResponse<Info> = response;
+                self.entries
+                    .extend(response.entries.into_iter().map(|e| e.body));
+            }
+            // Nothing matching the pattern at all is reported differently
+            Err(e)
+                if matches!(
+                    e.nt_status(),
+                    Some(NtStatus::NoMoreFiles | NtStatus::NoSuchFile)
+                ) =>
+            {
+                self.done = true
+            }
+            Err(e) => return Err(e),
+        }
+        Ok(())
+    }
+}
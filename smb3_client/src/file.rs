@@ -0,0 +1,297 @@
+use super::op::OpState;
+use super::{Client, DeferredCloses, Result, Transport, Tree, IO_SIZE};
+use smb3::{FileId, FileStandardInformation, NtStatus};
+use std::fmt;
+use std::future::poll_fn;
+use std::io::SeekFrom;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use tokio::io::{self, AsyncRead, AsyncSeek, AsyncWrite, ReadBuf};
+
+enum OpResult {
+    Read(Result<Vec<u8>>),
+    Write(Result<u32>),
+    Size(Result<u64>),
+    Flush(Result<()>),
+}
+
+/// An open file with a position, read and written through [`AsyncRead`], [`AsyncWrite`] and
+/// [`AsyncSeek`].
+///
+/// It is closed by [`File::close`], or when it is dropped along with the next request the client
+/// sends.
+pub struct File<'client, TransportT: Transport> {
+    tree: Tree,
+    file_id: FileId,
+    state: OpState<'client, TransportT, OpResult>,
+    /// Where to put the file to close it when dropped, even while the client is lent out
+    deferred_closes: DeferredCloses,
+    /// Data read from the server starting at `position`, not yet returned
+    read_buffer: Vec<u8>,
+    position: u64,
+    /// A seek which still has to happen, `SeekFrom::Current` is turned into `SeekFrom::Start`
+    seek: Option<SeekFrom>,
+    /// Writes go to the end of the file
+    append: bool,
+    closed: bool,
+}
+
+impl<'client, TransportT: Transport> fmt::Debug for File<'client, TransportT> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("File")
+            .field("tree", &self.tree)
+            .field("file_id", &self.file_id)
+            .field("position", &self.position)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'client, TransportT: Transport> File<'client, TransportT> {
+    pub(crate) fn new(client: &'client mut Client<TransportT>, file_id: FileId) -> Self {
+        let (tree, file_id) = client.take_open(file_id);
+        Self {
+            tree,
+            file_id,
+            deferred_closes: client.deferred_closes(tree),
+            state: OpState::Idle(client),
+            read_buffer: vec![],
+            position: 0,
+            seek: None,
+            append: false,
+            closed: false,
+        }
+    }
+
+    /// Makes writes go to the end of the file, for files opened only to append to.
+    pub(crate) fn appending(mut self) -> Self {
+        self.append = true;
+        self
+    }
+
+    pub fn file_id(&self) -> FileId {
+        self.file_id
+    }
+
+    fn start_read(&mut self, count: u32) {
+        let (tree, file_id, offset) = (self.tree, self.file_id, self.position);
+        self.state.start(move |client| {
+            Box::pin(async move {
+                let result = match client.read_on(tree, file_id, offset, count).await {
+                    Ok((_, response)) => Ok(response.data),
+                    Err(e) if e.nt_status() == Some(NtStatus::EndOfFile) => Ok(vec![]),
+                    Err(e) => Err(e),
+                };
+                (client, OpResult::Read(result))
+            })
+        });
+    }
+
+    fn start_write(&mut self, data: Vec<u8>) {
+        let (tree, file_id) = (self.tree, self.file_id);
+        // This offset means the end of the file
+        let offset = if self.append { u64::MAX } else { self.position };
+        self.state.start(move |client| {
+            Box::pin(async move {
+                let result = client.write_on(tree, file_id, offset, data).await;
+                (client, OpResult::Write(result))
+            })
+        });
+    }
+
+    fn start_size(&mut self) {
+        let (tree, file_id) = (self.tree, self.file_id);
+        self.state.start(move |client| {
+            Box::pin(async move {
+                let result = client
+                    .query_info_on::<FileStandardInformation>(tree, file_id)
+                    .await
+                    .map(|info| info.end_of_file as u64);
+                (client, OpResult::Size(result))
+            })
+        });
+    }
+
+    fn start_flush(&mut self) {
+        let (tree, file_id) = (self.tree, self.file_id);
+        self.state.start(move |client| {
+            Box::pin(async move {
+                let result = client.flush_on(tree, file_id).await;
+                (client, OpResult::Flush(result))
+            })
+        });
+    }
+
+    /// Drives any in-progress operation to completion, returning its result. The data of a
+    /// finished read is kept around for the next read and a finished write moves the position.
+    fn poll_op(&mut self, cx: &mut Context<'_>) -> Poll<Option<OpResult>> {
+        let result = ready!(self.state.poll(cx));
+        match &result {
+            Some(OpResult::Read(Ok(data))) => self.read_buffer.extend(data),
+            Some(OpResult::Write(Ok(count))) if !self.append => self.position += *count as u64,
+            _ => {}
+        }
+        Poll::Ready(result)
+    }
+
+    async fn client(&mut self) -> &mut Client<TransportT> {
+        poll_fn(|cx| self.poll_op(cx).map(|_| ())).await;
+        self.state.client().unwrap()
+    }
+
+    /// Moves to `position`, dropping anything read ahead.
+    fn seek_to(&mut self, position: u64) -> u64 {
+        self.read_buffer.clear();
+        self.position = position;
+        position
+    }
+
+    pub async fn close(mut self) -> Result<()> {
+        let (tree, file_id) = (self.tree, self.file_id);
+        self.closed = true;
+        let client = self.client().await;
+        client.close_on(tree, file_id).await?;
+        Ok(())
+    }
+}
+
+fn invalid_seek() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "invalid seek to a negative or overflowing position",
+    )
+}
+
+impl<'client, TransportT: Transport> Drop for File<'client, TransportT> {
+    fn drop(&mut self) {
+        if !self.closed {
+            self.deferred_closes
+                .lock()
+                .unwrap()
+                .push((self.tree.tree_id, self.file_id));
+        }
+    }
+}
+
+impl<'client, TransportT: Transport> AsyncRead for File<'client, TransportT> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if !this.read_buffer.is_empty() {
+                let amount = this.read_buffer.len().min(buf.remaining());
+                buf.put_slice(&this.read_buffer[..amount]);
+                this.read_buffer.drain(..amount);
+                this.position += amount as u64;
+                return Poll::Ready(Ok(()));
+            }
+
+            if this.state.is_idle() {
+                let count = buf.remaining().min(IO_SIZE) as u32;
+                this.start_read(count);
+            }
+            // An operation we didn't start here was abandoned by its caller, its result is
+            // dropped.
+            match ready!(this.poll_op(cx)) {
+                Some(OpResult::Read(Err(e))) => return Poll::Ready(Err(e.into())),
+                Some(OpResult::Read(Ok(data))) if data.is_empty() => {
+                    // End of file
+                    return Poll::Ready(Ok(()));
+                }
+                _ => continue,
+            }
+        }
+    }
+}
+
+impl<'client, TransportT: Transport> AsyncWrite for File<'client, TransportT> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        loop {
+            if this.state.is_idle() {
+                // Anything read ahead is from before the write
+                this.read_buffer.clear();
+                this.start_write(buf[..buf.len().min(IO_SIZE)].to_vec());
+            }
+            match ready!(this.poll_op(cx)) {
+                Some(OpResult::Write(result)) => return Poll::Ready(Ok(result? as usize)),
+                // Something abandoned finished, now we can write.
+                _ => continue,
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if this.state.is_idle() {
+                this.start_flush();
+            }
+            match ready!(this.poll_op(cx)) {
+                Some(OpResult::Flush(result)) => return Poll::Ready(Ok(result?)),
+                _ => continue,
+            }
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_flush(cx)
+    }
+}
+
+impl<'client, TransportT: Transport> AsyncSeek for File<'client, TransportT> {
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        let this = self.get_mut();
+        this.seek = Some(match position {
+            SeekFrom::Current(offset) => SeekFrom::Start(
+                this.position
+                    .checked_add_signed(offset)
+                    .ok_or_else(invalid_seek)?,
+            ),
+            position => position,
+        });
+        Ok(())
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        let this = self.get_mut();
+        loop {
+            let result = ready!(this.poll_op(cx));
+            match (this.seek, result) {
+                (Some(SeekFrom::End(offset)), Some(OpResult::Size(size))) => {
+                    this.seek = None;
+                    let position = size?.checked_add_signed(offset).ok_or_else(invalid_seek)?;
+                    return Poll::Ready(Ok(this.seek_to(position)));
+                }
+                (Some(SeekFrom::End(_)), _) => this.start_size(),
+                (Some(SeekFrom::Start(position)), _) => {
+                    this.seek = None;
+                    return Poll::Ready(Ok(this.seek_to(position)));
+                }
+                _ => return Poll::Ready(Ok(this.position)),
+            }
+        }
+    }
+}
+
+impl<TransportT: Transport> Client<TransportT> {
+    /// Opens the existing file at `path` for reading, positioned at the start. Use
+    /// [`OpenOptions`](crate::OpenOptions) for anything else.
+    pub async fn open(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<File<'_, TransportT>> {
+        crate::OpenOptions::new().read(true).open(self, path).await
+    }
+
+    /// Takes over `file_id`, which the returned `File` closes.
+    pub fn file(&mut self, file_id: FileId) -> File<'_, TransportT> {
+        File::new(self, file_id)
+    }
+}
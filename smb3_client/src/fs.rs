@@ -0,0 +1,246 @@
+use super::{is_unsupported_info_class, Client, OpenOptions, Result, Transport, IO_SIZE};
+use smb3::{
+    AccessMask, FileAllInformation, FileAttributes, FileCreateOptions, FileId,
+    FileIdBothDirectoryInformation, FileNetworkOpenInformation, NtStatus, Time,
+};
+use std::path::Path;
+use std::time::SystemTime;
+
+/// The times, size and attributes of a file, see [`Client::metadata`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Metadata {
+    created: Time,
+    accessed: Time,
+    modified: Time,
+    changed: Time,
+    len: u64,
+    allocation_size: u64,
+    attributes: FileAttributes,
+}
+
+impl Metadata {
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// How much space the file takes up on the server.
+    pub fn allocation_size(&self) -> u64 {
+        self.allocation_size
+    }
+
+    pub fn attributes(&self) -> FileAttributes {
+        self.attributes
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.attributes.contains(FileAttributes::DIRECTORY)
+    }
+
+    pub fn is_file(&self) -> bool {
+        !self.is_dir()
+    }
+
+    /// Whether this is a symbolic link, junction or other reparse point.
+    pub fn is_reparse_point(&self) -> bool {
+        self.attributes.contains(FileAttributes::REPARSE_POINT)
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.attributes.contains(FileAttributes::READONLY)
+    }
+
+    /// `None`, like the other times, when the server sent a time a `SystemTime` can't hold.
+    pub fn created(&self) -> Option<SystemTime> {
+        self.created.to_system_time()
+    }
+
+    pub fn accessed(&self) -> Option<SystemTime> {
+        self.accessed.to_system_time()
+    }
+
+    pub fn modified(&self) -> Option<SystemTime> {
+        self.modified.to_system_time()
+    }
+
+    /// The time the file's metadata last changed
+    pub fn changed(&self) -> Option<SystemTime> {
+        self.changed.to_system_time()
+    }
+}
+
+impl From<FileNetworkOpenInformation> for Metadata {
+    fn from(info: FileNetworkOpenInformation) -> Self {
+        Self {
+            created: info.creation_time,
+            accessed: info.last_access_time,
+            modified: info.last_write_time,
+            changed: info.change_time,
+            len: info.end_of_file as u64,
+            allocation_size: info.allocation_size as u64,
+            attributes: info.file_attributes,
+        }
+    }
+}
+
+impl From<FileAllInformation> for Metadata {
+    fn from(info: FileAllInformation) -> Self {
+        Self {
+            created: info.basic.creation_time,
+            accessed: info.basic.last_access_time,
+            modified: info.basic.last_write_time,
+            changed: info.basic.change_time,
+            len: info.standard.end_of_file as u64,
+            allocation_size: info.standard.allocation_size,
+            attributes: info.basic.file_attributes,
+        }
+    }
+}
+
+impl From<&FileIdBothDirectoryInformation> for Metadata {
+    fn from(info: &FileIdBothDirectoryInformation) -> Self {
+        Self {
+            created: info.creation_time.clone(),
+            accessed: info.last_access_time.clone(),
+            modified: info.last_write_time.clone(),
+            changed: info.change_time.clone(),
+            len: info.end_of_file as u64,
+            allocation_size: info.allocation_size as u64,
+            attributes: info.file_attributes,
+        }
+    }
+}
+
+/// Whether the error means there is nothing at the path.
+fn is_not_found(status: Option<NtStatus>) -> bool {
+    matches!(
+        status,
+        Some(NtStatus::ObjectNameNotFound | NtStatus::ObjectPathNotFound)
+    )
+}
+
+/// These open and close the file themselves, it is closed even when something fails part way.
+impl<TransportT: Transport> Client<TransportT> {
+    /// The metadata of the file or directory at `path`, following symbolic links.
+    pub async fn metadata(&mut self, path: impl AsRef<Path>) -> Result<Metadata> {
+        match self
+            .query_info_path::<FileNetworkOpenInformation>(&path)
+            .await
+        {
+            Err(e) if is_unsupported_info_class(&e) => self
+                .query_info_path::<FileAllInformation>(path)
+                .await
+                .map(Metadata::from),
+            result => result.map(Metadata::from),
+        }
+    }
+
+    /// Whether there is a file or directory at `path`.
+    pub async fn exists(&mut self, path: impl AsRef<Path>) -> Result<bool> {
+        match OpenOptions::new().open_file_id(self, path).await {
+            Ok(file_id) => {
+                self.close(file_id).await?;
+                Ok(true)
+            }
+            Err(e) if is_not_found(e.nt_status()) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Reads the whole of the file at `path`. It is named so as not to clash with `read`, which
+    /// reads part of an open file.
+    pub async fn read_file(&mut self, path: impl AsRef<Path>) -> Result<Vec<u8>> {
+        let file_id = OpenOptions::new()
+            .read(true)
+            .open_file_id(self, path)
+            .await?;
+        let mut contents = vec![];
+        let result = self.read_all(file_id, &mut contents).await;
+        self.close(file_id).await?;
+        result.map(|()| contents)
+    }
+
+    /// Makes `contents` the contents of the file at `path`, creating it if need be.
+    pub async fn write_file(
+        &mut self,
+        path: impl AsRef<Path>,
+        contents: impl AsRef<[u8]>,
+    ) -> Result<()> {
+        let file_id = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open_file_id(self, path)
+            .await?;
+        let result = self.write_all(file_id, contents.as_ref()).await;
+        self.close(file_id).await?;
+        result
+    }
+
+    /// Lists the directory at `path`, leaving out `.` and `..`.
+    pub async fn read_dir(
+        &mut self,
+        path: impl AsRef<Path>,
+    ) -> Result<Vec<FileIdBothDirectoryInformation>> {
+        let file_id = OpenOptions::new()
+            .access(AccessMask::FILE_LIST_DIRECTORY)
+            .create_options(FileCreateOptions::DIRECTORY_FILE)
+            .open_file_id(self, path)
+            .await?;
+        let entries = self.query_directory(file_id).await;
+        self.close(file_id).await?;
+        Ok(entries?
+            .into_iter()
+            .filter(|e| e.file_name != "." && e.file_name != "..")
+            .collect())
+    }
+
+    /// Copies the contents of the file at `from` to `to`, replacing anything there, and returns
+    /// how many bytes were copied. The data goes through the client.
+    pub async fn copy(&mut self, from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<u64> {
+        let source = OpenOptions::new()
+            .read(true)
+            .open_file_id(self, from)
+            .await?;
+        let destination = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open_file_id(self, to)
+            .await;
+        let destination = match destination {
+            Ok(destination) => destination,
+            Err(e) => {
+                self.close(source).await?;
+                return Err(e);
+            }
+        };
+
+        let result = self.copy_open(source, destination).await;
+        let closed_source = self.close(source).await;
+        self.close(destination).await?;
+        closed_source?;
+        result
+    }
+
+    async fn copy_open(&mut self, source: FileId, destination: FileId) -> Result<u64> {
+        let mut copied = 0;
+        loop {
+            let data = match self.read(source, copied, IO_SIZE as u32).await {
+                Ok(data) if data.is_empty() => return Ok(copied),
+                Ok(data) => data,
+                Err(e) if e.nt_status() == Some(NtStatus::EndOfFile) => return Ok(copied),
+                Err(e) => return Err(e),
+            };
+            // Whatever a short write leaves out is read again
+            let count = self.write(destination, copied, data).await?;
+            if count == 0 {
+                return Err(std::io::Error::from(std::io::ErrorKind::WriteZero).into());
+            }
+            copied += count as u64;
+        }
+    }
+}
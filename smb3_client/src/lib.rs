@@ -1,7 +1,10 @@
 use sspi_bobbobbio as sspi;
 
 use cmac::Mac as _;
+use compound::{request_result, Compound};
 use derive_more::From;
+use dfs::ReferralCache;
+use metrics::ConnectionMetrics;
 use rand::Rng as _;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use sha2::Digest as _;
@@ -11,22 +14,212 @@ use sspi::{
     AuthIdentity, ClientRequestFlags, CredentialUse, DataRepresentation, Ntlm, SecurityBuffer,
     SecurityBufferType, SecurityStatus, Sspi, SspiImpl,
 };
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::mem;
-use std::path::{Component, Path};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::io::{self, AsyncReadExt as _, AsyncWriteExt as _};
+use trace::{debug, info, trace, warn, RequestSpans};
+
+mod compound;
+pub mod dfs;
+mod dir;
+mod file;
+mod fs;
+mod metrics;
+mod op;
+mod open;
+mod path;
+mod pipe;
+mod retry;
+pub mod rpc;
+mod times;
+mod trace;
+mod walk;
+
+pub use dfs::Connector;
+pub use dir::{DirectoryEntries, QueryDirectoryOptions};
+pub use file::File;
+pub use fs::Metadata;
+pub use metrics::{ConnectionCounters, InMemoryMetrics, Metrics, NoopMetrics};
+pub use open::OpenOptions;
+pub use path::{PathError, SmbPath, UncPath};
+pub use pipe::NamedPipe;
+pub use retry::RetryPolicy;
+pub use rpc::srvsvc::{ShareInfo, ShareKind};
+pub use times::{FileTimes, TimeUpdate};
+pub use walk::{Walk, WalkEntry, WALK_CONCURRENCY};
 
 pub const PORT: u16 = 445;
 
+/// How long the client waits for the response to a request unless told otherwise, see
+/// [`Client::set_timeout`].
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long the client waits for the response to a request the server said it is working on,
+/// unless told otherwise, see [`Client::set_async_timeout`].
+pub const DEFAULT_ASYNC_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
 const IO_SIZE: usize = 4096 * 16;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Debug, From)]
 pub enum Error {
+    /// A status the client decided on itself, the server's are in `Request`
     NtStatus(NtStatus),
     Sspi(sspi::Error),
     Seralization(serde_smb::Error),
     Io(std::io::Error),
+    Rpc(rpc::Error),
+    /// The server failed a request
+    Request(Box<RequestError>),
+    Path(PathError),
+    /// The server didn't respond to the request sent as this message in time, it was cancelled
+    #[from(ignore)]
+    TimedOut(MessageId),
+    /// The connection to the server was lost, along with the session and the files open on it
+    #[from(ignore)]
+    Disconnected(std::io::Error),
+    /// The server didn't grant the credits to send a request for this command in time, so it
+    /// wasn't sent
+    #[from(ignore)]
+    OutOfCredits(Command),
+}
+
+/// A request the server failed, and what it was for.
+#[derive(Debug)]
+pub struct RequestError {
+    pub status: NtStatus,
+    pub command: Command,
+    pub message_id: MessageId,
+    /// The path the request was for relative to the share, if it was for one
+    pub path: Option<String>,
+    /// More information the server sent about why
+    pub contexts: Vec<ErrorContext>,
+}
+
+/// Like `Create of "dir\file" failed (message 12): ObjectNameNotFound (0xc0000034): ...`
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.command)?;
+        if let Some(path) = &self.path {
+            write!(f, " of {path:?}")?;
+        }
+        write!(
+            f,
+            " failed (message {}): {}",
+            self.message_id.0, self.status
+        )
+    }
+}
+
+impl std::error::Error for RequestError {}
+
+/// For conversions which can't fail, like an `UncPath` into itself.
+impl From<std::convert::Infallible> for Error {
+    fn from(e: std::convert::Infallible) -> Self {
+        match e {}
+    }
+}
+
+impl Error {
+    /// The status the server failed the request with, if that is what went wrong.
+    pub fn nt_status(&self) -> Option<NtStatus> {
+        match self {
+            Self::NtStatus(status) => Some(*status),
+            Self::Request(e) => Some(e.status),
+            _ => None,
+        }
+    }
+
+    /// The extra information the server sent with the error status.
+    pub fn error_contexts(&self) -> &[ErrorContext] {
+        match self {
+            Self::Request(e) => &e.contexts,
+            _ => &[],
+        }
+    }
+
+    /// Whether the failure may not happen again, so a request which is safe to repeat is worth
+    /// sending again. See [`RetryPolicy`].
+    pub fn is_transient(&self) -> bool {
+        matches!(self, Self::TimedOut(_) | Self::OutOfCredits(_))
+            || matches!(
+                self.nt_status(),
+                Some(
+                    NtStatus::InsufficientResources
+                        | NtStatus::NetworkBusy
+                        | NtStatus::RequestNotAccepted
+                )
+            )
+    }
+
+    /// The kind of I/O error this is nearest to, to match on without listing statuses.
+    pub fn kind(&self) -> std::io::ErrorKind {
+        match self {
+            Self::NtStatus(status) => (*status).into(),
+            Self::Request(e) => e.status.into(),
+            Self::Io(e) => e.kind(),
+            Self::Seralization(_) => std::io::ErrorKind::InvalidData,
+            Self::Path(_) => std::io::ErrorKind::InvalidInput,
+            Self::TimedOut(_) | Self::OutOfCredits(_) => std::io::ErrorKind::TimedOut,
+            Self::Disconnected(_) => std::io::ErrorKind::ConnectionAborted,
+            Self::Sspi(_) | Self::Rpc(_) => std::io::ErrorKind::Other,
+        }
+    }
+
+    /// Records that the failed request was for `path`.
+    fn with_path(mut self, path: &str) -> Self {
+        if let Self::Request(e) = &mut self {
+            e.path.get_or_insert_with(|| path.into());
+        }
+        self
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NtStatus(status) => fmt::Display::fmt(status, f),
+            Self::Sspi(e) => write!(f, "authentication failed: {e}"),
+            Self::Seralization(e) => write!(f, "malformed message: {e}"),
+            Self::Io(e) => fmt::Display::fmt(e, f),
+            Self::Rpc(e) => fmt::Display::fmt(e, f),
+            Self::Request(e) => fmt::Display::fmt(e, f),
+            Self::Path(e) => fmt::Display::fmt(e, f),
+            Self::TimedOut(message_id) => {
+                write!(f, "no response to message {} in time", message_id.0)
+            }
+            Self::Disconnected(e) => write!(f, "lost the connection to the server: {e}"),
+            Self::OutOfCredits(command) => {
+                write!(f, "no credits to send {command:?} granted in time")
+            }
+        }
+    }
+}
+
+/// The message of the error wrapped is part of this one's, so its source is passed on.
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Sspi(e) => e.source(),
+            Self::Seralization(e) => e.source(),
+            Self::Io(e) | Self::Disconnected(e) => e.source(),
+            _ => None,
+        }
+    }
+}
+
+impl From<Error> for std::io::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Io(e) => e,
+            e => std::io::Error::new(e.kind(), e),
+        }
+    }
 }
 
 pub trait Transport: io::AsyncRead + io::AsyncWrite + Unpin {}
@@ -37,6 +230,29 @@ struct UnauthenticatedClient<TransportT> {
     next_message_id: MessageId,
     transport: TransportT,
     pre_auth_hash: Vec<u8>,
+    /// Responses which arrived while waiting for the response to another request
+    unclaimed: HashMap<MessageId, Vec<u8>>,
+    /// Requests nobody is going to wait for, their responses are thrown away
+    ignored: HashSet<MessageId>,
+    /// Requests the server is working on in the background, after an interim response
+    async_ids: HashMap<MessageId, AsyncId>,
+    /// Messages not yet completely written. Writing them can be picked up again after the future
+    /// doing it was dropped, without leaving half a message on the connection.
+    write_buffer: Vec<u8>,
+    /// What has been read of messages not yet completely read
+    read_buffer: Vec<u8>,
+    /// The request `receive` is waiting for. It is left set if that is dropped part way through.
+    receiving: Option<MessageId>,
+    /// How long to wait for a response, `None` waits forever
+    timeout: Option<Duration>,
+    /// How long to wait for a response after an interim one, `None` waits forever
+    async_timeout: Option<Duration>,
+    requests: RequestSpans,
+    metrics: ConnectionMetrics,
+    /// Credits the server has granted which requests haven't been charged yet
+    credits: u32,
+    /// Requests sent which the final response to hasn't arrived for
+    outstanding: HashSet<MessageId>,
 }
 
 type SignatureFuncRef<'a> = &'a mut dyn FnMut(&[u8]) -> Result<Signature>;
@@ -47,11 +263,27 @@ impl<TransportT: Transport> UnauthenticatedClient<TransportT> {
             next_message_id: MessageId(0),
             transport,
             pre_auth_hash: vec![0; 64],
+            unclaimed: HashMap::new(),
+            ignored: HashSet::new(),
+            async_ids: HashMap::new(),
+            write_buffer: vec![],
+            read_buffer: vec![],
+            receiving: None,
+            timeout: Some(DEFAULT_TIMEOUT),
+            async_timeout: Some(DEFAULT_ASYNC_TIMEOUT),
+            requests: RequestSpans::default(),
+            metrics: ConnectionMetrics::default(),
+            // A connection starts out with the one credit negotiation uses
+            credits: 1,
+            outstanding: HashSet::new(),
         }
     }
 
     #[allow(clippy::too_many_arguments)]
-    async fn request<T: serde::Serialize + HasCommand, R: serde::de::DeserializeOwned>(
+    async fn request<
+        T: serde::Serialize + HasCommand + fmt::Debug,
+        R: serde::de::DeserializeOwned + fmt::Debug,
+    >(
         &mut self,
         credit_charge: Credits,
         credits_requested: Credits,
@@ -60,7 +292,134 @@ impl<TransportT: Transport> UnauthenticatedClient<TransportT> {
         tree_id: Option<TreeId>,
         request: T,
     ) -> Result<(ResponseHeader, R)> {
+        let message_id = self
+            .send(
+                credit_charge,
+                credits_requested,
+                session_id,
+                signature_func,
+                tree_id,
+                request,
+            )
+            .await?;
+        self.receive(message_id).await
+    }
+
+    /// Sends the requests of `compound` together, returning their message ids to pass to
+    /// `receive`. Every request after the first is related to the one before.
+    async fn send_compound(
+        &mut self,
+        credits_requested: Credits,
+        session_id: SessionId,
+        signature_func: SignatureFuncRef<'_>,
+        tree_id: TreeId,
+        compound: Compound,
+    ) -> Result<Vec<MessageId>> {
+        if let Some((command, _)) = compound.requests.first() {
+            let charge = compound.requests.len() as u16;
+            self.wait_for_credits(*command, charge).await?;
+        }
+        let message_ids = self.queue_compound(
+            credits_requested,
+            session_id,
+            signature_func,
+            tree_id,
+            compound,
+        )?;
+        self.flush_writes().await?;
+        Ok(message_ids)
+    }
+
+    fn queue_compound(
+        &mut self,
+        credits_requested: Credits,
+        session_id: SessionId,
+        signature_func: SignatureFuncRef<'_>,
+        tree_id: TreeId,
+        compound: Compound,
+    ) -> Result<Vec<MessageId>> {
+        let count = compound.requests.len();
+        let mut message_ids = vec![];
+        let mut req_bytes = vec![];
+        for (i, (command, serialize)) in compound.requests.into_iter().enumerate() {
+            let message_id = self.next_message_id;
+            let header = RequestHeader {
+                protocol_id: ProtocolId::new(),
+                header_length: 64,
+                credit_charge: Credits(1),
+                channel_sequence: 0,
+                command,
+                credits_requested,
+                flags: HeaderFlags::new().with_signing(true).with_chained(i > 0),
+                chain_offset: 0,
+                message_id,
+                process_id: ProcessId(0),
+                tree_id,
+                session_id,
+                signature: Signature([0; 16]),
+            };
+            self.next_message_id = MessageId(self.next_message_id.0 + 1);
+
+            self.requests.sent(&header);
+            self.charge(&header);
+            let mut message = serialize(&header)?;
+            if i + 1 < count {
+                // The next request starts 8 byte aligned, the padding is signed along with this
+                // one
+                message.resize(message.len().next_multiple_of(8), 0);
+                let chain_offset = message.len() as u32;
+                message[20..24].clone_from_slice(&chain_offset.to_le_bytes());
+            }
+            let sig = signature_func(&message[..])?;
+            message[48..64].clone_from_slice(&sig.0[..]);
+            self.metrics.sent(&header, message.len());
+            req_bytes.extend(message);
+            message_ids.push(message_id);
+        }
+
+        self.queue(&req_bytes);
+        Ok(message_ids)
+    }
+
+    /// Sends `request` without waiting for its response, returning the message id to pass to
+    /// `receive`.
+    #[allow(clippy::too_many_arguments)]
+    async fn send<T: serde::Serialize + HasCommand + fmt::Debug>(
+        &mut self,
+        credit_charge: Credits,
+        credits_requested: Credits,
+        session_id: Option<SessionId>,
+        signature_func: Option<SignatureFuncRef<'_>>,
+        tree_id: Option<TreeId>,
+        request: T,
+    ) -> Result<MessageId> {
+        self.wait_for_credits(T::command(), credit_charge.0.max(1))
+            .await?;
+        let message_id = self.queue_request(
+            credit_charge,
+            credits_requested,
+            session_id,
+            signature_func,
+            tree_id,
+            request,
+        )?;
+        self.flush_writes().await?;
+        Ok(message_id)
+    }
+
+    /// Adds `request` to the messages to write, see `flush_writes`.
+    #[allow(clippy::too_many_arguments)]
+    fn queue_request<T: serde::Serialize + HasCommand + fmt::Debug>(
+        &mut self,
+        credit_charge: Credits,
+        credits_requested: Credits,
+        session_id: Option<SessionId>,
+        signature_func: Option<SignatureFuncRef<'_>>,
+        tree_id: Option<TreeId>,
+        request: T,
+    ) -> Result<MessageId> {
         let command = T::command();
+        let message_id = self.next_message_id;
         let header = RequestHeader {
             protocol_id: ProtocolId::new(),
             header_length: 64,
@@ -70,7 +429,7 @@ impl<TransportT: Transport> UnauthenticatedClient<TransportT> {
             credits_requested,
             flags: HeaderFlags::new().with_signing(signature_func.is_some()),
             chain_offset: 0,
-            message_id: self.next_message_id,
+            message_id,
             process_id: ProcessId(0),
             tree_id: tree_id.unwrap_or(TreeId(0)),
             session_id: session_id.unwrap_or(SessionId(0)),
@@ -78,7 +437,15 @@ impl<TransportT: Transport> UnauthenticatedClient<TransportT> {
         };
         self.next_message_id = MessageId(self.next_message_id.0 + 1);
 
-        let mut req_bytes = serde_smb::to_vec(&(header, request))?;
+        self.requests.sent(&header);
+        self.charge(&header);
+        trace!(
+            target: trace::MESSAGES,
+            ?header,
+            request = ?trace::body(header.command, &request),
+            "request"
+        );
+        let mut req_bytes = serde_smb::to_vec(&(&header, request))?;
 
         if let Some(func) = signature_func {
             let sig = func(&req_bytes[..])?;
@@ -90,38 +457,259 @@ impl<TransportT: Transport> UnauthenticatedClient<TransportT> {
             self.pre_auth_hash = hasher.finalize().to_vec();
         }
 
-        self.transport.write_u32(req_bytes.len() as u32).await?;
-        self.transport.write_all(&req_bytes).await?;
+        self.metrics.sent(&header, req_bytes.len());
+        self.queue(&req_bytes);
+        Ok(message_id)
+    }
+
+    /// Adds a CANCEL for the request sent as `message_id` to the messages to write. It shares
+    /// the message id of the request, and has no response.
+    fn queue_cancel(
+        &mut self,
+        message_id: MessageId,
+        session_id: SessionId,
+        signature_func: SignatureFuncRef<'_>,
+    ) -> Result<()> {
+        let async_id = self.async_ids.get(&message_id).copied();
+        let (process_id, tree_id) = async_id.unwrap_or_default().to_ids();
+        let header = RequestHeader {
+            protocol_id: ProtocolId::new(),
+            header_length: 64,
+            credit_charge: Credits(0),
+            channel_sequence: 0,
+            command: Command::Cancel,
+            credits_requested: Credits(0),
+            flags: HeaderFlags::new()
+                .with_signing(true)
+                .with_async(async_id.is_some()),
+            chain_offset: 0,
+            message_id,
+            process_id,
+            tree_id,
+            session_id,
+            signature: Signature([0; 16]),
+        };
+        let mut req_bytes = serde_smb::to_vec(&(&header, CancelRequest))?;
+        let sig = signature_func(&req_bytes[..])?;
+        req_bytes[48..64].clone_from_slice(&sig.0[..]);
+        self.metrics.sent(&header, req_bytes.len());
+        self.queue(&req_bytes);
+        Ok(())
+    }
+
+    /// Takes the credits for the request with `header` from those granted.
+    fn charge(&mut self, header: &RequestHeader) {
+        let charge = header.credit_charge.0.max(1) as u32;
+        self.credits = self.credits.saturating_sub(charge);
+        self.outstanding.insert(header.message_id);
+    }
+
+    /// Waits until the server has granted the `charge` credits a request needs, by reading the
+    /// responses it grants them with. If no response is coming the request is left for the
+    /// server to decide on, if none comes in time it fails with `Error::OutOfCredits`.
+    async fn wait_for_credits(&mut self, command: Command, charge: u16) -> Result<()> {
+        if self.credits >= charge as u32 {
+            return Ok(());
+        }
+        debug!(
+            ?command,
+            charge,
+            available = self.credits,
+            "waiting for credits"
+        );
+        self.metrics.credit_stall(command, charge, self.credits);
+        self.flush_writes().await?;
+
+        let deadline = self
+            .timeout
+            .map(|timeout| tokio::time::Instant::now() + timeout);
+        while self.credits < charge as u32 && !self.outstanding.is_empty() {
+            let frame = match deadline {
+                Some(deadline) => {
+                    match tokio::time::timeout_at(deadline, self.read_frame()).await {
+                        Ok(frame) => frame?,
+                        Err(_) => return Err(Error::OutOfCredits(command)),
+                    }
+                }
+                None => self.read_frame().await?,
+            };
+            self.take_frame(frame)?;
+        }
+        Ok(())
+    }
+
+    fn queue(&mut self, message: &[u8]) {
+        self.write_buffer
+            .extend((message.len() as u32).to_be_bytes());
+        self.write_buffer.extend(message);
+    }
+
+    /// Writes the queued messages. Each write either happens or doesn't, so if this is dropped
+    /// the next call carries on where it left off.
+    async fn flush_writes(&mut self) -> Result<()> {
+        while !self.write_buffer.is_empty() {
+            let written = self
+                .transport
+                .write(&self.write_buffer)
+                .await
+                .map_err(transport_error)?;
+            if written == 0 {
+                return Err(transport_error(io::ErrorKind::WriteZero.into()));
+            }
+            self.write_buffer.drain(..written);
+        }
+        Ok(())
+    }
+
+    /// Reads the next message. What has been read is kept when this is dropped, like by a
+    /// timeout, so it can be called again.
+    async fn read_frame(&mut self) -> Result<Vec<u8>> {
+        loop {
+            if let Some(len) = self.read_buffer.get(..4) {
+                let len = u32::from_be_bytes(len.try_into().unwrap()) as usize;
+                if self.read_buffer.len() >= 4 + len {
+                    let frame = self.read_buffer[4..4 + len].to_vec();
+                    self.read_buffer.drain(..4 + len);
+                    return Ok(frame);
+                }
+                self.read_buffer.reserve(4 + len - self.read_buffer.len());
+            }
+            let read = self
+                .transport
+                .read_buf(&mut self.read_buffer)
+                .await
+                .map_err(transport_error)?;
+            if read == 0 {
+                return Err(transport_error(io::ErrorKind::UnexpectedEof.into()));
+            }
+        }
+    }
 
-        let mut response_header: ResponseHeader;
-        let mut response_bytes: Vec<u8>;
-        let mut deser = loop {
-            let len = self.transport.read_u32().await?;
-            response_bytes = vec![0; len as usize];
-            self.transport.read_exact(&mut response_bytes).await?;
+    /// Sets aside the responses in `frame` for whoever is waiting on them.
+    fn take_frame(&mut self, frame: Vec<u8>) -> Result<()> {
+        let response_header: ResponseHeader = serde_smb::from_slice(&frame)?;
 
-            let mut deser = serde_smb::Deserializer::new(&response_bytes[..]);
-            response_header = Deserialize::deserialize(&mut deser)?;
+        if response_header.signature == Signature([0; 16]) {
+            let mut hasher = sha2::Sha512::new();
+            hasher.update(&self.pre_auth_hash);
+            hasher.update(&frame);
+            self.pre_auth_hash = hasher.finalize().to_vec();
+        }
 
-            if response_header.signature == Signature([0; 16]) {
-                let mut hasher = sha2::Sha512::new();
-                hasher.update(&self.pre_auth_hash);
-                hasher.update(&response_bytes);
-                self.pre_auth_hash = hasher.finalize().to_vec();
+        for response_bytes in split_compound(frame)? {
+            let response_header: ResponseHeader = serde_smb::from_slice(&response_bytes)?;
+            let message_id = response_header.message_id;
+            self.metrics
+                .received(&response_header, response_bytes.len());
+            self.credits += response_header.credits_granted.0 as u32;
+            if response_header.nt_status == NtStatus::Pending {
+                self.requests.pending(&response_header);
+                if let Some(async_id) = response_header.async_id() {
+                    self.async_ids.insert(message_id, async_id);
+                }
+                continue;
             }
+            self.requests.responded(&response_header);
+            self.outstanding.remove(&message_id);
+            self.async_ids.remove(&message_id);
+            if !self.ignored.remove(&message_id) {
+                self.unclaimed.insert(message_id, response_bytes);
+            }
+        }
+        Ok(())
+    }
 
-            if response_header.nt_status != NtStatus::Pending {
-                break deser;
+    /// Waits for the final response to the request sent as `message_id`, setting aside any
+    /// responses to other requests that arrive first.
+    async fn receive<R: serde::de::DeserializeOwned + fmt::Debug>(
+        &mut self,
+        message_id: MessageId,
+    ) -> Result<(ResponseHeader, R)> {
+        // Nobody is waiting for the response to a request whose receive was dropped
+        if let Some(abandoned) = self.receiving.take() {
+            if self.unclaimed.remove(&abandoned).is_none() {
+                self.ignored.insert(abandoned);
             }
+        }
+        self.flush_writes().await?;
+
+        self.receiving = Some(message_id);
+        let deadline = self
+            .timeout
+            .map(|timeout| tokio::time::Instant::now() + timeout);
+        let mut async_deadline = None;
+        let response_bytes = loop {
+            if let Some(response_bytes) = self.unclaimed.remove(&message_id) {
+                break response_bytes;
+            }
+            // Once the server says it is working on the request, it gets the async timeout
+            // from then on
+            let deadline = if self.async_ids.contains_key(&message_id) {
+                *async_deadline.get_or_insert_with(|| {
+                    self.async_timeout
+                        .map(|timeout| tokio::time::Instant::now() + timeout)
+                })
+            } else {
+                deadline
+            };
+            let frame = match deadline {
+                Some(deadline) => {
+                    match tokio::time::timeout_at(deadline, self.read_frame()).await {
+                        Ok(frame) => frame?,
+                        Err(_) => {
+                            self.requests.timed_out(message_id);
+                            self.metrics.timed_out(message_id);
+                            self.receiving = None;
+                            self.ignored.insert(message_id);
+                            return Err(Error::TimedOut(message_id));
+                        }
+                    }
+                }
+                None => self.read_frame().await?,
+            };
+            self.take_frame(frame)?;
         };
+        self.receiving = None;
+
+        let mut deser = serde_smb::Deserializer::new(&response_bytes[..]);
+        let response_header: ResponseHeader = Deserialize::deserialize(&mut deser)?;
+
+        // Named pipes report a partially read message with BUFFER_OVERFLOW, the response body
+        // still contains the part of the message that fit. Only `receive_partial` passes it on.
+        let partial_message = response_header.nt_status == NtStatus::BufferOverflow
+            && [Command::Read, Command::Ioctl].contains(&response_header.command);
 
         if response_header.nt_status == NtStatus::Success
             || response_header.nt_status == NtStatus::MoreProcessingRequired
+            || partial_message
         {
             let response_body: R = Deserialize::deserialize(&mut deser)?;
+            trace!(
+                target: trace::MESSAGES,
+                header = ?response_header,
+                body = ?trace::body(response_header.command, &response_body),
+                "response"
+            );
             Ok((response_header, response_body))
         } else {
-            Err(Error::NtStatus(response_header.nt_status))
+            // The status is what matters, so an error body which can't be parsed is ignored
+            let status = response_header.nt_status;
+            let contexts = ErrorResponse::deserialize(&mut deser)
+                .and_then(|response| response.contexts(status))
+                .unwrap_or_default();
+            trace!(
+                target: trace::MESSAGES,
+                header = ?response_header,
+                ?contexts,
+                "error response"
+            );
+            Err(Error::Request(Box::new(RequestError {
+                status,
+                command: response_header.command,
+                message_id: response_header.message_id,
+                path: None,
+                contexts,
+            })))
         }
     }
 
@@ -142,9 +730,16 @@ impl<TransportT: Transport> UnauthenticatedClient<TransportT> {
             )],
         };
 
-        let _response: (_, NegotiateResponse) = self
+        #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+        let (_, response): (_, NegotiateResponse) = self
             .request(Credits(0), Credits(10), None, None, None, request)
             .await?;
+        info!(
+            dialect = ?response.dialect,
+            max_read_size = response.max_read_size,
+            max_write_size = response.max_write_size,
+            "negotiated"
+        );
         Ok(())
     }
 }
@@ -153,11 +748,22 @@ struct AuthenticatedClient<TransportT> {
     unauth_client: UnauthenticatedClient<TransportT>,
     session_id: SessionId,
     signing_key: Vec<u8>,
+    deferred_closes: DeferredCloses,
 }
 
+/// Files to close before sending the next request on a connection, for when there is no way to
+/// wait for the close, like when dropping a `File`.
+type DeferredCloses = Arc<Mutex<Vec<(TreeId, FileId)>>>;
+
 impl<TransportT: Transport> AuthenticatedClient<TransportT> {
-    async fn new(transport: TransportT, username: &str, password: &str) -> Result<Self> {
+    async fn new(
+        transport: TransportT,
+        username: &str,
+        password: &str,
+        metrics: ConnectionMetrics,
+    ) -> Result<Self> {
         let mut unauth_client = UnauthenticatedClient::new(transport);
+        unauth_client.metrics = metrics;
 
         unauth_client.negotiate().await?;
 
@@ -205,6 +811,7 @@ impl<TransportT: Transport> AuthenticatedClient<TransportT> {
         let session_id = resp_header.session_id;
 
         while resp_header.nt_status == NtStatus::MoreProcessingRequired {
+            debug!(session_id = session_id.0, "continuing session setup");
             let mut input_buffer = vec![SecurityBuffer::new(
                 mem::take(&mut response.security_blob),
                 SecurityBufferType::Token,
@@ -256,38 +863,152 @@ impl<TransportT: Transport> AuthenticatedClient<TransportT> {
             b"SMBSigningKey\0",
             &unauth_client.pre_auth_hash,
         );
+        info!(username, session_id = session_id.0, "authenticated");
 
         Ok(Self {
             unauth_client,
             session_id,
             signing_key,
+            deferred_closes: DeferredCloses::default(),
         })
     }
 
-    async fn request<T: serde::Serialize + HasCommand, R: serde::de::DeserializeOwned>(
+    async fn request<
+        T: serde::Serialize + HasCommand + fmt::Debug,
+        R: serde::de::DeserializeOwned + fmt::Debug,
+    >(
         &mut self,
         tree_id: Option<TreeId>,
         credit_charge: Credits,
         credits_requested: Credits,
         request: T,
     ) -> Result<(ResponseHeader, R)> {
-        let mut sig_func = |bytes: &[u8]| {
-            let mut mac = cmac::Cmac::<aes::Aes128>::new_from_slice(&self.signing_key[..]).unwrap();
-            mac.update(bytes);
-            Ok(Signature(mac.finalize().into_bytes().into()))
-        };
+        let message_id = self
+            .send(tree_id, credit_charge, credits_requested, request)
+            .await?;
+        self.receive(message_id).await
+    }
+
+    /// Like `request`, but a response with only part of a named pipe message succeeds too, see
+    /// `receive_partial`.
+    async fn request_partial<
+        T: serde::Serialize + HasCommand + fmt::Debug,
+        R: serde::de::DeserializeOwned + fmt::Debug,
+    >(
+        &mut self,
+        tree_id: Option<TreeId>,
+        credit_charge: Credits,
+        credits_requested: Credits,
+        request: T,
+    ) -> Result<(ResponseHeader, R)> {
+        let message_id = self
+            .send(tree_id, credit_charge, credits_requested, request)
+            .await?;
+        self.receive_partial(message_id).await
+    }
+
+    /// Sends `request`, after closing any files whose closes were deferred.
+    async fn send<T: serde::Serialize + HasCommand + fmt::Debug>(
+        &mut self,
+        tree_id: Option<TreeId>,
+        credit_charge: Credits,
+        credits_requested: Credits,
+        request: T,
+    ) -> Result<MessageId> {
+        let charge = credit_charge.0.max(1) + self.deferred_closes.lock().unwrap().len() as u16;
         self.unauth_client
-            .request(
-                credit_charge,
+            .wait_for_credits(T::command(), charge)
+            .await?;
+        self.queue_deferred_closes()?;
+        let message_id = self.queue(tree_id, credit_charge, credits_requested, request)?;
+        self.unauth_client.flush_writes().await?;
+        Ok(message_id)
+    }
+
+    fn queue_deferred_closes(&mut self) -> Result<()> {
+        let closes = mem::take(&mut *self.deferred_closes.lock().unwrap());
+        for (close_tree_id, file_id) in closes {
+            let flags = CloseFlags::empty();
+            let close = CloseRequest { flags, file_id };
+            let message_id = self.queue(Some(close_tree_id), Credits(1), Credits(64), close)?;
+            self.unauth_client.ignored.insert(message_id);
+        }
+        Ok(())
+    }
+
+    fn queue<T: serde::Serialize + HasCommand + fmt::Debug>(
+        &mut self,
+        tree_id: Option<TreeId>,
+        credit_charge: Credits,
+        credits_requested: Credits,
+        request: T,
+    ) -> Result<MessageId> {
+        let mut sig_func = |bytes: &[u8]| sign(&self.signing_key, bytes);
+        self.unauth_client.queue_request(
+            credit_charge,
+            credits_requested,
+            Some(self.session_id),
+            Some(&mut sig_func),
+            tree_id,
+            request,
+        )
+    }
+
+    async fn send_compound(
+        &mut self,
+        tree_id: TreeId,
+        credits_requested: Credits,
+        compound: Compound,
+    ) -> Result<Vec<MessageId>> {
+        self.queue_deferred_closes()?;
+        let mut sig_func = |bytes: &[u8]| sign(&self.signing_key, bytes);
+        self.unauth_client
+            .send_compound(
                 credits_requested,
-                Some(self.session_id),
-                Some(&mut sig_func),
+                self.session_id,
+                &mut sig_func,
                 tree_id,
-                request,
+                compound,
             )
             .await
     }
 
+    /// Waits for the response to the request sent as `message_id`, cancelling the request if it
+    /// doesn't come in time.
+    async fn receive<R: serde::de::DeserializeOwned + fmt::Debug>(
+        &mut self,
+        message_id: MessageId,
+    ) -> Result<(ResponseHeader, R)> {
+        let (header, response) = self.receive_partial(message_id).await?;
+        if header.nt_status == NtStatus::BufferOverflow {
+            return Err(Error::Request(Box::new(RequestError {
+                status: header.nt_status,
+                command: header.command,
+                message_id: header.message_id,
+                path: None,
+                contexts: vec![],
+            })));
+        }
+        Ok((header, response))
+    }
+
+    /// Like `receive`, but a READ or IOCTL response with BUFFER_OVERFLOW succeeds, with the part
+    /// of the named pipe message that fit. The rest is read with more READs.
+    async fn receive_partial<R: serde::de::DeserializeOwned + fmt::Debug>(
+        &mut self,
+        message_id: MessageId,
+    ) -> Result<(ResponseHeader, R)> {
+        let result = self.unauth_client.receive(message_id).await;
+        if let Err(Error::TimedOut(_)) = result {
+            warn!(message_id = message_id.0, "cancelling request");
+            let mut sig_func = |bytes: &[u8]| sign(&self.signing_key, bytes);
+            self.unauth_client
+                .queue_cancel(message_id, self.session_id, &mut sig_func)?;
+            self.unauth_client.flush_writes().await?;
+        }
+        result
+    }
+
     async fn tree_connect(&mut self, path: &str) -> Result<TreeId> {
         let (header, _): (_, TreeConnectResponse) = self
             .request(
@@ -300,11 +1021,62 @@ impl<TransportT: Transport> AuthenticatedClient<TransportT> {
                 },
             )
             .await?;
+        info!(path, tree_id = header.tree_id.0, "connected to tree");
 
         Ok(header.tree_id)
     }
 }
 
+fn read_request(file_id: FileId, offset: u64, count: u32) -> ReadRequest {
+    ReadRequest {
+        padding: 0,
+        flags: ReadFlags::empty(),
+        length: count,
+        offset,
+        file_id,
+        minimum_bytes: 0,
+        channel: Channel::None,
+        remaining_bytes: 0,
+        // this can't be empty for some reason
+        channel_data: vec![0],
+    }
+}
+
+fn ioctl_request(
+    file_id: FileId,
+    ctl_code: CtlCode,
+    input: Vec<u8>,
+    max_output_response: u32,
+) -> IoctlRequest {
+    IoctlRequest {
+        ctl_code,
+        file_id,
+        max_input_response: 0,
+        max_output_response,
+        flags: IoctlFlags::IS_FSCTL,
+        input,
+    }
+}
+
+/// Tells apart the connection being lost from other I/O errors.
+fn transport_error(e: io::Error) -> Error {
+    match e.kind() {
+        io::ErrorKind::UnexpectedEof
+        | io::ErrorKind::WriteZero
+        | io::ErrorKind::BrokenPipe
+        | io::ErrorKind::ConnectionReset
+        | io::ErrorKind::ConnectionAborted => Error::Disconnected(e),
+        _ => e.into(),
+    }
+}
+
+/// The signature of a message signed with `signing_key`.
+fn sign(signing_key: &[u8], message: &[u8]) -> Result<Signature> {
+    let mut mac = cmac::Cmac::<aes::Aes128>::new_from_slice(signing_key).unwrap();
+    mac.update(message);
+    Ok(Signature(mac.finalize().into_bytes().into()))
+}
+
 fn sp800_108_counter_kdf(key_len: usize, secret: &[u8], label: &[u8], salt: &[u8]) -> Vec<u8> {
     let length: u32 = (key_len * 8).try_into().unwrap();
 
@@ -327,157 +1099,976 @@ fn sp800_108_counter_kdf(key_len: usize, secret: &[u8], label: &[u8], salt: &[u8
     p
 }
 
-fn path_str(path: impl AsRef<Path>) -> String {
-    let path_compontents: Vec<_> = path
-        .as_ref()
-        .components()
-        .filter_map(|c| match c {
-            Component::Normal(s) => Some(String::from(s.to_str().unwrap())),
-            Component::ParentDir => Some(String::from("..")),
-            _ => None,
-        })
-        .collect();
-    path_compontents.join("\\")
+/// Splits a message from the server into the responses compounded in it.
+fn split_compound(mut frame: Vec<u8>) -> Result<Vec<Vec<u8>>> {
+    let mut responses = vec![];
+    loop {
+        let header: ResponseHeader = serde_smb::from_slice(&frame)?;
+        let chain_offset = header.chain_offset as usize;
+        if chain_offset == 0 {
+            responses.push(frame);
+            return Ok(responses);
+        }
+        if chain_offset < HEADER_SIZE || chain_offset >= frame.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("compound response chain offset {chain_offset} out of bounds"),
+            )
+            .into());
+        }
+        let rest = frame.split_off(chain_offset);
+        responses.push(frame);
+        frame = rest;
+    }
 }
 
-pub struct Client<TransportT> {
-    auth_client: AuthenticatedClient<TransportT>,
+/// Converts `path` to the backslash separated form the server expects, see [`SmbPath`].
+fn path_str(path: impl AsRef<Path>) -> std::result::Result<String, PathError> {
+    Ok(SmbPath::try_from(path.as_ref())?.to_string())
+}
+
+/// The path of the data stream called `stream` of the file at `path`, usable wherever the client
+/// takes a path.
+pub fn stream_path(path: impl AsRef<Path>, stream: &str) -> Result<SmbPath> {
+    Ok(SmbPath::try_from(path.as_ref())?.with_stream(stream)?)
+}
+
+/// How many deletes `Client::remove_dir_all` keeps in flight at once.
+pub const REMOVE_CONCURRENCY: usize = 16;
+
+/// A request opening `name` just to delete it, without following a reparse point at the end.
+fn open_for_delete(name: String, create_options: FileCreateOptions) -> CreateRequest {
+    CreateRequest {
+        requested_oplock_level: OplockLevel::None,
+        impersonation_level: ImpersonationLevel::Impersonation,
+        desired_access: AccessMask::DELETE | AccessMask::FILE_READ_ATTRIBUTES,
+        file_attributes: FileAttributes::empty(),
+        share_access: FileShareAccess::READ | FileShareAccess::WRITE | FileShareAccess::DELETE,
+        create_disposition: FileCreateDisposition::Open,
+        create_options: create_options | FileCreateOptions::OPEN_REPARSE_POINT,
+        name,
+        create_contexts: vec![],
+    }
+}
+
+/// Joins a name within the directory `dir` onto it, `dir` is empty for the root of the share.
+fn join_name(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        name.into()
+    } else {
+        format!("{dir}\\{name}")
+    }
+}
+
+/// Whether the server turned down an information class it doesn't know, rather than the request.
+fn is_unsupported_info_class(error: &Error) -> bool {
+    matches!(
+        error.nt_status(),
+        Some(NtStatus::InvalidInfoClass | NtStatus::InvalidLevel | NtStatus::NotSupported)
+    )
+}
+
+/// How many DFS referrals are followed for one path before giving up.
+const MAX_REFERRAL_HOPS: usize = 8;
+
+/// How many symbolic links are followed for one path before giving up, like Linux's MAXSYMLINKS.
+const MAX_SYMLINK_HOPS: usize = 40;
+
+/// Works out the path to open after the server stopped on the symbolic link `link` while
+/// opening `name`. Only relative links can be followed, absolute ones lead outside the share.
+fn follow_symlink(name: &str, link: &SymbolicLinkErrorResponse) -> Option<String> {
+    if !link.flags.contains(SymbolicLinkFlags::RELATIVE) {
+        return None;
+    }
+    let name: Vec<u16> = name.encode_utf16().collect();
+    let split = name
+        .len()
+        .checked_sub(usize::from(link.unparsed_path_length) / 2)?;
+    let link_path = String::from_utf16_lossy(&name[..split]);
+    let rest = String::from_utf16_lossy(&name[split..]);
+    let parent = link_path.rsplit_once('\\').map(|(p, _)| p).unwrap_or("");
+
+    let mut components = vec![];
+    for component in parent
+        .split('\\')
+        .chain(link.substitute_name.split('\\'))
+        .chain(rest.split('\\'))
+    {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop()?;
+            }
+            c => components.push(c),
+        }
+    }
+    Some(components.join("\\"))
+}
+
+/// A tree connected on one of the connections of a [`Client`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct Tree {
+    connection: usize,
     tree_id: TreeId,
 }
 
+struct Connection<TransportT> {
+    server: String,
+    auth_client: AuthenticatedClient<TransportT>,
+    ipc_tree_id: Option<TreeId>,
+}
+
+pub struct Client<TransportT> {
+    connections: Vec<Connection<TransportT>>,
+    tree_path: String,
+    tree: Tree,
+    username: String,
+    password: String,
+    connector: Option<Box<dyn Connector<TransportT>>>,
+    referrals: ReferralCache,
+    /// Trees connected while following DFS referrals, by path
+    dfs_trees: Vec<(String, Tree)>,
+    /// Files opened and not yet closed, by the id handed out for them: the tree each is open on
+    /// and the id the server gave it. Servers only keep file ids unique within a session, so one
+    /// already taken on another connection is handed out under an id made up here.
+    opens: HashMap<FileId, (Tree, FileId)>,
+    /// The next made up file id
+    next_alias: u64,
+    timeout: Option<Duration>,
+    async_timeout: Option<Duration>,
+    metrics: Arc<dyn Metrics>,
+    retry_policy: RetryPolicy,
+}
+
 impl<TransportT: Transport> Client<TransportT> {
-    pub async fn new(
+    /// Connects to the share named by `path`, a [`UncPath`] or a string like `\\\\server\\share`
+    /// or `smb://server/share` parsed as one.
+    pub async fn new<P>(
         transport: TransportT,
         username: &str,
         password: &str,
-        path: &str,
-    ) -> Result<Self> {
-        let mut auth_client = AuthenticatedClient::new(transport, username, password).await?;
-        let tree_id = auth_client.tree_connect(path).await?;
+        path: P,
+    ) -> Result<Self>
+    where
+        P: TryInto<UncPath>,
+        Error: From<P::Error>,
+    {
+        let path: UncPath = path.try_into()?;
+        if !path.path().is_root() {
+            return Err(PathError::NotShare(path.to_string()).into());
+        }
+        let tree_path = path.tree_path();
+        let metrics = Arc::new(NoopMetrics);
+        let connection_metrics = ConnectionMetrics::new(path.server(), metrics.clone());
+        let mut auth_client =
+            AuthenticatedClient::new(transport, username, password, connection_metrics).await?;
+        let tree_id = auth_client.tree_connect(&tree_path).await?;
         Ok(Self {
-            auth_client,
-            tree_id,
+            connections: vec![Connection {
+                server: path.server().into(),
+                auth_client,
+                ipc_tree_id: None,
+            }],
+            tree_path,
+            tree: Tree {
+                connection: 0,
+                tree_id,
+            },
+            username: username.into(),
+            password: password.into(),
+            connector: None,
+            referrals: ReferralCache::new(),
+            dfs_trees: vec![],
+            opens: HashMap::new(),
+            next_alias: 0,
+            timeout: Some(DEFAULT_TIMEOUT),
+            async_timeout: Some(DEFAULT_ASYNC_TIMEOUT),
+            metrics,
+            retry_policy: RetryPolicy::default(),
         })
     }
 
-    pub async fn look_up(&mut self, path: impl AsRef<Path>) -> Result<FileId> {
-        let (_, response): (_, CreateResponse) = self
+    /// Sets how to connect to other servers when following DFS referrals. Without one only
+    /// referrals to other shares on the same server are followed.
+    pub fn set_connector(&mut self, connector: impl Connector<TransportT> + 'static) {
+        self.connector = Some(Box::new(connector));
+    }
+
+    /// Sets how long to wait for the response to each request, `None` waits forever. A request
+    /// which gets no response in time is cancelled and fails with `Error::TimedOut`. Once the
+    /// server says it is working on a request, with an interim response, the async timeout
+    /// applies instead. The default is [`DEFAULT_TIMEOUT`].
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+        for connection in &mut self.connections {
+            connection.auth_client.unauth_client.timeout = timeout;
+        }
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Sets how long to wait for the response to a request after the server said it is working
+    /// on it, counting from the interim response, `None` waits forever. A request which takes
+    /// longer is cancelled and fails with `Error::TimedOut`. The default is
+    /// [`DEFAULT_ASYNC_TIMEOUT`].
+    pub fn set_async_timeout(&mut self, async_timeout: Option<Duration>) {
+        self.async_timeout = async_timeout;
+        for connection in &mut self.connections {
+            connection.auth_client.unauth_client.async_timeout = async_timeout;
+        }
+    }
+
+    pub fn async_timeout(&self) -> Option<Duration> {
+        self.async_timeout
+    }
+
+    /// Sets how requests which are safe to repeat are retried, see [`RetryPolicy`].
+    pub fn set_retry_policy(&mut self, retry_policy: RetryPolicy) {
+        self.retry_policy = retry_policy;
+    }
+
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+
+    /// Reports what each connection does from now on to `metrics`, including connections made
+    /// later to follow DFS referrals. By default this goes to [`NoopMetrics`].
+    pub fn set_metrics(&mut self, metrics: Arc<dyn Metrics>) {
+        for connection in &mut self.connections {
+            connection.auth_client.unauth_client.metrics.metrics = metrics.clone();
+        }
+        self.metrics = metrics;
+    }
+
+    fn connection(&mut self, tree: Tree) -> &mut AuthenticatedClient<TransportT> {
+        &mut self.connections[tree.connection].auth_client
+    }
+
+    /// Sends `request` on `tree`, which must be safe to send more than once. If it fails in a
+    /// way which may not happen again it is sent again, as the retry policy says.
+    async fn request_idempotent<T, R>(
+        &mut self,
+        tree: Tree,
+        credit_charge: Credits,
+        credits_requested: Credits,
+        request: T,
+    ) -> Result<(ResponseHeader, R)>
+    where
+        T: Serialize + HasCommand + fmt::Debug + Clone,
+        R: DeserializeOwned + fmt::Debug,
+    {
+        self.request_retrying(tree, credit_charge, credits_requested, request, true)
+            .await
+    }
+
+    /// Like `request_idempotent`, but a request which timed out is only retried if
+    /// `retry_timeouts`. The server may still have carried it out, which matters for requests
+    /// that move something along, like QUERY_DIRECTORY.
+    ///
+    /// A request failing because the tree or the connection is gone is retried after connecting
+    /// them again, the connection only if there is a connector to make a new one with.
+    async fn request_retrying<T, R>(
+        &mut self,
+        mut tree: Tree,
+        credit_charge: Credits,
+        credits_requested: Credits,
+        request: T,
+        retry_timeouts: bool,
+    ) -> Result<(ResponseHeader, R)>
+    where
+        T: Serialize + HasCommand + fmt::Debug + Clone,
+        R: DeserializeOwned + fmt::Debug,
+    {
+        let can_reconnect = self.connector.is_some();
+        let retry = |e: &Error| {
+            (e.is_transient() && (retry_timeouts || !matches!(e, Error::TimedOut(_))))
+                || e.nt_status() == Some(NtStatus::NetworkNameDeleted)
+                || (can_reconnect && matches!(e, Error::Disconnected(_)))
+        };
+        let mut attempt = 1;
+        loop {
+            let result = self
+                .connection(tree)
+                .request(
+                    Some(tree.tree_id),
+                    credit_charge,
+                    credits_requested,
+                    request.clone(),
+                )
+                .await;
+            match result {
+                Err(e) if retry(&e) && attempt < self.retry_policy.max_attempts => {
+                    let backoff = self.retry_policy.backoff(attempt);
+                    warn!(error = %e, attempt, ?backoff, "retrying");
+                    tokio::time::sleep(backoff).await;
+                    attempt += 1;
+                    if matches!(e, Error::Disconnected(_)) {
+                        tree = self.reconnect(tree).await?;
+                    } else if e.nt_status() == Some(NtStatus::NetworkNameDeleted) {
+                        tree = self.reconnect_tree(tree).await?;
+                    }
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Connects the share of `tree` again after the server disconnected it, returning the tree to
+    /// use in its place. Files open on the old tree are gone with it.
+    async fn reconnect_tree(&mut self, tree: Tree) -> Result<Tree> {
+        let connection = &mut self.connections[tree.connection];
+        let tree_id = if connection.ipc_tree_id == Some(tree.tree_id) {
+            connection.ipc_tree_id = None;
+            self.ipc_tree(tree.connection).await?.tree_id
+        } else {
+            let path = self.tree_path_of(tree).to_owned();
+            info!(path, "connecting to tree again");
+            self.connection(tree).tree_connect(&path).await?
+        };
+        let new_tree = Tree {
+            connection: tree.connection,
+            tree_id,
+        };
+        self.replace_trees(&[(tree, new_tree)]);
+        Ok(new_tree)
+    }
+
+    /// Makes a new connection to the server `tree` is on after the old one was lost, with a new
+    /// session and the trees that were connected on it connected again. Returns the tree to use in
+    /// place of `tree`.
+    async fn reconnect(&mut self, tree: Tree) -> Result<Tree> {
+        let Some(connector) = &mut self.connector else {
+            return Err(Error::NtStatus(NtStatus::ConnectionDisconnected));
+        };
+        let old = &self.connections[tree.connection];
+        let server = old.server.clone();
+        let ipc_tree = old.ipc_tree_id.map(|tree_id| Tree {
+            connection: tree.connection,
+            tree_id,
+        });
+        info!(server, "connecting again");
+        let transport = connector.connect(&server).await?;
+        let metrics = ConnectionMetrics::new(&server, self.metrics.clone());
+        let mut auth_client =
+            AuthenticatedClient::new(transport, &self.username, &self.password, metrics).await?;
+        auth_client.unauth_client.timeout = self.timeout;
+        auth_client.unauth_client.async_timeout = self.async_timeout;
+        self.connections[tree.connection] = Connection {
+            server,
+            auth_client,
+            ipc_tree_id: None,
+        };
+
+        let mut old_trees: Vec<(String, Tree)> = vec![(self.tree_path.clone(), self.tree)];
+        old_trees.extend(self.dfs_trees.iter().cloned());
+        old_trees.retain(|(_, t)| t.connection == tree.connection);
+        let mut renamed = vec![];
+        for (path, old_tree) in old_trees {
+            let tree_id = self.connection(old_tree).tree_connect(&path).await?;
+            let new_tree = Tree {
+                tree_id,
+                ..old_tree
+            };
+            renamed.push((old_tree, new_tree));
+        }
+        if let Some(old_tree) = ipc_tree {
+            renamed.push((old_tree, self.ipc_tree(tree.connection).await?));
+        }
+        self.replace_trees(&renamed);
+        Ok(renamed
+            .iter()
+            .find(|(old_tree, _)| *old_tree == tree)
+            .map_or(tree, |(_, new_tree)| *new_tree))
+    }
+
+    /// Replaces each old tree with its new one wherever it is remembered, all at once since the
+    /// new tree ids may be ones the old trees had.
+    fn replace_trees(&mut self, renamed: &[(Tree, Tree)]) {
+        let replace = |tree: &mut Tree| {
+            if let Some((_, new_tree)) = renamed.iter().find(|(old_tree, _)| old_tree == tree) {
+                *tree = *new_tree;
+            }
+        };
+        replace(&mut self.tree);
+        self.dfs_trees
+            .iter_mut()
+            .for_each(|(_, tree)| replace(tree));
+        self.opens.values_mut().for_each(|(tree, _)| replace(tree));
+    }
+
+    /// Starts tracking `file_id`, just opened on `tree`, returning the id to hand out for it.
+    fn add_open(&mut self, tree: Tree, file_id: FileId) -> FileId {
+        let mut handle = file_id;
+        while self.opens.contains_key(&handle) {
+            handle = FileId {
+                persistent: u64::MAX,
+                volatile: self.next_alias,
+            };
+            self.next_alias = self.next_alias.wrapping_add(1);
+        }
+        self.opens.insert(handle, (tree, file_id));
+        handle
+    }
+
+    /// The tree `handle` is open on and the id the server knows it by.
+    fn route(&self, handle: FileId) -> (Tree, FileId) {
+        self.opens
+            .get(&handle)
+            .copied()
+            .unwrap_or((self.tree, handle))
+    }
+
+    /// Stops tracking `handle`, for when it is closed or taken over by something which keeps
+    /// its own tree, like a `File`.
+    fn take_open(&mut self, handle: FileId) -> (Tree, FileId) {
+        self.opens.remove(&handle).unwrap_or((self.tree, handle))
+    }
+
+    /// Connects to the IPC$ share of the server the first time it is needed.
+    async fn ipc_tree(&mut self, connection: usize) -> Result<Tree> {
+        let conn = &mut self.connections[connection];
+        let tree_id = match conn.ipc_tree_id {
+            Some(tree_id) => tree_id,
+            None => {
+                let path = format!("\\\\{}\\IPC$", conn.server);
+                let tree_id = conn.auth_client.tree_connect(&path).await?;
+                conn.ipc_tree_id = Some(tree_id);
+                tree_id
+            }
+        };
+        Ok(Tree {
+            connection,
+            tree_id,
+        })
+    }
+
+    /// The path of `tree`, like `\\server\share`.
+    fn tree_path_of(&self, tree: Tree) -> &str {
+        if tree == self.tree {
+            return &self.tree_path;
+        }
+        self.dfs_trees
+            .iter()
+            .find(|(_, t)| *t == tree)
+            .map(|(path, _)| path.as_str())
+            .unwrap_or_default()
+    }
+
+    /// Connects to `share` on `server`, reusing an existing connection to the server.
+    async fn dfs_tree(&mut self, server: &str, share: &str) -> Result<Tree> {
+        let path = format!("\\\\{server}\\{share}");
+        if path.eq_ignore_ascii_case(&self.tree_path) {
+            return Ok(self.tree);
+        }
+        if let Some((_, tree)) = self
+            .dfs_trees
+            .iter()
+            .find(|(p, _)| p.eq_ignore_ascii_case(&path))
+        {
+            return Ok(*tree);
+        }
+
+        let connection = match self
+            .connections
+            .iter()
+            .position(|c| c.server.eq_ignore_ascii_case(server))
+        {
+            Some(connection) => connection,
+            None => {
+                let Some(connector) = &mut self.connector else {
+                    return Err(Error::NtStatus(NtStatus::PathNotCovered));
+                };
+                info!(server, "connecting for DFS");
+                let transport = connector.connect(server).await?;
+                let metrics = ConnectionMetrics::new(server, self.metrics.clone());
+                let mut auth_client =
+                    AuthenticatedClient::new(transport, &self.username, &self.password, metrics)
+                        .await?;
+                auth_client.unauth_client.timeout = self.timeout;
+                auth_client.unauth_client.async_timeout = self.async_timeout;
+                self.connections.push(Connection {
+                    server: server.into(),
+                    auth_client,
+                    ipc_tree_id: None,
+                });
+                self.connections.len() - 1
+            }
+        };
+
+        let tree_id = self.connections[connection]
             .auth_client
-            .request(
-                Some(self.tree_id),
-                Credits(1),
-                Credits(64),
-                CreateRequest {
-                    requested_oplock_level: OplockLevel::None,
-                    impersonation_level: ImpersonationLevel::Impersonation,
-                    desired_access: AccessMask::GENERIC_READ
-                        | AccessMask::GENERIC_WRITE
-                        | AccessMask::FILE_READ_ATTRIBUTES,
-                    file_attributes: FileAttributes::empty(),
-                    share_access: FileShareAccess::READ
-                        | FileShareAccess::WRITE
-                        | FileShareAccess::DELETE,
-                    create_disposition: FileCreateDisposition::Open,
-                    create_options: FileCreateOptions::empty(),
-                    name: path_str(path),
-                    create_contexts: vec![],
-                },
-            )
+            .tree_connect(&path)
             .await?;
-        Ok(response.file_id)
+        let tree = Tree {
+            connection,
+            tree_id,
+        };
+        self.dfs_trees.push((path, tree));
+        Ok(tree)
     }
 
-    pub async fn create_file(&mut self, path: impl AsRef<Path>) -> Result<FileId> {
-        let (_, response): (_, CreateResponse) = self
-            .auth_client
-            .request(
-                Some(self.tree_id),
-                Credits(1),
-                Credits(64),
-                CreateRequest {
-                    requested_oplock_level: OplockLevel::None,
-                    impersonation_level: ImpersonationLevel::Impersonation,
-                    desired_access: AccessMask::GENERIC_WRITE | AccessMask::FILE_READ_ATTRIBUTES,
-                    file_attributes: FileAttributes::empty(),
-                    share_access: FileShareAccess::READ
-                        | FileShareAccess::WRITE
-                        | FileShareAccess::DELETE,
-                    create_disposition: FileCreateDisposition::Create,
-                    create_options: FileCreateOptions::NON_DIRECTORY_FILE,
-                    name: path_str(path),
-                    create_contexts: vec![],
-                },
+    /// Asks the server where the DFS path `dfs_path` (like `\server\share\link`) is served.
+    pub async fn get_dfs_referrals(&mut self, dfs_path: &str) -> Result<DfsReferralResponse> {
+        self.get_dfs_referrals_on(0, dfs_path).await
+    }
+
+    async fn get_dfs_referrals_on(
+        &mut self,
+        connection: usize,
+        dfs_path: &str,
+    ) -> Result<DfsReferralResponse> {
+        let tree = self.ipc_tree(connection).await?;
+        let request = DfsGetReferralsRequest {
+            max_referral_level: DFS_MAX_REFERRAL_LEVEL,
+            request_file_name: dfs_path.into(),
+        };
+        let (_, response) = self
+            .ioctl_on(
+                tree,
+                FileId::UNSPECIFIED,
+                CtlCode::FsctlDfsGetReferrals,
+                request.to_bytes(),
+                IO_SIZE as u32,
             )
             .await?;
+        Ok(DfsReferralResponse::from_bytes(&response.output)?)
+    }
+
+    /// Finds the tree and path within it that `dfs_path` refers to as of `now`, trying each
+    /// target in turn.
+    async fn resolve_dfs_path(
+        &mut self,
+        dfs_path: &str,
+        now: Instant,
+    ) -> Result<Option<(Tree, String)>> {
+        let mut last_error = None;
+        for target in self.referrals.resolve(dfs_path, now) {
+            let Some((server, share, rest)) = dfs::split_path(&target) else {
+                continue;
+            };
+            match self.dfs_tree(server, share).await {
+                Ok(tree) => return Ok(Some((tree, rest.into()))),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        match last_error {
+            Some(e) => Err(e),
+            None => Ok(None),
+        }
+    }
+
+    /// The DFS path of `name` on `tree`, like `\server\share\name`. Only trees connected
+    /// with the server in their path have one.
+    fn dfs_path(&self, tree: Tree, name: &str) -> Option<String> {
+        let tree_path = self.tree_path_of(tree).strip_prefix('\\')?;
+        if !tree_path.starts_with('\\') {
+            return None;
+        }
+        Some(if name.is_empty() {
+            tree_path.into()
+        } else {
+            format!("{tree_path}\\{name}")
+        })
+    }
+
+    /// Sends `request` for `request.name` relative to the share, following DFS referrals when
+    /// the path crosses a DFS link and relative symbolic links the server stops on.
+    async fn create(&mut self, request: CreateRequest) -> Result<CreateResponse> {
+        let (_, _, response) = self.create_resolved(request).await?;
+        Ok(response)
+    }
+
+    /// Like `create`, but also returns the tree and name the file was finally opened as.
+    async fn create_resolved(
+        &mut self,
+        mut request: CreateRequest,
+    ) -> Result<(Tree, String, CreateResponse)> {
+        let mut tree = self.tree;
+        let mut referred = None;
+        let mut referred_at = None;
+        let mut referral_hops = 0;
+        let mut symlink_hops = 0;
+        loop {
+            // Right after a referral, resolve as of when it arrived so referrals that expire
+            // immediately still get used once.
+            let now = referred_at.take().unwrap_or_else(Instant::now);
+            if let Some(dfs_path) = self.dfs_path(tree, &request.name) {
+                if let Some(target) = self.resolve_dfs_path(&dfs_path, now).await? {
+                    (tree, request.name) = target;
+                }
+            }
+
+            let result = self
+                .connection(tree)
+                .request(Some(tree.tree_id), Credits(1), Credits(64), request.clone())
+                .await;
+            match result {
+                Ok((_, response)) => {
+                    let mut response: CreateResponse = response;
+                    response.file_id = self.add_open(tree, response.file_id);
+                    return Ok((tree, request.name, response));
+                }
+                Err(e)
+                    if e.nt_status() == Some(NtStatus::PathNotCovered)
+                        && referral_hops < MAX_REFERRAL_HOPS =>
+                {
+                    referral_hops += 1;
+                    debug!(path = request.name, "asking for a DFS referral");
+                    // Give up if there is no referral to ask for, or the last one didn't help.
+                    let dfs_path = self
+                        .dfs_path(tree, &request.name)
+                        .filter(|p| referred.as_ref() != Some(p))
+                        .ok_or(Error::NtStatus(NtStatus::PathNotCovered))?;
+                    let referrals = self
+                        .get_dfs_referrals_on(tree.connection, &dfs_path)
+                        .await?;
+                    let now = Instant::now();
+                    self.referrals.insert(&dfs_path, &referrals, now);
+                    referred = Some(dfs_path);
+                    referred_at = Some(now);
+                }
+                Err(e) => {
+                    let link = e.error_contexts().iter().find_map(|c| match c {
+                        ErrorContext::SymbolicLink(link) => Some(link),
+                        _ => None,
+                    });
+                    match link.and_then(|link| follow_symlink(&request.name, link)) {
+                        Some(name) if symlink_hops < MAX_SYMLINK_HOPS => {
+                            symlink_hops += 1;
+                            debug!(from = request.name, to = name, "following symbolic link");
+                            request.name = name;
+                        }
+                        _ => return Err(e.with_path(&request.name)),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Opens the existing file or directory at `path` for reading and writing. Use `OpenOptions`
+    /// for anything else.
+    pub async fn look_up(&mut self, path: impl AsRef<Path>) -> Result<FileId> {
+        OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open_file_id(self, path)
+            .await
+    }
+
+    /// Opens the existing file or directory at `path` asking only for `desired_access`.
+    pub async fn open_with_access(
+        &mut self,
+        path: impl AsRef<Path>,
+        desired_access: AccessMask,
+    ) -> Result<FileId> {
+        let response = self
+            .create(CreateRequest {
+                requested_oplock_level: OplockLevel::None,
+                impersonation_level: ImpersonationLevel::Impersonation,
+                desired_access,
+                file_attributes: FileAttributes::empty(),
+                share_access: FileShareAccess::READ
+                    | FileShareAccess::WRITE
+                    | FileShareAccess::DELETE,
+                create_disposition: FileCreateDisposition::Open,
+                create_options: FileCreateOptions::empty(),
+                name: path_str(path)?,
+                create_contexts: vec![],
+            })
+            .await?;
         Ok(response.file_id)
     }
 
+    /// Creates a new file at `path`, open for writing.
+    pub async fn create_file(&mut self, path: impl AsRef<Path>) -> Result<FileId> {
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .create_options(FileCreateOptions::NON_DIRECTORY_FILE)
+            .open_file_id(self, path)
+            .await
+    }
+
     pub async fn delete(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let file_id = self.open_with_access(path, AccessMask::DELETE).await?;
+        let result = self.delete_open(file_id).await;
+        self.close(file_id).await?;
+        result
+    }
+
+    /// Deletes the file or empty directory `file_id`, which must be open with DELETE access. Where
+    /// the server supports POSIX semantics the name goes away as soon as `file_id` is closed, even
+    /// if it is open elsewhere. Otherwise it goes away once every open is closed.
+    pub async fn delete_open(&mut self, file_id: FileId) -> Result<()> {
+        let flags = DispositionFlags::DELETE | DispositionFlags::POSIX_SEMANTICS;
+        match self.set_disposition(file_id, flags).await {
+            Err(e) if is_unsupported_info_class(&e) => self.set_delete_pending(file_id, true).await,
+            result => result,
+        }
+    }
+
+    /// Marks `file_id` to be deleted once every open of it is closed, or clears that mark.
+    pub async fn set_delete_pending(
+        &mut self,
+        file_id: FileId,
+        delete_pending: bool,
+    ) -> Result<()> {
+        self.set_info(file_id, FileDispositionInformation { delete_pending })
+            .await
+    }
+
+    pub async fn set_disposition(
+        &mut self,
+        file_id: FileId,
+        flags: DispositionFlags,
+    ) -> Result<()> {
+        self.set_info(file_id, FileDispositionInformationEx { flags })
+            .await
+    }
+
+    /// Creates a directory at `path`, failing if something is already there.
+    pub async fn create_dir(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        self.make_dir(path_str(path)?, FileCreateDisposition::Create)
+            .await
+    }
+
+    /// Creates a directory at `path` along with any missing parents. Directories which already
+    /// exist are fine.
+    pub async fn create_dir_all(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path_str(path)?;
+        let mut end = 0;
+        for component in path.split('\\') {
+            end += component.len();
+            self.make_dir(path[..end].into(), FileCreateDisposition::OpenIf)
+                .await?;
+            end += 1;
+        }
+        Ok(())
+    }
+
+    async fn make_dir(
+        &mut self,
+        name: String,
+        create_disposition: FileCreateDisposition,
+    ) -> Result<()> {
+        let response = self
+            .create(CreateRequest {
+                requested_oplock_level: OplockLevel::None,
+                impersonation_level: ImpersonationLevel::Impersonation,
+                desired_access: AccessMask::FILE_READ_ATTRIBUTES,
+                file_attributes: FileAttributes::DIRECTORY,
+                share_access: FileShareAccess::READ
+                    | FileShareAccess::WRITE
+                    | FileShareAccess::DELETE,
+                create_disposition,
+                create_options: FileCreateOptions::DIRECTORY_FILE,
+                name,
+                create_contexts: vec![],
+            })
+            .await?;
+        self.close(response.file_id).await?;
+        Ok(())
+    }
+
+    /// Removes the empty directory at `path`.
+    pub async fn remove_dir(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let response = self
+            .create(open_for_delete(
+                path_str(path)?,
+                FileCreateOptions::DIRECTORY_FILE,
+            ))
+            .await?;
+        let result = self.delete_open(response.file_id).await;
+        self.close(response.file_id).await?;
+        result
+    }
+
+    /// Removes the directory at `path` and everything in it. Symbolic links inside are removed
+    /// rather than followed. Up to `REMOVE_CONCURRENCY` files are deleted at once.
+    pub async fn remove_dir_all(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let (tree, root, response) = self
+            .create_resolved(CreateRequest {
+                desired_access: AccessMask::FILE_LIST_DIRECTORY,
+                ..open_for_delete(path_str(path)?, FileCreateOptions::DIRECTORY_FILE)
+            })
+            .await?;
+        self.close(response.file_id).await?;
+
+        // Files are deleted one directory at a time, directories once they are empty, deepest
+        // first
+        let mut levels = vec![vec![root]];
+        while let Some(level) = levels.last() {
+            let mut next_level = vec![];
+            for dir in level.clone() {
+                let mut files = vec![];
+                for entry in self.list_dir_on(tree, &dir).await? {
+                    if entry.file_name == "." || entry.file_name == ".." {
+                        continue;
+                    }
+                    let name = join_name(&dir, &entry.file_name);
+                    if entry.file_attributes.contains(FileAttributes::DIRECTORY)
+                        && !entry
+                            .file_attributes
+                            .contains(FileAttributes::REPARSE_POINT)
+                    {
+                        next_level.push(name);
+                    } else {
+                        files.push(name);
+                    }
+                }
+                self.delete_all_on(tree, files, FileCreateOptions::empty())
+                    .await?;
+            }
+            if next_level.is_empty() {
+                break;
+            }
+            levels.push(next_level);
+        }
+
+        for level in levels.into_iter().rev() {
+            self.delete_all_on(tree, level, FileCreateOptions::DIRECTORY_FILE)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Lists the directory called `name` on `tree`.
+    async fn list_dir_on(
+        &mut self,
+        tree: Tree,
+        name: &str,
+    ) -> Result<Vec<FileIdBothDirectoryInformation>> {
         let (_, response): (_, CreateResponse) = self
-            .auth_client
+            .connection(tree)
             .request(
-                Some(self.tree_id),
+                Some(tree.tree_id),
                 Credits(1),
                 Credits(64),
                 CreateRequest {
-                    requested_oplock_level: OplockLevel::None,
-                    impersonation_level: ImpersonationLevel::Impersonation,
-                    desired_access: AccessMask::DELETE,
-                    file_attributes: FileAttributes::empty(),
-                    share_access: FileShareAccess::READ
-                        | FileShareAccess::WRITE
-                        | FileShareAccess::DELETE,
-                    create_disposition: FileCreateDisposition::Open,
-                    create_options: FileCreateOptions::DELETE_ON_CLOSE,
-                    name: path_str(path),
-                    create_contexts: vec![],
+                    desired_access: AccessMask::FILE_LIST_DIRECTORY,
+                    ..open_for_delete(name.into(), FileCreateOptions::DIRECTORY_FILE)
                 },
             )
             .await?;
-        self.close(response.file_id).await?;
-        Ok(())
+        let file_id = self.add_open(tree, response.file_id);
+        let entries = self.query_directory(file_id).await;
+        self.close(file_id).await?;
+        entries
+    }
+
+    /// Deletes everything in `names` on `tree`, keeping up to `REMOVE_CONCURRENCY` requests in
+    /// flight. Every name is tried, the first error is returned.
+    async fn delete_all_on(
+        &mut self,
+        tree: Tree,
+        names: Vec<String>,
+        create_options: FileCreateOptions,
+    ) -> Result<()> {
+        let mut first_error = None;
+        for chunk in names.chunks(REMOVE_CONCURRENCY) {
+            let opens: Vec<Result<CreateResponse>> = self
+                .request_all(
+                    tree,
+                    chunk.iter().map(|name| CreateRequest {
+                        create_options: create_options | FileCreateOptions::DELETE_ON_CLOSE,
+                        ..open_for_delete(name.clone(), create_options)
+                    }),
+                )
+                .await?;
+            let mut closes = vec![];
+            for open in opens {
+                match open {
+                    Ok(response) => closes.push(CloseRequest {
+                        flags: CloseFlags::empty(),
+                        file_id: response.file_id,
+                    }),
+                    Err(e) => {
+                        first_error.get_or_insert(e);
+                    }
+                }
+            }
+            let closes: Vec<Result<CloseResponse>> = self.request_all(tree, closes).await?;
+            if let Some(e) = closes.into_iter().find_map(Result::err) {
+                first_error.get_or_insert(e);
+            }
+        }
+        first_error.map_or(Ok(()), Err)
+    }
+
+    /// Sends all of `requests` on `tree` before waiting for any of the responses. The outer
+    /// error is for failing to talk to the server at all, the inner ones are per request.
+    async fn request_all<
+        T: Serialize + HasCommand + fmt::Debug,
+        R: DeserializeOwned + fmt::Debug,
+    >(
+        &mut self,
+        tree: Tree,
+        requests: impl IntoIterator<Item = T>,
+    ) -> Result<Vec<Result<R>>> {
+        let connection = self.connection(tree);
+        let mut message_ids = vec![];
+        for request in requests {
+            message_ids.push(
+                connection
+                    .send(Some(tree.tree_id), Credits(1), Credits(64), request)
+                    .await?,
+            );
+        }
+        let mut responses = vec![];
+        for message_id in message_ids {
+            responses.push(request_result(connection.receive(message_id).await)?);
+        }
+        Ok(responses)
     }
 
     pub async fn query_directory(
         &mut self,
         file_id: FileId,
     ) -> Result<Vec<FileIdBothDirectoryInformation>> {
+        let mut entries = self.query_directory_entries(file_id, QueryDirectoryOptions::new());
         let mut output = vec![];
-
-        loop {
-            let res = self
-                .auth_client
-                .request(
-                    Some(self.tree_id),
-                    Credits(1),
-                    Credits(64),
-                    QueryDirectoryRequest {
-                        file_information_class:
-                            FileInformationClass::FileIdFullDirectoryInformation,
-                        flags: QueryDirectoryFlags::empty(),
-                        file_index: 0,
-                        file_id,
-                        output_buffer_length: 15380,
-                        search_pattern: "*".into(),
-                    },
-                )
-                .await;
-            let (_, response): (_, QueryDirectoryResponse<FileIdBothDirectoryInformation>) =
-                match res {
-                    Ok(v) => v,
-                    Err(Error::NtStatus(NtStatus::NoMoreFiles)) => break,
-                    Err(e) => return Err(e),
-                };
-            output.extend(response.entries.into_iter().map(|e| e.body));
+        while let Some(entry) = entries.next_entry().await? {
+            output.push(entry);
         }
         Ok(output)
     }
 
+    /// Lists the directory `file_id` as `Info`, one of the directory information classes like
+    /// `FileNamesInformation`. Entries are fetched as they are read rather than all at once.
+    pub fn query_directory_entries<
+        Info: DeserializeOwned + fmt::Debug + HasFileInformationClass,
+    >(
+        &mut self,
+        file_id: FileId,
+        options: QueryDirectoryOptions,
+    ) -> DirectoryEntries<'_, TransportT, Info> {
+        DirectoryEntries::new(self, file_id, options)
+    }
+
+    /// Walks everything below the directory at `root`, see [`Walk`] for the options. Several
+    /// directories are listed at once, up to `WALK_CONCURRENCY`.
+    pub fn walk(&mut self, root: impl AsRef<Path>) -> Walk<'_, TransportT> {
+        Walk::new(self, path_str(root))
+    }
+
     pub async fn write(&mut self, file_id: FileId, offset: u64, data: Vec<u8>) -> Result<u32> {
+        let (tree, file_id) = self.route(file_id);
+        self.write_on(tree, file_id, offset, data).await
+    }
+
+    async fn write_on(
+        &mut self,
+        tree: Tree,
+        file_id: FileId,
+        offset: u64,
+        data: Vec<u8>,
+    ) -> Result<u32> {
         let (_, response): (_, WriteResponse) = self
-            .auth_client
+            .connection(tree)
             .request(
-                Some(self.tree_id),
+                Some(tree.tree_id),
                 Credits(1),
                 Credits(64),
                 WriteRequest {
@@ -520,29 +2111,27 @@ impl<TransportT: Transport> Client<TransportT> {
     }
 
     pub async fn read(&mut self, file_id: FileId, offset: u64, count: u32) -> Result<Vec<u8>> {
-        let (_, response): (_, ReadResponse) = self
-            .auth_client
-            .request(
-                Some(self.tree_id),
-                Credits(1),
-                Credits(9),
-                ReadRequest {
-                    padding: 0,
-                    flags: ReadFlags::empty(),
-                    length: count,
-                    offset,
-                    file_id,
-                    minimum_bytes: 0,
-                    channel: Channel::None,
-                    remaining_bytes: 0,
-                    // this can't be empty for some reason
-                    channel_data: vec![0],
-                },
-            )
-            .await?;
+        let (tree, file_id) = self.route(file_id);
+        let (_, response) = self.read_on(tree, file_id, offset, count).await?;
         Ok(response.data)
     }
 
+    async fn read_on(
+        &mut self,
+        tree: Tree,
+        file_id: FileId,
+        offset: u64,
+        count: u32,
+    ) -> Result<(ResponseHeader, ReadResponse)> {
+        self.request_idempotent(
+            tree,
+            Credits(1),
+            Credits(9),
+            read_request(file_id, offset, count),
+        )
+        .await
+    }
+
     pub async fn read_all(
         &mut self,
         file_id: FileId,
@@ -555,42 +2144,110 @@ impl<TransportT: Transport> Client<TransportT> {
                     offset += read_data.len() as u64;
                     sink.write_all(&read_data).await?;
                 }
-                Err(Error::NtStatus(NtStatus::EndOfFile)) => break,
+                Err(e) if e.nt_status() == Some(NtStatus::EndOfFile) => break,
                 Err(e) => return Err(e),
             }
         }
         Ok(())
     }
 
-    pub async fn query_info<Info: DeserializeOwned + HasFileInformationClass>(
+    pub async fn query_info<Info: DeserializeOwned + fmt::Debug + HasFileInformationClass>(
         &mut self,
         file_id: FileId,
     ) -> Result<Info> {
-        let (_, response): (_, QueryInfoResponse<Info>) = self
-            .auth_client
-            .request(
-                Some(self.tree_id),
-                Credits(1),
-                Credits(64),
-                QueryInfoRequest {
-                    info_type: InfoType::File,
-                    file_info_class: Info::file_information_class(),
-                    output_buffer_length: 8293,
-                    additional_information: 0,
-                    flags: QueryInfoFlags::empty(),
-                    file_id,
-                    buffer: vec![],
+        let (tree, file_id) = self.route(file_id);
+        self.query_info_on(tree, file_id).await
+    }
+
+    async fn query_info_on<Info: DeserializeOwned + fmt::Debug + HasFileInformationClass>(
+        &mut self,
+        tree: Tree,
+        file_id: FileId,
+    ) -> Result<Info> {
+        self.query_info_raw(tree, file_id, Info::file_information_class(), 0)
+            .await
+    }
+
+    /// Queries information about the volume holding `file_id`, any open file on the share will do.
+    pub async fn query_fs_info<Info: DeserializeOwned + fmt::Debug + HasFsInformationClass>(
+        &mut self,
+        file_id: FileId,
+    ) -> Result<Info> {
+        let (tree, file_id) = self.route(file_id);
+        self.query_info_raw(tree, file_id, Info::fs_information_class(), 0)
+            .await
+    }
+
+    async fn query_info_raw<Class, Info>(
+        &mut self,
+        tree: Tree,
+        file_id: FileId,
+        info_class: Class,
+        additional_information: u32,
+    ) -> Result<Info>
+    where
+        Class: InfoClass + Serialize + Clone + fmt::Debug,
+        Info: DeserializeOwned + fmt::Debug,
+    {
+        self.query_info_request(
+            tree,
+            QueryInfoRequest {
+                info_type: Class::info_type(),
+                file_info_class: info_class,
+                output_buffer_length: 8293,
+                additional_information,
+                flags: QueryInfoFlags::empty(),
+                file_id,
+                buffer: vec![],
+            },
+        )
+        .await
+    }
+
+    /// Sends `request`, growing its output buffer as long as the server says it is too small.
+    async fn query_info_request<Class, Info>(
+        &mut self,
+        tree: Tree,
+        mut request: QueryInfoRequest<Class>,
+    ) -> Result<Info>
+    where
+        Class: Serialize + Clone + fmt::Debug,
+        Info: DeserializeOwned + fmt::Debug,
+    {
+        loop {
+            let result: Result<(_, QueryInfoResponse<Info>)> = self
+                .request_idempotent(tree, Credits(1), Credits(64), request.clone())
+                .await;
+            match result {
+                Ok((_, response)) => return Ok(response.info),
+                // Retry with the size the server asked for, as long as it is asking for more
+                Err(e) => match e.error_contexts() {
+                    [ErrorContext::BufferTooSmall(size)]
+                        if *size > request.output_buffer_length =>
+                    {
+                        request.output_buffer_length = *size;
+                    }
+                    _ => return Err(e),
                 },
-            )
-            .await?;
-        Ok(response.info)
+            }
+        }
     }
 
     pub async fn close(&mut self, file_id: FileId) -> Result<CloseResponse> {
+        let (tree, file_id) = self.take_open(file_id);
+        self.close_on(tree, file_id).await
+    }
+
+    /// The files to close along with the next request on the connection of `tree`.
+    fn deferred_closes(&mut self, tree: Tree) -> DeferredCloses {
+        self.connection(tree).deferred_closes.clone()
+    }
+
+    async fn close_on(&mut self, tree: Tree, file_id: FileId) -> Result<CloseResponse> {
         let (_, response): (_, CloseResponse) = self
-            .auth_client
+            .connection(tree)
             .request(
-                Some(self.tree_id),
+                Some(tree.tree_id),
                 Credits(1),
                 Credits(64),
                 CloseRequest {
@@ -603,10 +2260,15 @@ impl<TransportT: Transport> Client<TransportT> {
     }
 
     pub async fn flush(&mut self, file_id: FileId) -> Result<()> {
+        let (tree, file_id) = self.route(file_id);
+        self.flush_on(tree, file_id).await
+    }
+
+    async fn flush_on(&mut self, tree: Tree, file_id: FileId) -> Result<()> {
         let (_, _response): (_, FlushResponse) = self
-            .auth_client
+            .connection(tree)
             .request(
-                Some(self.tree_id),
+                Some(tree.tree_id),
                 Credits(1),
                 Credits(64),
                 FlushRequest { file_id },
@@ -615,21 +2277,46 @@ impl<TransportT: Transport> Client<TransportT> {
         Ok(())
     }
 
-    pub async fn set_info<Info: Serialize + HasFileInformationClass>(
+    pub async fn set_info<Info: Serialize + fmt::Debug + HasFileInformationClass>(
         &mut self,
         file_id: FileId,
         info: Info,
+    ) -> Result<()> {
+        let (tree, file_id) = self.route(file_id);
+        self.set_info_on(tree, file_id, info).await
+    }
+
+    async fn set_info_on<Info: Serialize + fmt::Debug + HasFileInformationClass>(
+        &mut self,
+        tree: Tree,
+        file_id: FileId,
+        info: Info,
+    ) -> Result<()> {
+        self.set_info_raw(tree, file_id, Info::file_information_class(), 0, info)
+            .await
+    }
+
+    async fn set_info_raw<
+        Class: InfoClass + Serialize + fmt::Debug,
+        Info: Serialize + fmt::Debug,
+    >(
+        &mut self,
+        tree: Tree,
+        file_id: FileId,
+        info_class: Class,
+        additional_information: u32,
+        info: Info,
     ) -> Result<()> {
         let (_, _response): (_, SetInfoResponse) = self
-            .auth_client
+            .connection(tree)
             .request(
-                Some(self.tree_id),
+                Some(tree.tree_id),
                 Credits(1),
                 Credits(64),
                 SetInfoRequest {
-                    info_type: InfoType::File,
-                    file_info_class: Info::file_information_class(),
-                    additional_information: 0,
+                    info_type: Class::info_type(),
+                    file_info_class: info_class,
+                    additional_information,
                     file_id,
                     info,
                 },
@@ -638,21 +2325,1229 @@ impl<TransportT: Transport> Client<TransportT> {
         Ok(())
     }
 
+    /// Reads the parts of the security descriptor of `file_id` selected by `info`. The file must
+    /// be open with READ_CONTROL, and ACCESS_SYSTEM_SECURITY for the SACL.
+    pub async fn query_security(
+        &mut self,
+        file_id: FileId,
+        info: SecurityInformation,
+    ) -> Result<SecurityDescriptor> {
+        let (tree, file_id) = self.route(file_id);
+        let data: Vec<u8> = self
+            .query_info_raw(tree, file_id, SecurityInformationClass::None, info.bits())
+            .await?;
+        Ok(SecurityDescriptor::from_bytes(&data)?)
+    }
+
+    /// Replaces the parts of the security descriptor of `file_id` selected by `info`. The file
+    /// must be open with the matching WRITE_OWNER, WRITE_DAC or ACCESS_SYSTEM_SECURITY access.
+    pub async fn apply_security(
+        &mut self,
+        file_id: FileId,
+        descriptor: &SecurityDescriptor,
+        info: SecurityInformation,
+    ) -> Result<()> {
+        let (tree, file_id) = self.route(file_id);
+        self.set_info_raw(
+            tree,
+            file_id,
+            SecurityInformationClass::None,
+            info.bits(),
+            descriptor.to_bytes(),
+        )
+        .await
+    }
+
+    /// Reads the owner, group, DACL and/or SACL of the file or directory at `path`.
+    pub async fn get_security(
+        &mut self,
+        path: impl AsRef<Path>,
+        info: SecurityInformation,
+    ) -> Result<SecurityDescriptor> {
+        let mut access = AccessMask::READ_CONTROL;
+        if info.contains(SecurityInformation::SACL) {
+            access |= AccessMask::ACCESS_SYSTEM_SECURITY;
+        }
+        let file_id = self.open_with_access(path, access).await?;
+        let descriptor = self.query_security(file_id, info).await;
+        self.close(file_id).await?;
+        descriptor
+    }
+
+    /// Replaces the owner, group, DACL and/or SACL of the file or directory at `path` with the
+    /// ones from `descriptor`.
+    pub async fn set_security(
+        &mut self,
+        path: impl AsRef<Path>,
+        descriptor: &SecurityDescriptor,
+        info: SecurityInformation,
+    ) -> Result<()> {
+        let mut access = AccessMask::empty();
+        if info.intersects(
+            SecurityInformation::OWNER | SecurityInformation::GROUP | SecurityInformation::LABEL,
+        ) {
+            access |= AccessMask::WRITE_OWNER;
+        }
+        if info.contains(SecurityInformation::DACL) {
+            access |= AccessMask::WRITE_DAC;
+        }
+        if info.contains(SecurityInformation::SACL) {
+            access |= AccessMask::ACCESS_SYSTEM_SECURITY;
+        }
+        let file_id = self.open_with_access(path, access).await?;
+        let result = self.apply_security(file_id, descriptor, info).await;
+        self.close(file_id).await?;
+        result
+    }
+
+    /// Lists the data streams of the file at `path`, including the default one.
+    pub async fn list_streams(
+        &mut self,
+        path: impl AsRef<Path>,
+    ) -> Result<Vec<FileStreamInformation>> {
+        let file_id = self
+            .open_with_access(path, AccessMask::FILE_READ_ATTRIBUTES)
+            .await?;
+        let streams = self.query_info(file_id).await;
+        self.close(file_id).await?;
+        streams
+    }
+
+    /// Lists the extended attributes of `file_id`. The file must be open with FILE_READ_EA.
+    pub async fn list_eas(&mut self, file_id: FileId) -> Result<Vec<FileFullEaInformation>> {
+        self.query_eas(file_id, vec![]).await
+    }
+
+    async fn query_eas(
+        &mut self,
+        file_id: FileId,
+        names: Vec<FileGetEaInformation>,
+    ) -> Result<Vec<FileFullEaInformation>> {
+        let (tree, file_id) = self.route(file_id);
+        let result = self
+            .query_info_request(
+                tree,
+                QueryInfoRequest {
+                    info_type: InfoType::File,
+                    file_info_class: FileInformationClass::FileFullEaInformation,
+                    output_buffer_length: 65536,
+                    additional_information: 0,
+                    flags: QueryInfoFlags::RESTART_SCAN,
+                    file_id,
+                    buffer: serde_smb::to_vec(&names)?,
+                },
+            )
+            .await;
+        match result {
+            Err(e) if e.nt_status() == Some(NtStatus::NoEasOnFile) => Ok(vec![]),
+            result => result,
+        }
+    }
+
+    /// Reads the value of the extended attribute called `name` on `file_id`, if it has one.
+    pub async fn get_ea(&mut self, file_id: FileId, name: &str) -> Result<Option<Vec<u8>>> {
+        let names = vec![FileGetEaInformation {
+            ea_name: name.into(),
+        }];
+        // The server answers for a missing EA with an entry holding no value
+        let eas = self.query_eas(file_id, names).await?;
+        Ok(eas
+            .into_iter()
+            .find(|ea| ea.ea_name.eq_ignore_ascii_case(name.as_bytes()))
+            .map(|ea| ea.ea_value)
+            .filter(|value| !value.is_empty()))
+    }
+
+    /// Sets the extended attribute called `name` on `file_id`, or removes it when `value` is
+    /// empty. The file must be open with FILE_WRITE_EA.
+    pub async fn set_ea(&mut self, file_id: FileId, name: &str, value: &[u8]) -> Result<()> {
+        let (tree, file_id) = self.route(file_id);
+        self.set_info_on(tree, file_id, vec![FileFullEaInformation::new(name, value)])
+            .await
+    }
+
+    pub async fn remove_ea(&mut self, file_id: FileId, name: &str) -> Result<()> {
+        self.set_ea(file_id, name, &[]).await
+    }
+
     pub async fn rename(&mut self, file_id: FileId, path: impl AsRef<Path>) -> Result<()> {
         self.set_info(
             file_id,
             FileRenameInformation {
                 replace_if_exists: false,
-                path: path_str(path),
+                path: path_str(path)?,
             },
         )
         .await?;
         Ok(())
     }
 
+    /// Gives the file `file_id` another name at `path`, replacing what is there if `replace` is
+    /// set.
+    pub async fn hard_link(
+        &mut self,
+        file_id: FileId,
+        path: impl AsRef<Path>,
+        replace: bool,
+    ) -> Result<()> {
+        self.set_info(
+            file_id,
+            FileLinkInformation {
+                replace_if_exists: replace,
+                path: path_str(path)?,
+            },
+        )
+        .await
+    }
+
+    /// Lists every name of `file_id`, each as a name within a parent directory.
+    pub async fn hard_links(&mut self, file_id: FileId) -> Result<Vec<FileLinkEntryInformation>> {
+        let (tree, file_id) = self.route(file_id);
+        let data: Vec<u8> = self
+            .query_info_request(
+                tree,
+                QueryInfoRequest {
+                    info_type: InfoType::File,
+                    file_info_class: FileInformationClass::FileHardLinkInformation,
+                    output_buffer_length: 65536,
+                    additional_information: 0,
+                    flags: QueryInfoFlags::empty(),
+                    file_id,
+                    buffer: vec![],
+                },
+            )
+            .await?;
+        Ok(FileLinksInformation::from_bytes(&data)?.entries)
+    }
+
+    /// Renames `file_id` to `path`. With `RenameFlags::POSIX_SEMANTICS` a file being replaced is
+    /// unlinked even while it is open elsewhere.
+    pub async fn rename_ex(
+        &mut self,
+        file_id: FileId,
+        path: impl AsRef<Path>,
+        flags: RenameFlags,
+    ) -> Result<()> {
+        self.set_info(
+            file_id,
+            FileRenameInformationEx {
+                flags,
+                path: path_str(path)?,
+            },
+        )
+        .await
+    }
+
+    /// Sets the times of `file_id`, which must be open with FILE_WRITE_ATTRIBUTES.
+    pub async fn set_times(&mut self, file_id: FileId, times: FileTimes) -> Result<()> {
+        self.set_info(file_id, times.to_basic_information(FileAttributes::empty()))
+            .await
+    }
+
+    /// Replaces the attributes of `file_id`, leaving its times alone. An empty set clears every
+    /// attribute, it is sent as NORMAL because zero would mean leaving them unchanged.
+    pub async fn set_attributes(
+        &mut self,
+        file_id: FileId,
+        attributes: FileAttributes,
+    ) -> Result<()> {
+        let attributes = if attributes.is_empty() {
+            FileAttributes::NORMAL
+        } else {
+            attributes
+        };
+        self.set_info(file_id, FileTimes::new().to_basic_information(attributes))
+            .await
+    }
+
     pub async fn resize(&mut self, file_id: FileId, size: i64) -> Result<()> {
         self.set_info(file_id, FileEndOfFileInformation { end_of_file: size })
             .await?;
         Ok(())
     }
+
+    pub async fn ioctl(
+        &mut self,
+        file_id: FileId,
+        ctl_code: CtlCode,
+        input: Vec<u8>,
+        max_output_response: u32,
+    ) -> Result<Vec<u8>> {
+        let (tree, file_id) = self.route(file_id);
+        let (_, response) = self
+            .ioctl_on(tree, file_id, ctl_code, input, max_output_response)
+            .await?;
+        Ok(response.output)
+    }
+
+    async fn ioctl_on(
+        &mut self,
+        tree: Tree,
+        file_id: FileId,
+        ctl_code: CtlCode,
+        input: Vec<u8>,
+        max_output_response: u32,
+    ) -> Result<(ResponseHeader, IoctlResponse)> {
+        self.connection(tree)
+            .request(
+                Some(tree.tree_id),
+                Credits(1),
+                Credits(64),
+                ioctl_request(file_id, ctl_code, input, max_output_response),
+            )
+            .await
+    }
+
+    pub async fn get_reparse_point(&mut self, file_id: FileId) -> Result<ReparseData> {
+        let output = self
+            .ioctl(file_id, CtlCode::FsctlGetReparsePoint, vec![], 16384)
+            .await?;
+        Ok(ReparseData::from_bytes(&output)?)
+    }
+
+    pub async fn set_reparse_point(&mut self, file_id: FileId, data: &ReparseData) -> Result<()> {
+        self.ioctl(file_id, CtlCode::FsctlSetReparsePoint, data.to_bytes(), 0)
+            .await?;
+        Ok(())
+    }
+
+    /// Returns where the symbolic link or mount point at `path` points, as stored on the server.
+    pub async fn read_link(&mut self, path: impl AsRef<Path>) -> Result<String> {
+        let response = self
+            .create(CreateRequest {
+                requested_oplock_level: OplockLevel::None,
+                impersonation_level: ImpersonationLevel::Impersonation,
+                desired_access: AccessMask::FILE_READ_ATTRIBUTES,
+                file_attributes: FileAttributes::empty(),
+                share_access: FileShareAccess::READ
+                    | FileShareAccess::WRITE
+                    | FileShareAccess::DELETE,
+                create_disposition: FileCreateDisposition::Open,
+                create_options: FileCreateOptions::OPEN_REPARSE_POINT,
+                name: path_str(path)?,
+                create_contexts: vec![],
+            })
+            .await?;
+        let data = self.get_reparse_point(response.file_id).await;
+        self.close(response.file_id).await?;
+        match data? {
+            ReparseData::SymbolicLink(link) => Ok(link.substitute_name),
+            ReparseData::MountPoint(mount_point) => Ok(mount_point.substitute_name),
+            ReparseData::Other { .. } => Err(Error::NtStatus(NtStatus::NotAReparsePoint)),
+        }
+    }
+
+    async fn create_symlink(
+        &mut self,
+        target: &str,
+        link: impl AsRef<Path>,
+        create_options: FileCreateOptions,
+    ) -> Result<()> {
+        let link = path_str(link)?;
+        let response = self
+            .create(CreateRequest {
+                requested_oplock_level: OplockLevel::None,
+                impersonation_level: ImpersonationLevel::Impersonation,
+                desired_access: AccessMask::GENERIC_WRITE | AccessMask::DELETE,
+                file_attributes: FileAttributes::empty(),
+                share_access: FileShareAccess::READ
+                    | FileShareAccess::WRITE
+                    | FileShareAccess::DELETE,
+                create_disposition: FileCreateDisposition::Create,
+                create_options: create_options | FileCreateOptions::OPEN_REPARSE_POINT,
+                name: link.clone(),
+                create_contexts: vec![],
+            })
+            .await?;
+
+        let mut flags = SymbolicLinkFlags::empty();
+        if !target.starts_with('\\') {
+            flags |= SymbolicLinkFlags::RELATIVE;
+        }
+        let data = ReparseData::SymbolicLink(SymbolicLinkReparseData {
+            substitute_name: target.into(),
+            print_name: target.into(),
+            flags,
+        });
+        let result = self.set_reparse_point(response.file_id, &data).await;
+        self.close(response.file_id).await?;
+        if result.is_err() {
+            // Don't leave an empty file or directory behind
+            self.delete(&link).await?;
+        }
+        result
+    }
+
+    /// Creates a symbolic link at `link` pointing to the file `target`. A target not starting
+    /// with a backslash is relative to the directory containing the link.
+    pub async fn symlink(&mut self, target: &str, link: impl AsRef<Path>) -> Result<()> {
+        self.create_symlink(target, link, FileCreateOptions::NON_DIRECTORY_FILE)
+            .await
+    }
+
+    /// Like [`Self::symlink`] but for a target which is a directory.
+    pub async fn symlink_dir(&mut self, target: &str, link: impl AsRef<Path>) -> Result<()> {
+        self.create_symlink(target, link, FileCreateOptions::DIRECTORY_FILE)
+            .await
+    }
+
+    /// Waits for an instance of the named pipe `name` to be available for opening. The server
+    /// picks the timeout if none is given.
+    pub async fn wait_pipe(&mut self, name: &str, timeout: Option<Duration>) -> Result<()> {
+        let tree = self.ipc_tree(0).await?;
+        let request = FsctlPipeWaitRequest {
+            timeout: timeout.map(|t| (t.as_nanos() / 100) as i64).unwrap_or(0),
+            timeout_specified: timeout.is_some(),
+            name: name.into(),
+        };
+        self.ioctl_on(
+            tree,
+            FileId::UNSPECIFIED,
+            CtlCode::FsctlPipeWait,
+            serde_smb::to_vec(&request)?,
+            0,
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Opens the named pipe `name` (for example "srvsvc") on the IPC$ share of the server.
+    pub async fn open_pipe(&mut self, name: &str) -> Result<NamedPipe<'_, TransportT>> {
+        let tree = self.ipc_tree(0).await?;
+        let (_, response): (_, CreateResponse) = self
+            .connection(tree)
+            .request(
+                Some(tree.tree_id),
+                Credits(1),
+                Credits(64),
+                CreateRequest {
+                    requested_oplock_level: OplockLevel::None,
+                    impersonation_level: ImpersonationLevel::Impersonation,
+                    desired_access: AccessMask::FILE_READ_DATA
+                        | AccessMask::FILE_WRITE_DATA
+                        | AccessMask::FILE_APPEND_DATA
+                        | AccessMask::FILE_READ_EA
+                        | AccessMask::FILE_WRITE_EA
+                        | AccessMask::FILE_READ_ATTRIBUTES
+                        | AccessMask::FILE_WRITE_ATTRIBUTES
+                        | AccessMask::READ_CONTROL
+                        | AccessMask::SYNCHRONIZE,
+                    file_attributes: FileAttributes::empty(),
+                    share_access: FileShareAccess::READ | FileShareAccess::WRITE,
+                    create_disposition: FileCreateDisposition::Open,
+                    create_options: FileCreateOptions::empty(),
+                    name: name.into(),
+                    create_contexts: vec![],
+                },
+            )
+            .await?;
+        Ok(NamedPipe::new(self, tree, response.file_id))
+    }
+
+    /// Lists the shares on the server, using the srvsvc RPC interface.
+    pub async fn list_shares(&mut self) -> Result<Vec<ShareInfo>> {
+        let server_name = format!("\\\\{}", self.connections[0].server);
+        let mut pipe = self.open_pipe(rpc::srvsvc::PIPE_NAME).await?;
+        let shares = async {
+            let mut rpc = rpc::RpcClient::bind(&mut pipe, &rpc::srvsvc::SRVSVC_SYNTAX).await?;
+            rpc::srvsvc::share_enum_all(&mut rpc, Some(&server_name)).await
+        }
+        .await;
+        pipe.close().await?;
+        shares
+    }
+}
+
+#[test]
+fn follow_relative_symlink() {
+    let link =
+        |substitute_name: &str, unparsed_path_length: u16, flags| SymbolicLinkErrorResponse {
+            unparsed_path_length,
+            substitute_name: substitute_name.into(),
+            print_name: substitute_name.into(),
+            flags,
+        };
+
+    // a\\link\\file where "link" points to "..\\b"
+    assert_eq!(
+        follow_symlink(
+            "a\\link\\file",
+            &link("..\\b", 10, SymbolicLinkFlags::RELATIVE)
+        ),
+        Some("b\\file".into())
+    );
+    assert_eq!(
+        follow_symlink("a\\link", &link(".\\c\\d", 0, SymbolicLinkFlags::RELATIVE)),
+        Some("a\\c\\d".into())
+    );
+
+    // Leaving the share or following an absolute link isn't possible
+    assert_eq!(
+        follow_symlink("link", &link("..\\x", 0, SymbolicLinkFlags::RELATIVE)),
+        None
+    );
+    assert_eq!(
+        follow_symlink("link", &link("\\??\\C:\\x", 0, SymbolicLinkFlags::empty())),
+        None
+    );
+}
+
+#[test]
+fn stream_aware_paths() {
+    assert_eq!(path_str("/a/./b\\c").unwrap(), "a\\b\\c");
+    assert_eq!(path_str("a:b").unwrap(), "a:b");
+    assert_eq!(
+        path_str("/dir/file:Zone.Identifier:$DATA").unwrap(),
+        "dir\\file:Zone.Identifier:$DATA"
+    );
+    assert_eq!(
+        stream_path("/dir/file", "Zone.Identifier")
+            .unwrap()
+            .as_str(),
+        "dir\\file:Zone.Identifier"
+    );
+    assert_eq!(path_str("a/../.."), Err(PathError::EscapesShare));
+}
+
+#[test]
+fn request_error_messages() {
+    let error = Error::Request(Box::new(RequestError {
+        status: NtStatus::ObjectNameNotFound,
+        command: Command::Create,
+        message_id: MessageId(12),
+        path: None,
+        contexts: vec![],
+    }))
+    .with_path("dir\\file");
+    assert_eq!(
+        error.to_string(),
+        "Create of \"dir\\\\file\" failed (message 12): ObjectNameNotFound (0xc0000034): \
+         The object name is not found."
+    );
+    let error = std::io::Error::from(error);
+    assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+
+    assert_eq!(
+        Error::NtStatus(NtStatus::Toomanyuids).to_string(),
+        "Toomanyuids (0xc000205a)"
+    );
+    assert_eq!(
+        Error::NtStatus(NtStatus::ObjectNameCollision).kind(),
+        std::io::ErrorKind::AlreadyExists
+    );
+}
+
+#[test]
+fn split_compound_responses() {
+    let header = |chain_offset, message_id| ResponseHeader {
+        protocol_id: ProtocolId::new(),
+        header_length: 64,
+        credit_charge: Credits(1),
+        nt_status: NtStatus::Success,
+        command: Command::Close,
+        credits_granted: Credits(1),
+        flags: HeaderFlags::new().with_response(true),
+        chain_offset,
+        message_id: MessageId(message_id),
+        process_id: ProcessId(0),
+        tree_id: TreeId(1),
+        session_id: SessionId(1),
+        signature: Signature([0; 16]),
+    };
+    let mut frame = serde_smb::to_vec(&header(72, 1)).unwrap();
+    frame.extend([0xaa; 8]);
+    frame.extend(serde_smb::to_vec(&header(0, 2)).unwrap());
+    frame.extend([0xbb; 4]);
+    assert_eq!(
+        split_compound(frame.clone()).unwrap(),
+        vec![frame[..72].to_vec(), frame[72..].to_vec()]
+    );
+
+    let frame = serde_smb::to_vec(&header(200, 1)).unwrap();
+    assert!(split_compound(frame).is_err());
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn timed_out_receive_keeps_connection_in_sync() {
+    let response = |message_id| {
+        let header = ResponseHeader {
+            protocol_id: ProtocolId::new(),
+            header_length: 64,
+            credit_charge: Credits(1),
+            nt_status: NtStatus::Success,
+            command: Command::Flush,
+            credits_granted: Credits(1),
+            flags: HeaderFlags::new().with_response(true),
+            chain_offset: 0,
+            message_id: MessageId(message_id),
+            process_id: ProcessId(0),
+            tree_id: TreeId(1),
+            session_id: SessionId(1),
+            signature: Signature([1; 16]),
+        };
+        let message = serde_smb::to_vec(&(header, FlushResponse)).unwrap();
+        let mut frame = (message.len() as u32).to_be_bytes().to_vec();
+        frame.extend(message);
+        frame
+    };
+    let (transport, mut server) = io::duplex(4096);
+    let mut client = UnauthenticatedClient::new(transport);
+    client.timeout = Some(Duration::from_millis(10));
+
+    // Half of the response to 1 arrives while waiting for 0
+    let frame = response(1);
+    server.write_all(&frame[..30]).await.unwrap();
+    assert_matches::assert_matches!(
+        client.receive::<FlushResponse>(MessageId(0)).await,
+        Err(Error::TimedOut(MessageId(0)))
+    );
+    let mut sig_func = |_: &[u8]| Ok(Signature([2; 16]));
+    client
+        .queue_cancel(MessageId(0), SessionId(1), &mut sig_func)
+        .unwrap();
+    client.flush_writes().await.unwrap();
+    let mut cancel = [0; 4 + 68];
+    server.read_exact(&mut cancel).await.unwrap();
+    let (header, CancelRequest): (RequestHeader, CancelRequest) =
+        serde_smb::from_slice(&cancel[4..]).unwrap();
+    assert_eq!(
+        (header.command, header.message_id),
+        (Command::Cancel, MessageId(0))
+    );
+
+    // The late response to 0 is dropped and the rest of 1 is picked up
+    server.write_all(&frame[30..]).await.unwrap();
+    server.write_all(&response(0)).await.unwrap();
+    let (header, _): (_, FlushResponse) = client.receive(MessageId(1)).await.unwrap();
+    assert_eq!(header.message_id, MessageId(1));
+    client.timeout = None;
+    server.write_all(&response(2)).await.unwrap();
+    let (header, _): (_, FlushResponse) = client.receive(MessageId(2)).await.unwrap();
+    assert_eq!(header.message_id, MessageId(2));
+    assert!(client.unclaimed.is_empty());
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn pending_requests_time_out_with_the_async_timeout() {
+    let async_id = AsyncId(0x1234_0000_5678);
+    let (process_id, tree_id) = async_id.to_ids();
+    let header = ResponseHeader {
+        protocol_id: ProtocolId::new(),
+        header_length: 64,
+        credit_charge: Credits(1),
+        nt_status: NtStatus::Pending,
+        command: Command::Flush,
+        credits_granted: Credits(1),
+        flags: HeaderFlags::new().with_response(true).with_async(true),
+        chain_offset: 0,
+        message_id: MessageId(0),
+        process_id,
+        tree_id,
+        session_id: SessionId(1),
+        signature: Signature([1; 16]),
+    };
+    let message = serde_smb::to_vec(&(
+        header,
+        ErrorResponse {
+            error_context_count: 0,
+            error_data: vec![],
+        },
+    ))
+    .unwrap();
+    let (transport, mut server) = io::duplex(4096);
+    let mut client = UnauthenticatedClient::new(transport);
+    client.timeout = Some(Duration::from_millis(10));
+    client.async_timeout = Some(Duration::from_millis(50));
+    server
+        .write_all(&(message.len() as u32).to_be_bytes())
+        .await
+        .unwrap();
+    server.write_all(&message).await.unwrap();
+
+    // The interim response gives the request longer than the timeout, but not forever
+    let started = tokio::time::Instant::now();
+    assert_matches::assert_matches!(
+        client.receive::<FlushResponse>(MessageId(0)).await,
+        Err(Error::TimedOut(MessageId(0)))
+    );
+    assert!(started.elapsed() >= Duration::from_millis(50));
+
+    let mut sig_func = |_: &[u8]| Ok(Signature([2; 16]));
+    client
+        .queue_cancel(MessageId(0), SessionId(1), &mut sig_func)
+        .unwrap();
+    client.flush_writes().await.unwrap();
+    let mut cancel = [0; 4 + 68];
+    server.read_exact(&mut cancel).await.unwrap();
+    let (header, CancelRequest): (RequestHeader, CancelRequest) =
+        serde_smb::from_slice(&cancel[4..]).unwrap();
+    assert!(header.flags.r#async());
+    assert_eq!(
+        AsyncId::from_ids(header.process_id, header.tree_id),
+        async_id
+    );
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn metrics_count_requests_and_responses() {
+    let frame = |message_id, nt_status| {
+        let header = ResponseHeader {
+            protocol_id: ProtocolId::new(),
+            header_length: 64,
+            credit_charge: Credits(1),
+            nt_status,
+            command: Command::Flush,
+            credits_granted: Credits(1),
+            flags: HeaderFlags::new().with_response(true),
+            chain_offset: 0,
+            message_id: MessageId(message_id),
+            process_id: ProcessId(0),
+            tree_id: TreeId(1),
+            session_id: SessionId(1),
+            signature: Signature([1; 16]),
+        };
+        let message = serde_smb::to_vec(&(header, FlushResponse)).unwrap();
+        let mut frame = (message.len() as u32).to_be_bytes().to_vec();
+        frame.extend(message);
+        frame
+    };
+    let metrics = Arc::new(InMemoryMetrics::new());
+    let (transport, mut server) = io::duplex(4096);
+    let mut client = UnauthenticatedClient::new(transport);
+    client.metrics = ConnectionMetrics::new("server", metrics.clone());
+
+    // The second request waits for the response to the first to grant it a credit
+    let flush = FlushRequest {
+        file_id: FileId::LAST,
+    };
+    for message_id in 0..2 {
+        client
+            .send(Credits(1), Credits(1), None, None, None, flush.clone())
+            .await
+            .unwrap();
+        let mut request = [0; 4 + 88];
+        server.read_exact(&mut request).await.unwrap();
+        let status = [NtStatus::Success, NtStatus::AccessDenied][message_id as usize];
+        server.write_all(&frame(message_id, status)).await.unwrap();
+    }
+    assert_eq!(client.credits, 0);
+    client.receive::<FlushResponse>(MessageId(0)).await.unwrap();
+    assert_eq!(
+        client
+            .receive::<FlushResponse>(MessageId(1))
+            .await
+            .unwrap_err()
+            .nt_status(),
+        Some(NtStatus::AccessDenied)
+    );
+
+    let counters = metrics.connection("server");
+    assert_eq!(counters.requests(Command::Flush), 2);
+    assert_eq!(counters.bytes_written, 2 * 88);
+    assert_eq!(
+        counters.bytes_read,
+        2 * (frame(0, NtStatus::Success).len() as u64 - 4)
+    );
+    assert_eq!(counters.credit_stalls, 1);
+    assert_eq!(counters.latencies[&Command::Flush].len(), 2);
+    assert_eq!(
+        counters.errors,
+        HashMap::from([(NtStatus::AccessDenied, 1)])
+    );
+    assert_eq!(counters.timeouts, 0);
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn lost_connection_is_reported() {
+    let (transport, server) = io::duplex(4096);
+    let mut client = UnauthenticatedClient::new(transport);
+    drop(server);
+    let result = client.receive::<FlushResponse>(MessageId(0)).await;
+    let error = result.unwrap_err();
+    assert_matches::assert_matches!(error, Error::Disconnected(_));
+    assert!(!error.is_transient());
+    assert_eq!(error.kind(), std::io::ErrorKind::ConnectionAborted);
+}
+
+#[test]
+fn transient_errors() {
+    assert!(Error::TimedOut(MessageId(3)).is_transient());
+    assert!(Error::OutOfCredits(Command::Read).is_transient());
+    assert!(Error::NtStatus(NtStatus::InsufficientResources).is_transient());
+    let request_error = |status| {
+        Error::Request(Box::new(RequestError {
+            status,
+            command: Command::Read,
+            message_id: MessageId(3),
+            path: None,
+            contexts: vec![],
+        }))
+    };
+    assert!(request_error(NtStatus::NetworkBusy).is_transient());
+    // The tree connect is gone, sending the same request again only works once it is connected
+    // again, which retrying requests does
+    assert!(!request_error(NtStatus::NetworkNameDeleted).is_transient());
+    assert!(!request_error(NtStatus::AccessDenied).is_transient());
+    assert!(!Error::Io(io::ErrorKind::InvalidInput.into()).is_transient());
+}
+
+#[cfg(test)]
+fn test_auth_client(transport: io::DuplexStream) -> AuthenticatedClient<io::DuplexStream> {
+    AuthenticatedClient {
+        unauth_client: UnauthenticatedClient::new(transport),
+        session_id: SessionId(1),
+        signing_key: vec![0; 16],
+        deferred_closes: DeferredCloses::default(),
+    }
+}
+
+/// A client with tree 1 connected on each of `transports`, the first being its share.
+#[cfg(test)]
+fn test_client(transports: Vec<io::DuplexStream>) -> Client<io::DuplexStream> {
+    Client {
+        connections: transports
+            .into_iter()
+            .map(|transport| Connection {
+                server: String::new(),
+                auth_client: test_auth_client(transport),
+                ipc_tree_id: None,
+            })
+            .collect(),
+        tree_path: String::new(),
+        tree: Tree {
+            connection: 0,
+            tree_id: TreeId(1),
+        },
+        username: String::new(),
+        password: String::new(),
+        connector: None,
+        referrals: ReferralCache::new(),
+        dfs_trees: vec![],
+        opens: HashMap::new(),
+        next_alias: 0,
+        timeout: None,
+        async_timeout: None,
+        metrics: Arc::new(NoopMetrics),
+        retry_policy: RetryPolicy::default(),
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn partial_reads_only_where_asked_for() {
+    let (transport, mut server) = io::duplex(4096);
+    let mut client = test_auth_client(transport);
+    for message_id in 0..2 {
+        let header = ResponseHeader {
+            protocol_id: ProtocolId::new(),
+            header_length: 64,
+            credit_charge: Credits(1),
+            nt_status: NtStatus::BufferOverflow,
+            command: Command::Read,
+            credits_granted: Credits(1),
+            flags: HeaderFlags::new().with_response(true),
+            chain_offset: 0,
+            message_id: MessageId(message_id),
+            process_id: ProcessId(0),
+            tree_id: TreeId(1),
+            session_id: SessionId(1),
+            signature: Signature([1; 16]),
+        };
+        let response = ReadResponse {
+            data_remaining: 0,
+            flags: ReadResponseFlags::None,
+            data: vec![1, 2, 3],
+        };
+        let message = serde_smb::to_vec(&(header, response)).unwrap();
+        server
+            .write_all(&(message.len() as u32).to_be_bytes())
+            .await
+            .unwrap();
+        server.write_all(&message).await.unwrap();
+    }
+
+    let result: Result<(_, ReadResponse)> = client.receive(MessageId(0)).await;
+    assert_eq!(
+        result.unwrap_err().nt_status(),
+        Some(NtStatus::BufferOverflow)
+    );
+    let (header, response): (_, ReadResponse) = client.receive_partial(MessageId(1)).await.unwrap();
+    assert_eq!(header.nt_status, NtStatus::BufferOverflow);
+    assert_eq!(response.data, [1, 2, 3]);
+}
+
+#[test]
+fn file_ids_taken_on_another_connection_are_aliased() {
+    let tree = |connection| Tree {
+        connection,
+        tree_id: TreeId(1),
+    };
+    let mut client = test_client(vec![]);
+    let file_id = FileId {
+        persistent: 1,
+        volatile: 2,
+    };
+    let local = client.add_open(tree(0), file_id);
+    let remote = client.add_open(tree(1), file_id);
+    assert_eq!(local, file_id);
+    assert_ne!(remote, file_id);
+    assert_eq!(client.route(local), (tree(0), file_id));
+    assert_eq!(client.route(remote), (tree(1), file_id));
+
+    assert_eq!(client.take_open(local), (tree(0), file_id));
+    assert_eq!(client.route(remote), (tree(1), file_id));
+    assert_eq!(client.add_open(tree(1), file_id), file_id);
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn dropping_a_busy_file_still_closes_it() {
+    let (transport, _server) = io::duplex(4096);
+    let mut client = test_client(vec![transport]);
+    let file_id = FileId {
+        persistent: 1,
+        volatile: 2,
+    };
+    let mut file = client.file(file_id);
+    // The server never answers, so the write is still in progress when the file is dropped
+    let write = tokio::time::timeout(Duration::from_millis(10), file.write(b"data")).await;
+    assert!(write.is_err());
+    drop(file);
+    assert_eq!(
+        *client.connections[0]
+            .auth_client
+            .deferred_closes
+            .lock()
+            .unwrap(),
+        [(TreeId(1), file_id)]
+    );
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn files_on_another_connection_flush_there() {
+    let (transport0, _server0) = io::duplex(4096);
+    let (transport1, mut server1) = io::duplex(4096);
+    let mut client = test_client(vec![transport0, transport1]);
+    let file_id = FileId {
+        persistent: 1,
+        volatile: 2,
+    };
+    let remote = client.add_open(
+        Tree {
+            connection: 1,
+            tree_id: TreeId(7),
+        },
+        file_id,
+    );
+    let mut file = client.file(remote);
+    // The server never answers, only the request matters
+    let flush = tokio::time::timeout(Duration::from_millis(10), file.flush()).await;
+    assert!(flush.is_err());
+    let header = tokio::time::timeout(Duration::from_secs(1), read_request_header(&mut server1))
+        .await
+        .expect("flush sent on the wrong connection");
+    assert_eq!(header.command, Command::Flush);
+    assert_eq!(header.tree_id, TreeId(7));
+}
+
+/// The header of a response to `message_id` with `status`, on tree 1 of session 1.
+#[cfg(test)]
+fn response_header(message_id: MessageId, command: Command, status: NtStatus) -> ResponseHeader {
+    ResponseHeader {
+        protocol_id: ProtocolId::new(),
+        header_length: 64,
+        credit_charge: Credits(1),
+        nt_status: status,
+        command,
+        credits_granted: Credits(1),
+        flags: HeaderFlags::new().with_response(true),
+        chain_offset: 0,
+        message_id,
+        process_id: ProcessId(0),
+        tree_id: TreeId(1),
+        session_id: SessionId(1),
+        signature: Signature([1; 16]),
+    }
+}
+
+/// A response with `header` and `body`, as sent over the transport.
+#[cfg(test)]
+fn response_frame(header: &ResponseHeader, body: impl Serialize) -> Vec<u8> {
+    let message = serde_smb::to_vec(&(header, body)).unwrap();
+    let mut frame = (message.len() as u32).to_be_bytes().to_vec();
+    frame.extend(message);
+    frame
+}
+
+/// A response to `message_id` failing with `status`, as sent over the transport.
+#[cfg(test)]
+fn error_frame(message_id: MessageId, command: Command, status: NtStatus) -> Vec<u8> {
+    let body = ErrorResponse {
+        error_context_count: 0,
+        error_data: vec![],
+    };
+    response_frame(&response_header(message_id, command, status), body)
+}
+
+/// Reads the next request sent to the server, returning its header.
+#[cfg(test)]
+async fn read_request_header(server: &mut io::DuplexStream) -> RequestHeader {
+    serde_smb::from_slice(&read_message(server).await).unwrap()
+}
+
+/// Reads the next request sent to the server, returning its header and body.
+#[cfg(test)]
+async fn receive_request<T: DeserializeOwned>(server: &mut io::DuplexStream) -> (RequestHeader, T) {
+    serde_smb::from_slice(&read_message(server).await).unwrap()
+}
+
+#[cfg(test)]
+async fn read_message(server: &mut io::DuplexStream) -> Vec<u8> {
+    let mut len = [0; 4];
+    server.read_exact(&mut len).await.unwrap();
+    let mut message = vec![0; u32::from_be_bytes(len) as usize];
+    server.read_exact(&mut message).await.unwrap();
+    message
+}
+
+/// Plays the server's part in negotiating and setting up a session, without checking who the
+/// client says it is.
+#[cfg(test)]
+async fn accept_session(server: &mut io::DuplexStream) {
+    use sspi::ServerRequestFlags;
+
+    let (header, _): (_, NegotiateRequest) = receive_request(server).await;
+    let negotiate = NegotiateResponse {
+        security_mode: SecurityMode::SIGNING_ENABLED,
+        dialect: Dialect::Smb3_1_1,
+        server_guid: Uuid::new(&mut rand::thread_rng()),
+        capabilities: Capabilities::empty(),
+        max_transaction_size: IO_SIZE as u32,
+        max_read_size: IO_SIZE as u32,
+        max_write_size: IO_SIZE as u32,
+        current_time: Time { intervals: 0 },
+        boot_time: Time { intervals: 0 },
+        security_blob: vec![],
+        negotiate_contexts: vec![NegotiateContext::Smb2PreauthIntegrityCapabilities(
+            Smb2PreauthIntegrityCapabilities {
+                data_length: 38,
+                hash_algorithms: vec![HashAlgorithm::Sha512],
+                salt: vec![0; 32],
+            },
+        )],
+    };
+    let header = response_header(header.message_id, Command::Negotiate, NtStatus::Success);
+    server
+        .write_all(&response_frame(&header, negotiate))
+        .await
+        .unwrap();
+
+    let mut ntlm = Ntlm::new();
+    let mut credentials = ntlm
+        .acquire_credentials_handle()
+        .with_credential_use(CredentialUse::Inbound)
+        .execute()
+        .unwrap()
+        .credentials_handle;
+    let (header, request): (_, SessionSetupRequest) = receive_request(server).await;
+    let mut input = vec![SecurityBuffer::new(
+        request.security_blob,
+        SecurityBufferType::Token,
+    )];
+    let mut output = vec![SecurityBuffer::new(vec![], SecurityBufferType::Token)];
+    ntlm.accept_security_context()
+        .with_credentials_handle(&mut credentials)
+        .with_context_requirements(ServerRequestFlags::ALLOCATE_MEMORY)
+        .with_target_data_representation(DataRepresentation::Native)
+        .with_input(&mut input)
+        .with_output(&mut output)
+        .execute()
+        .unwrap();
+    let challenge = SessionSetupResponse {
+        flags: SessionFlags::empty(),
+        security_blob: output.pop().unwrap().buffer,
+    };
+    let status = NtStatus::MoreProcessingRequired;
+    let header = response_header(header.message_id, Command::SessionSetup, status);
+    server
+        .write_all(&response_frame(&header, challenge))
+        .await
+        .unwrap();
+
+    let (header, _): (_, SessionSetupRequest) = receive_request(server).await;
+    let done = SessionSetupResponse {
+        flags: SessionFlags::empty(),
+        security_blob: vec![],
+    };
+    let header = response_header(header.message_id, Command::SessionSetup, NtStatus::Success);
+    server
+        .write_all(&response_frame(&header, done))
+        .await
+        .unwrap();
+}
+
+/// Answers the next request, which must be a TREE_CONNECT for `path`, with `tree_id`.
+#[cfg(test)]
+async fn accept_tree_connect(server: &mut io::DuplexStream, path: &str, tree_id: TreeId) {
+    let (header, request): (_, TreeConnectRequest) = receive_request(server).await;
+    assert_eq!(request.path, path);
+    let mut header = response_header(header.message_id, Command::TreeConnect, NtStatus::Success);
+    header.tree_id = tree_id;
+    let response = TreeConnectResponse {
+        share_type: ShareType::Disk,
+        share_flags: ShareFlags::empty(),
+        share_capabilities: ShareCapabilities::empty(),
+        access_mask: AccessMask::empty(),
+    };
+    server
+        .write_all(&response_frame(&header, response))
+        .await
+        .unwrap();
+}
+
+/// Answers the next request, which must be a READ on `tree_id`, with `data`.
+#[cfg(test)]
+async fn accept_read(server: &mut io::DuplexStream, tree_id: TreeId, data: &[u8]) {
+    let header = read_request_header(server).await;
+    assert_eq!(header.command, Command::Read);
+    assert_eq!(header.tree_id, tree_id);
+    let mut response_header = response_header(header.message_id, Command::Read, NtStatus::Success);
+    response_header.tree_id = tree_id;
+    let response = ReadResponse {
+        data_remaining: 0,
+        flags: ReadResponseFlags::None,
+        data: data.into(),
+    };
+    server
+        .write_all(&response_frame(&response_header, response))
+        .await
+        .unwrap();
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn reads_are_retried_on_a_tree_connected_again() {
+    let (transport, mut server) = io::duplex(4096);
+    let mut client = test_client(vec![transport]);
+    client.tree_path = r"\\server\share".into();
+    let file_id = FileId {
+        persistent: 1,
+        volatile: 2,
+    };
+    let serve = async {
+        let header = read_request_header(&mut server).await;
+        let status = NtStatus::NetworkNameDeleted;
+        server
+            .write_all(&error_frame(header.message_id, Command::Read, status))
+            .await
+            .unwrap();
+        accept_tree_connect(&mut server, r"\\server\share", TreeId(5)).await;
+        accept_read(&mut server, TreeId(5), b"data").await;
+    };
+    let (data, ()) = tokio::join!(client.read(file_id, 0, 4), serve);
+    assert_eq!(data.unwrap(), b"data");
+    assert_eq!(client.tree.tree_id, TreeId(5));
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn reads_are_retried_on_a_new_connection() {
+    let (transport, server) = io::duplex(4096);
+    drop(server);
+    let mut client = test_client(vec![transport]);
+    client.tree_path = r"\\server\share".into();
+    client.connections[0].server = "server".into();
+    client.username = "user".into();
+    client.password = "password".into();
+    let (new_transport, mut new_server) = io::duplex(4096);
+    let mut new_transport = Some(new_transport);
+    client.set_connector(move |server: &str| {
+        assert_eq!(server, "server");
+        let transport = new_transport.take();
+        async move { transport.ok_or_else(|| io::ErrorKind::ConnectionRefused.into()) }
+    });
+    let handle = client.add_open(
+        client.tree,
+        FileId {
+            persistent: 1,
+            volatile: 2,
+        },
+    );
+
+    let serve = async {
+        accept_session(&mut new_server).await;
+        accept_tree_connect(&mut new_server, r"\\server\share", TreeId(5)).await;
+        accept_read(&mut new_server, TreeId(5), b"data").await;
+    };
+    let (data, ()) = tokio::join!(client.read(handle, 0, 4), serve);
+    assert_eq!(data.unwrap(), b"data");
+    assert_eq!(client.route(handle).0.tree_id, TreeId(5));
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn requests_fail_without_credits_in_time() {
+    let (transport, _server) = io::duplex(4096);
+    let mut client = test_auth_client(transport);
+    client.unauth_client.credits = 0;
+    client.unauth_client.outstanding.insert(MessageId(0));
+    client.unauth_client.timeout = Some(Duration::from_millis(10));
+    let file_id = FileId {
+        persistent: 1,
+        volatile: 2,
+    };
+    let result = client
+        .request::<_, FlushResponse>(
+            Some(TreeId(1)),
+            Credits(1),
+            Credits(1),
+            FlushRequest { file_id },
+        )
+        .await;
+    let error = result.unwrap_err();
+    assert_matches::assert_matches!(error, Error::OutOfCredits(Command::Flush));
+    assert!(error.is_transient());
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn pipes_are_closed_even_after_a_failed_write() {
+    let (transport, mut server) = io::duplex(4096);
+    let mut client = test_client(vec![transport]);
+    let tree = client.tree;
+    let file_id = FileId {
+        persistent: 1,
+        volatile: 2,
+    };
+    let mut pipe = NamedPipe::new(&mut client, tree, file_id);
+    let write = tokio::time::timeout(Duration::from_millis(10), pipe.write(b"data")).await;
+    assert!(write.is_err());
+
+    let server = async {
+        let write = read_request_header(&mut server).await;
+        let frame = error_frame(write.message_id, Command::Write, NtStatus::PipeBroken);
+        server.write_all(&frame).await.unwrap();
+        let close = read_request_header(&mut server).await;
+        let frame = error_frame(close.message_id, Command::Close, NtStatus::PipeBroken);
+        server.write_all(&frame).await.unwrap();
+        close.command
+    };
+    let (closed, command) = tokio::join!(pipe.close(), server);
+    assert_eq!(command, Command::Close);
+    assert_eq!(closed.unwrap_err().nt_status(), Some(NtStatus::PipeBroken));
+
+    drop(NamedPipe::new(&mut client, tree, file_id));
+    assert_eq!(
+        *client.connections[0]
+            .auth_client
+            .deferred_closes
+            .lock()
+            .unwrap(),
+        [(TreeId(1), file_id)]
+    );
 }
@@ -0,0 +1,174 @@
+use smb3::{Command, MessageId, NtStatus, RequestHeader, ResponseHeader};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Receives what each connection of a [`Client`](crate::Client) does, to export as metrics. Each
+/// call names the server the connection is to. Every method does nothing by default.
+pub trait Metrics: Send + Sync {
+    /// A request was sent, `bytes` long including its header.
+    fn request_sent(&self, _server: &str, _command: Command, _bytes: usize) {}
+
+    /// The final response to a request arrived, `latency` after the request was sent.
+    fn response_received(
+        &self,
+        _server: &str,
+        _command: Command,
+        _status: NtStatus,
+        _bytes: usize,
+        _latency: Duration,
+    ) {
+    }
+
+    /// A request charging `charge` credits had to wait for the server to grant more than the
+    /// `available` ones.
+    fn credit_stall(&self, _server: &str, _command: Command, _charge: u16, _available: u32) {}
+
+    /// No response to a request came in time, and it is being cancelled.
+    fn request_timed_out(&self, _server: &str, _command: Command) {}
+}
+
+/// Metrics which go nowhere, the default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoopMetrics;
+
+impl Metrics for NoopMetrics {}
+
+/// What one connection did, as counted by [`InMemoryMetrics`].
+#[derive(Clone, Debug, Default)]
+pub struct ConnectionCounters {
+    pub requests: HashMap<Command, u64>,
+    pub bytes_written: u64,
+    pub bytes_read: u64,
+    pub credit_stalls: u64,
+    /// The latency of each response, by the command of the request
+    pub latencies: HashMap<Command, Vec<Duration>>,
+    /// Responses with an error status
+    pub errors: HashMap<NtStatus, u64>,
+    pub timeouts: u64,
+}
+
+impl ConnectionCounters {
+    /// How many requests of `command` were sent.
+    pub fn requests(&self, command: Command) -> u64 {
+        self.requests.get(&command).copied().unwrap_or(0)
+    }
+}
+
+/// Metrics counted in memory, by server, for tests and inspection.
+#[derive(Debug, Default)]
+pub struct InMemoryMetrics {
+    connections: Mutex<HashMap<String, ConnectionCounters>>,
+}
+
+impl InMemoryMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// What the connection to `server` has done so far.
+    pub fn connection(&self, server: &str) -> ConnectionCounters {
+        let connections = self.connections.lock().unwrap();
+        connections.get(server).cloned().unwrap_or_default()
+    }
+
+    fn update(&self, server: &str, f: impl FnOnce(&mut ConnectionCounters)) {
+        let mut connections = self.connections.lock().unwrap();
+        f(connections.entry(server.into()).or_default())
+    }
+}
+
+impl Metrics for InMemoryMetrics {
+    fn request_sent(&self, server: &str, command: Command, bytes: usize) {
+        self.update(server, |c| {
+            *c.requests.entry(command).or_default() += 1;
+            c.bytes_written += bytes as u64;
+        })
+    }
+
+    fn response_received(
+        &self,
+        server: &str,
+        command: Command,
+        status: NtStatus,
+        bytes: usize,
+        latency: Duration,
+    ) {
+        self.update(server, |c| {
+            c.bytes_read += bytes as u64;
+            c.latencies.entry(command).or_default().push(latency);
+            if status.is_error() {
+                *c.errors.entry(status).or_default() += 1;
+            }
+        })
+    }
+
+    fn credit_stall(&self, server: &str, _command: Command, _charge: u16, _available: u32) {
+        self.update(server, |c| c.credit_stalls += 1)
+    }
+
+    fn request_timed_out(&self, server: &str, _command: Command) {
+        self.update(server, |c| c.timeouts += 1)
+    }
+}
+
+/// Reports what one connection does to a [`Metrics`].
+pub(crate) struct ConnectionMetrics {
+    server: String,
+    pub(crate) metrics: Arc<dyn Metrics>,
+    /// What each request waiting for a response is, and when it was sent
+    sent: HashMap<MessageId, (Command, Instant)>,
+}
+
+impl ConnectionMetrics {
+    pub(crate) fn new(server: &str, metrics: Arc<dyn Metrics>) -> Self {
+        Self {
+            server: server.into(),
+            metrics,
+            sent: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn sent(&mut self, header: &RequestHeader, bytes: usize) {
+        self.metrics
+            .request_sent(&self.server, header.command, bytes);
+        // A cancel shares the message id of the request it cancels
+        if header.command == Command::Cancel {
+            return;
+        }
+        self.sent
+            .insert(header.message_id, (header.command, Instant::now()));
+    }
+
+    pub(crate) fn received(&mut self, header: &ResponseHeader, bytes: usize) {
+        if header.nt_status == NtStatus::Pending {
+            return;
+        }
+        if let Some((command, sent)) = self.sent.remove(&header.message_id) {
+            self.metrics.response_received(
+                &self.server,
+                command,
+                header.nt_status,
+                bytes,
+                sent.elapsed(),
+            );
+        }
+    }
+
+    pub(crate) fn credit_stall(&self, command: Command, charge: u16, available: u32) {
+        self.metrics
+            .credit_stall(&self.server, command, charge, available);
+    }
+
+    pub(crate) fn timed_out(&self, message_id: MessageId) {
+        if let Some((command, _)) = self.sent.get(&message_id) {
+            self.metrics.request_timed_out(&self.server, *command);
+        }
+    }
+}
+
+impl Default for ConnectionMetrics {
+    fn default() -> Self {
+        Self::new("", Arc::new(NoopMetrics))
+    }
+}
@@ -0,0 +1,53 @@
+use super::Client;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+/// An operation which has the client until it finishes, then hands it back with its result.
+pub(crate) type OpFuture<'client, TransportT, R> =
+    Pin<Box<dyn Future<Output = (&'client mut Client<TransportT>, R)> + 'client>>;
+
+/// A client lent to one operation at a time, for things driven through poll functions like
+/// `AsyncRead`, which can't borrow the client across calls.
+pub(crate) enum OpState<'client, TransportT, R> {
+    Idle(&'client mut Client<TransportT>),
+    Busy(OpFuture<'client, TransportT, R>),
+}
+
+impl<'client, TransportT, R: 'client> OpState<'client, TransportT, R> {
+    pub(crate) fn is_idle(&self) -> bool {
+        matches!(self, Self::Idle(_))
+    }
+
+    /// Lends the client to `op`, which must not be called while another operation is in
+    /// progress.
+    pub(crate) fn start(
+        &mut self,
+        op: impl FnOnce(&'client mut Client<TransportT>) -> OpFuture<'client, TransportT, R>,
+    ) {
+        // Temporarily swap in a future that is never polled, so the client can be moved out.
+        let state = std::mem::replace(self, Self::Busy(Box::pin(std::future::pending())));
+        let Self::Idle(client) = state else {
+            panic!("operation started while another is in progress");
+        };
+        *self = Self::Busy(op(client));
+    }
+
+    /// Drives any in-progress operation to completion, returning its result.
+    pub(crate) fn poll(&mut self, cx: &mut Context<'_>) -> Poll<Option<R>> {
+        let Self::Busy(op) = self else {
+            return Poll::Ready(None);
+        };
+        let (client, result) = ready!(op.as_mut().poll(cx));
+        *self = Self::Idle(client);
+        Poll::Ready(Some(result))
+    }
+
+    /// The client, as long as no operation is in progress.
+    pub(crate) fn client(&mut self) -> Option<&mut Client<TransportT>> {
+        match self {
+            Self::Idle(client) => Some(client),
+            Self::Busy(_) => None,
+        }
+    }
+}
@@ -0,0 +1,247 @@
+use super::{path_str, Client, File, Result, Transport};
+use smb3::{
+    AccessMask, CreateContext, CreateRequest, FileAttributes, FileCreateDisposition,
+    FileCreateOptions, FileId, FileShareAccess, ImpersonationLevel, OplockLevel,
+};
+use std::io;
+use std::path::Path;
+
+/// How to open a file, along the lines of `std::fs::OpenOptions`. By default an existing file is
+/// opened just to read its attributes, shared with everyone else.
+#[derive(Clone, Debug)]
+pub struct OpenOptions {
+    read: bool,
+    write: bool,
+    append: bool,
+    truncate: bool,
+    create: bool,
+    create_new: bool,
+    access: AccessMask,
+    share_access: FileShareAccess,
+    create_options: FileCreateOptions,
+    impersonation_level: ImpersonationLevel,
+    oplock_level: OplockLevel,
+    attributes: FileAttributes,
+    create_contexts: Vec<CreateContext>,
+}
+
+impl Default for OpenOptions {
+    fn default() -> Self {
+        Self {
+            read: false,
+            write: false,
+            append: false,
+            truncate: false,
+            create: false,
+            create_new: false,
+            access: AccessMask::FILE_READ_ATTRIBUTES,
+            share_access: FileShareAccess::READ | FileShareAccess::WRITE | FileShareAccess::DELETE,
+            create_options: FileCreateOptions::empty(),
+            impersonation_level: ImpersonationLevel::Impersonation,
+            oplock_level: OplockLevel::None,
+            attributes: FileAttributes::empty(),
+            create_contexts: vec![],
+        }
+    }
+}
+
+fn invalid_input(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+impl OpenOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn read(mut self, read: bool) -> Self {
+        self.read = read;
+        self
+    }
+
+    pub fn write(mut self, write: bool) -> Self {
+        self.write = write;
+        self
+    }
+
+    /// Write only at the end of the file. Without `write` as well, writes through a `File` go to
+    /// the end of the file wherever its position is.
+    pub fn append(mut self, append: bool) -> Self {
+        self.append = append;
+        self
+    }
+
+    /// Empty the file if it exists, which needs `write`.
+    pub fn truncate(mut self, truncate: bool) -> Self {
+        self.truncate = truncate;
+        self
+    }
+
+    /// Create the file if it doesn't exist, which needs `write` or `append`.
+    pub fn create(mut self, create: bool) -> Self {
+        self.create = create;
+        self
+    }
+
+    /// Create the file, failing if it exists already. `create` and `truncate` are then ignored.
+    pub fn create_new(mut self, create_new: bool) -> Self {
+        self.create_new = create_new;
+        self
+    }
+
+    /// Ask for `access` on top of what reading and writing need, like `DELETE` or `WRITE_DAC`.
+    pub fn access(mut self, access: AccessMask) -> Self {
+        self.access |= access;
+        self
+    }
+
+    /// What others may do with the file while it is open. The default is to share everything.
+    pub fn share_access(mut self, share_access: FileShareAccess) -> Self {
+        self.share_access = share_access;
+        self
+    }
+
+    pub fn create_options(mut self, create_options: FileCreateOptions) -> Self {
+        self.create_options = create_options;
+        self
+    }
+
+    pub fn impersonation_level(mut self, impersonation_level: ImpersonationLevel) -> Self {
+        self.impersonation_level = impersonation_level;
+        self
+    }
+
+    pub fn oplock_level(mut self, oplock_level: OplockLevel) -> Self {
+        self.oplock_level = oplock_level;
+        self
+    }
+
+    /// The attributes to give the file if it gets created or overwritten.
+    pub fn attributes(mut self, attributes: FileAttributes) -> Self {
+        self.attributes = attributes;
+        self
+    }
+
+    pub fn create_context(mut self, create_context: CreateContext) -> Self {
+        self.create_contexts.push(create_context);
+        self
+    }
+
+    fn desired_access(&self) -> AccessMask {
+        let mut access = self.access;
+        if self.read {
+            access |= AccessMask::GENERIC_READ;
+        }
+        if self.write {
+            access |= AccessMask::GENERIC_WRITE;
+        }
+        if self.append {
+            access |= AccessMask::FILE_APPEND_DATA
+                | AccessMask::FILE_WRITE_ATTRIBUTES
+                | AccessMask::FILE_WRITE_EA
+                | AccessMask::READ_CONTROL
+                | AccessMask::SYNCHRONIZE;
+        }
+        access
+    }
+
+    fn create_disposition(&self) -> io::Result<FileCreateDisposition> {
+        let writing = self.write || self.append;
+        if (self.create || self.create_new) && !writing {
+            return Err(invalid_input("creating a file needs write or append"));
+        }
+        if self.truncate && !self.create_new && !self.write {
+            return Err(invalid_input("truncating a file needs write"));
+        }
+        Ok(match (self.create, self.truncate, self.create_new) {
+            (_, _, true) => FileCreateDisposition::Create,
+            (false, false, false) => FileCreateDisposition::Open,
+            (true, false, false) => FileCreateDisposition::OpenIf,
+            (false, true, false) => FileCreateDisposition::Overwrite,
+            (true, true, false) => FileCreateDisposition::OverwriteIf,
+        })
+    }
+
+    /// The request opening the file at `path` with these options.
+    pub fn to_create_request(&self, path: impl AsRef<Path>) -> Result<CreateRequest> {
+        Ok(CreateRequest {
+            requested_oplock_level: self.oplock_level,
+            impersonation_level: self.impersonation_level,
+            desired_access: self.desired_access(),
+            file_attributes: self.attributes,
+            share_access: self.share_access,
+            create_disposition: self.create_disposition()?,
+            create_options: self.create_options,
+            name: path_str(path)?,
+            create_contexts: self
+                .create_contexts
+                .iter()
+                .cloned()
+                .map(Into::into)
+                .collect(),
+        })
+    }
+
+    /// Opens the file at `path` with `client`.
+    pub async fn open<'client, TransportT: Transport>(
+        &self,
+        client: &'client mut Client<TransportT>,
+        path: impl AsRef<Path>,
+    ) -> Result<File<'client, TransportT>> {
+        let file_id = self.open_file_id(client, path).await?;
+        let file = client.file(file_id);
+        Ok(if self.append && !self.write {
+            file.appending()
+        } else {
+            file
+        })
+    }
+
+    /// Like `open`, but leaves closing the file to the caller.
+    pub async fn open_file_id<TransportT: Transport>(
+        &self,
+        client: &mut Client<TransportT>,
+        path: impl AsRef<Path>,
+    ) -> Result<FileId> {
+        let response = client.create(self.to_create_request(path)?).await?;
+        Ok(response.file_id)
+    }
+}
+
+#[test]
+fn create_dispositions() {
+    let disposition = |options: OpenOptions| options.create_disposition().ok();
+    let write = OpenOptions::new().write(true);
+    assert_eq!(
+        disposition(write.clone()),
+        Some(FileCreateDisposition::Open)
+    );
+    assert_eq!(
+        disposition(write.clone().create(true)),
+        Some(FileCreateDisposition::OpenIf)
+    );
+    assert_eq!(
+        disposition(write.clone().truncate(true)),
+        Some(FileCreateDisposition::Overwrite)
+    );
+    assert_eq!(
+        disposition(write.clone().create(true).truncate(true)),
+        Some(FileCreateDisposition::OverwriteIf)
+    );
+    assert_eq!(
+        disposition(write.create_new(true).truncate(true)),
+        Some(FileCreateDisposition::Create)
+    );
+    assert_eq!(
+        disposition(OpenOptions::new().append(true).create(true)),
+        Some(FileCreateDisposition::OpenIf)
+    );
+    assert_eq!(
+        disposition(OpenOptions::new().read(true).create(true)),
+        None
+    );
+    assert_eq!(
+        disposition(OpenOptions::new().append(true).truncate(true)),
+        None
+    );
+}
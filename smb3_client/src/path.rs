@@ -0,0 +1,387 @@
+//! Paths within a share, and UNC paths naming the share as well.
+
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PathError {
+    /// The path isn't valid UTF-8
+    NotUtf8,
+    /// A name contains a character SMB doesn't allow in names
+    InvalidCharacter(char),
+    /// A stream name or type is malformed
+    InvalidStream(String),
+    /// `..` went above the root of the share
+    EscapesShare,
+    /// Not of the form `\\server\share` or `smb://server/share`
+    NotUnc(String),
+    /// A UNC path was expected to name just a share, but went further
+    NotShare(String),
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotUtf8 => write!(f, "path isn't valid UTF-8"),
+            Self::InvalidCharacter(c) => write!(f, "invalid character {c:?} in path"),
+            Self::InvalidStream(s) => write!(f, "invalid stream {s:?}"),
+            Self::EscapesShare => write!(f, "path goes above the root of the share"),
+            Self::NotUnc(p) => write!(f, "{p:?} isn't a UNC path or smb:// URL"),
+            Self::NotShare(p) => write!(f, "{p:?} names more than a share"),
+        }
+    }
+}
+
+impl std::error::Error for PathError {}
+
+/// Characters which can't appear in a name.
+fn check_name(name: &str) -> Result<(), PathError> {
+    match name.chars().find(|&c| c < ' ' || "\"*/:<>?\\|".contains(c)) {
+        Some(c) => Err(PathError::InvalidCharacter(c)),
+        None => Ok(()),
+    }
+}
+
+/// A stream is `name` or `name:$TYPE`, where only the default stream `::$DATA` has no name.
+fn check_stream(stream: &str) -> Result<(), PathError> {
+    let invalid = || PathError::InvalidStream(stream.into());
+    let (name, stream_type) = match stream.split_once(':') {
+        Some((name, stream_type)) => (name, Some(stream_type)),
+        None => (stream, None),
+    };
+    check_name(name).map_err(|_| invalid())?;
+    match stream_type {
+        Some(stream_type) if stream_type.len() > 1 && stream_type.starts_with('$') => {
+            check_name(stream_type).map_err(|_| invalid())
+        }
+        None if !name.is_empty() => Ok(()),
+        _ => Err(invalid()),
+    }
+}
+
+/// A path within a share, normalised to the form the server expects: components separated by
+/// backslashes, without one at the start, and maybe a `:stream` suffix naming a data stream.
+///
+/// Either slash separates components when parsing, `.` components are dropped and `..` ones
+/// remove the component before.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct SmbPath {
+    path: String,
+    /// Where the `:` starting the stream is in `path`
+    stream_start: Option<usize>,
+}
+
+impl SmbPath {
+    /// The root of the share
+    pub fn root() -> Self {
+        Self::default()
+    }
+
+    pub fn new(path: &str) -> Result<Self, PathError> {
+        let parts: Vec<_> = path.split(['/', '\\']).collect();
+        let mut components = vec![];
+        let mut stream = None;
+        for (i, mut part) in parts.iter().copied().enumerate() {
+            if i + 1 == parts.len() {
+                if let Some((name, s)) = part.split_once(':') {
+                    check_stream(s)?;
+                    stream = Some(s);
+                    part = name;
+                }
+            }
+            match part {
+                "" | "." => {}
+                ".." => {
+                    components.pop().ok_or(PathError::EscapesShare)?;
+                }
+                name => {
+                    check_name(name)?;
+                    components.push(name);
+                }
+            }
+        }
+
+        let mut path = components.join("\\");
+        let stream_start = stream.map(|stream| {
+            let start = path.len();
+            path = format!("{path}:{stream}");
+            start
+        });
+        Ok(Self { path, stream_start })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.path
+    }
+
+    /// The path without any stream.
+    fn file_path(&self) -> &str {
+        &self.path[..self.stream_start.unwrap_or(self.path.len())]
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.file_path().is_empty()
+    }
+
+    pub fn components(&self) -> impl Iterator<Item = &str> {
+        self.file_path().split('\\').filter(|c| !c.is_empty())
+    }
+
+    pub fn file_name(&self) -> Option<&str> {
+        self.components().last()
+    }
+
+    /// The directory this is in, or `None` for the root.
+    pub fn parent(&self) -> Option<SmbPath> {
+        let (parent, _) = self.file_path().rsplit_once('\\').unwrap_or(("", ""));
+        (!self.is_root()).then(|| SmbPath {
+            path: parent.into(),
+            stream_start: None,
+        })
+    }
+
+    /// The name of the data stream, with its type if it was given one, like `Zone.Identifier` or
+    /// `Zone.Identifier:$DATA`.
+    pub fn stream(&self) -> Option<&str> {
+        self.stream_start.map(|start| &self.path[start + 1..])
+    }
+
+    /// This path with `path` added on to the end, replacing any stream.
+    pub fn join(&self, path: &str) -> Result<SmbPath, PathError> {
+        SmbPath::new(&format!("{}\\{path}", self.file_path()))
+    }
+
+    /// The data stream called `stream` of this file.
+    pub fn with_stream(&self, stream: &str) -> Result<SmbPath, PathError> {
+        SmbPath::new(&format!("{}:{stream}", self.file_path()))
+    }
+}
+
+impl fmt::Display for SmbPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.path)
+    }
+}
+
+impl FromStr for SmbPath {
+    type Err = PathError;
+
+    fn from_str(path: &str) -> Result<Self, PathError> {
+        Self::new(path)
+    }
+}
+
+impl TryFrom<&str> for SmbPath {
+    type Error = PathError;
+
+    fn try_from(path: &str) -> Result<Self, PathError> {
+        Self::new(path)
+    }
+}
+
+impl TryFrom<&Path> for SmbPath {
+    type Error = PathError;
+
+    fn try_from(path: &Path) -> Result<Self, PathError> {
+        Self::new(path.to_str().ok_or(PathError::NotUtf8)?)
+    }
+}
+
+/// So an `SmbPath` can be passed wherever the client takes a path.
+impl AsRef<Path> for SmbPath {
+    fn as_ref(&self) -> &Path {
+        Path::new(&self.path)
+    }
+}
+
+/// Decodes the `%XX` escapes of a URL.
+fn percent_decode(s: &str) -> Option<String> {
+    let mut bytes = vec![];
+    let mut rest = s.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        if b == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(b);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// A path naming a server and share as well, like `\\server\share\dir\file` or
+/// `smb://server/share/dir/file`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct UncPath {
+    server: String,
+    port: Option<u16>,
+    share: String,
+    path: SmbPath,
+}
+
+impl UncPath {
+    pub fn new(path: &str) -> Result<Self, PathError> {
+        let not_unc = || PathError::NotUnc(path.into());
+        let is_url = path
+            .get(..6)
+            .is_some_and(|scheme| scheme.eq_ignore_ascii_case("smb://"));
+        let (server, port, rest) = if is_url {
+            let (authority, rest) = path[6..].split_once('/').unwrap_or((&path[6..], ""));
+            // Credentials are given to the client separately
+            let host = authority
+                .rsplit_once('@')
+                .map_or(authority, |(_, host)| host);
+            let (server, port) = match host.rsplit_once(':') {
+                Some((server, port)) if !port.contains(']') => {
+                    (server, Some(port.parse().map_err(|_| not_unc())?))
+                }
+                _ => (host, None),
+            };
+            (
+                server.to_owned(),
+                port,
+                percent_decode(rest).ok_or_else(not_unc)?,
+            )
+        } else {
+            let rest = path
+                .strip_prefix("\\\\")
+                .or_else(|| path.strip_prefix("//"))
+                .ok_or_else(not_unc)?;
+            let (server, rest) = rest.split_once(['/', '\\']).unwrap_or((rest, ""));
+            (server.to_owned(), None, rest.to_owned())
+        };
+
+        let (share, rest) = rest.split_once(['/', '\\']).unwrap_or((&rest, ""));
+        if server.is_empty() || share.is_empty() {
+            return Err(not_unc());
+        }
+        check_name(share)?;
+        Ok(Self {
+            server,
+            port,
+            share: share.into(),
+            path: SmbPath::new(rest)?,
+        })
+    }
+
+    pub fn server(&self) -> &str {
+        &self.server
+    }
+
+    /// The port given in an `smb://` URL
+    pub fn port(&self) -> Option<u16> {
+        self.port
+    }
+
+    pub fn share(&self) -> &str {
+        &self.share
+    }
+
+    /// The path within the share
+    pub fn path(&self) -> &SmbPath {
+        &self.path
+    }
+
+    /// The path of just the share, `\\server\share`, as a tree is connected with.
+    pub fn tree_path(&self) -> String {
+        format!("\\\\{}\\{}", self.server, self.share)
+    }
+}
+
+impl fmt::Display for UncPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.tree_path())?;
+        if !self.path.as_str().is_empty() {
+            write!(f, "\\{}", self.path)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for UncPath {
+    type Err = PathError;
+
+    fn from_str(path: &str) -> Result<Self, PathError> {
+        Self::new(path)
+    }
+}
+
+impl TryFrom<&str> for UncPath {
+    type Error = PathError;
+
+    fn try_from(path: &str) -> Result<Self, PathError> {
+        Self::new(path)
+    }
+}
+
+impl TryFrom<&String> for UncPath {
+    type Error = PathError;
+
+    fn try_from(path: &String) -> Result<Self, PathError> {
+        Self::new(path)
+    }
+}
+
+#[test]
+fn smb_paths() {
+    let path = SmbPath::new("/a/./b/../c\\d").unwrap();
+    assert_eq!(path.as_str(), "a\\c\\d");
+    assert_eq!(path.components().collect::<Vec<_>>(), ["a", "c", "d"]);
+    assert_eq!(path.file_name(), Some("d"));
+    assert_eq!(path.parent().unwrap().as_str(), "a\\c");
+    assert_eq!(path.join("../e").unwrap().as_str(), "a\\c\\e");
+    assert_eq!(SmbPath::new("a").unwrap().parent(), Some(SmbPath::root()));
+    assert_eq!(SmbPath::root().parent(), None);
+
+    let path = SmbPath::new("dir/file:Zone.Identifier:$DATA").unwrap();
+    assert_eq!(path.as_str(), "dir\\file:Zone.Identifier:$DATA");
+    assert_eq!(path.stream(), Some("Zone.Identifier:$DATA"));
+    assert_eq!(path.file_name(), Some("file"));
+    assert_eq!(path.with_stream("s").unwrap().as_str(), "dir\\file:s");
+    assert_eq!(SmbPath::new("f::$DATA").unwrap().stream(), Some(":$DATA"));
+
+    assert_eq!(SmbPath::new(".."), Err(PathError::EscapesShare));
+    assert_eq!(SmbPath::new("a*b"), Err(PathError::InvalidCharacter('*')));
+    assert_eq!(SmbPath::new("a:b/c"), Err(PathError::InvalidCharacter(':')));
+    assert_eq!(SmbPath::new("a:"), Err(PathError::InvalidStream("".into())));
+    assert_eq!(
+        SmbPath::new("a:b:DATA"),
+        Err(PathError::InvalidStream("b:DATA".into()))
+    );
+}
+
+#[test]
+fn unc_paths() {
+    let path = UncPath::new("\\\\server\\share\\dir\\file").unwrap();
+    assert_eq!(path.server(), "server");
+    assert_eq!(path.share(), "share");
+    assert_eq!(path.path().as_str(), "dir\\file");
+    assert_eq!(path.tree_path(), "\\\\server\\share");
+    assert_eq!(path.to_string(), "\\\\server\\share\\dir\\file");
+
+    let path = UncPath::new("//server/share").unwrap();
+    assert!(path.path().is_root());
+    assert_eq!(path.to_string(), "\\\\server\\share");
+
+    let path = UncPath::new("smb://user@server:4450/share/a%20dir/file").unwrap();
+    assert_eq!(path.server(), "server");
+    assert_eq!(path.port(), Some(4450));
+    assert_eq!(path.path().as_str(), "a dir\\file");
+
+    let path = UncPath::new("SMB://[::1]/share").unwrap();
+    assert_eq!(path.server(), "[::1]");
+    assert_eq!(path.port(), None);
+
+    assert!(matches!(UncPath::new("share"), Err(PathError::NotUnc(_))));
+    assert!(matches!(
+        UncPath::new("\\\\server"),
+        Err(PathError::NotUnc(_))
+    ));
+    assert!(matches!(
+        UncPath::new("smb://server/share/%zz"),
+        Err(PathError::NotUnc(_))
+    ));
+}
@@ -0,0 +1,278 @@
+use super::op::OpState;
+use super::{
+    ioctl_request, read_request, Client, DeferredCloses, Result, Transport, Tree, IO_SIZE,
+};
+use smb3::{
+    Credits, CtlCode, FileId, FilePipeInformation, IoctlResponse, NtStatus, PipeCompletionMode,
+    PipeReadMode, ReadResponse, ResponseHeader,
+};
+use std::fmt;
+use std::future::poll_fn;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use tokio::io::{self, AsyncRead, AsyncWrite, ReadBuf};
+
+enum OpResult {
+    Read(Result<Vec<u8>>),
+    Write(Result<u32>),
+}
+
+/// An open named pipe on the IPC$ share.
+///
+/// Reads and writes go through [`AsyncRead`] and [`AsyncWrite`], while [`NamedPipe::transact`]
+/// does a write followed by a read of the reply in one FSCTL_PIPE_TRANSCEIVE round trip.
+///
+/// It is closed by [`NamedPipe::close`], or when it is dropped along with the next request the
+/// client sends.
+pub struct NamedPipe<'client, TransportT> {
+    tree: Tree,
+    file_id: FileId,
+    state: OpState<'client, TransportT, OpResult>,
+    /// Where to put the pipe to close it when dropped, even while the client is lent out
+    deferred_closes: DeferredCloses,
+    read_buffer: Vec<u8>,
+    closed: bool,
+}
+
+impl<'client, TransportT> fmt::Debug for NamedPipe<'client, TransportT> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NamedPipe")
+            .field("tree", &self.tree)
+            .field("file_id", &self.file_id)
+            .finish_non_exhaustive()
+    }
+}
+
+fn is_end_of_pipe(status: NtStatus) -> bool {
+    [
+        NtStatus::EndOfFile,
+        NtStatus::PipeBroken,
+        NtStatus::PipeClosing,
+        NtStatus::PipeDisconnected,
+    ]
+    .contains(&status)
+}
+
+impl<'client, TransportT: Transport> NamedPipe<'client, TransportT> {
+    pub(crate) fn new(
+        client: &'client mut Client<TransportT>,
+        tree: Tree,
+        file_id: FileId,
+    ) -> Self {
+        Self {
+            tree,
+            file_id,
+            deferred_closes: client.deferred_closes(tree),
+            state: OpState::Idle(client),
+            read_buffer: vec![],
+            closed: false,
+        }
+    }
+
+    pub fn file_id(&self) -> FileId {
+        self.file_id
+    }
+
+    fn start_read(&mut self, count: u32) {
+        let (tree, file_id) = (self.tree, self.file_id);
+        self.state.start(move |client| {
+            Box::pin(async move {
+                let result = client.pipe_read(tree, file_id, count).await;
+                (client, OpResult::Read(result))
+            })
+        });
+    }
+
+    fn start_write(&mut self, data: Vec<u8>) {
+        let (tree, file_id) = (self.tree, self.file_id);
+        self.state.start(move |client| {
+            Box::pin(async move {
+                let result = client.write_on(tree, file_id, 0, data).await;
+                (client, OpResult::Write(result))
+            })
+        });
+    }
+
+    /// Drives any in-progress operation to completion. The data of a finished read is kept
+    /// around for the next read, the result of a finished write is returned.
+    fn poll_op(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<u32>>> {
+        match ready!(self.state.poll(cx)) {
+            None => Poll::Ready(None),
+            Some(OpResult::Read(Ok(data))) => {
+                self.read_buffer.extend(data);
+                Poll::Ready(None)
+            }
+            Some(OpResult::Read(Err(e))) => Poll::Ready(Some(Err(e))),
+            Some(OpResult::Write(r)) => Poll::Ready(Some(r)),
+        }
+    }
+
+    async fn client(&mut self) -> Result<&mut Client<TransportT>> {
+        if let Some(Err(e)) = poll_fn(|cx| self.poll_op(cx)).await {
+            return Err(e);
+        }
+        Ok(self.state.client().unwrap())
+    }
+
+    /// Writes `input` to the pipe and reads back the whole reply message.
+    pub async fn transact(&mut self, input: &[u8]) -> Result<Vec<u8>> {
+        let (tree, file_id) = (self.tree, self.file_id);
+        let client = self.client().await?;
+        let (header, response) = client.pipe_transceive(tree, file_id, input.into()).await?;
+        let mut output = response.output;
+
+        let mut status = header.nt_status;
+        while status == NtStatus::BufferOverflow {
+            let (header, response) = client.pipe_read_on(tree, file_id, IO_SIZE as u32).await?;
+            output.extend(response.data);
+            status = header.nt_status;
+        }
+        Ok(output)
+    }
+
+    pub async fn pipe_information(&mut self) -> Result<FilePipeInformation> {
+        let (tree, file_id) = (self.tree, self.file_id);
+        self.client().await?.query_info_on(tree, file_id).await
+    }
+
+    pub async fn set_pipe_information(
+        &mut self,
+        read_mode: PipeReadMode,
+        completion_mode: PipeCompletionMode,
+    ) -> Result<()> {
+        let (tree, file_id) = (self.tree, self.file_id);
+        let info = FilePipeInformation {
+            read_mode,
+            completion_mode,
+        };
+        self.client().await?.set_info_on(tree, file_id, info).await
+    }
+
+    pub async fn close(mut self) -> Result<()> {
+        let (tree, file_id) = (self.tree, self.file_id);
+        self.closed = true;
+        // Whatever happened to an abandoned read or write, the pipe still gets closed
+        let _abandoned = poll_fn(|cx| self.poll_op(cx)).await;
+        let client = self.state.client().unwrap();
+        client.close_on(tree, file_id).await?;
+        Ok(())
+    }
+}
+
+impl<'client, TransportT> Drop for NamedPipe<'client, TransportT> {
+    fn drop(&mut self) {
+        if !self.closed {
+            self.deferred_closes
+                .lock()
+                .unwrap()
+                .push((self.tree.tree_id, self.file_id));
+        }
+    }
+}
+
+impl<TransportT: Transport> Client<TransportT> {
+    /// Reads up to `count` bytes of the current message. A BUFFER_OVERFLOW status means there is
+    /// more of the message left to read. This is never retried, a message read is gone from the
+    /// pipe even if the response is lost.
+    async fn pipe_read_on(
+        &mut self,
+        tree: Tree,
+        file_id: FileId,
+        count: u32,
+    ) -> Result<(ResponseHeader, ReadResponse)> {
+        self.connection(tree)
+            .request_partial(
+                Some(tree.tree_id),
+                Credits(1),
+                Credits(9),
+                read_request(file_id, 0, count),
+            )
+            .await
+    }
+
+    /// Writes `input` as a message and reads the reply, with BUFFER_OVERFLOW like `pipe_read_on`.
+    async fn pipe_transceive(
+        &mut self,
+        tree: Tree,
+        file_id: FileId,
+        input: Vec<u8>,
+    ) -> Result<(ResponseHeader, IoctlResponse)> {
+        let request = ioctl_request(file_id, CtlCode::FsctlPipeTransceive, input, IO_SIZE as u32);
+        self.connection(tree)
+            .request_partial(Some(tree.tree_id), Credits(1), Credits(64), request)
+            .await
+    }
+
+    /// Reads up to `count` bytes from a pipe, an empty result means the other end closed it.
+    async fn pipe_read(&mut self, tree: Tree, file_id: FileId, count: u32) -> Result<Vec<u8>> {
+        match self.pipe_read_on(tree, file_id, count).await {
+            Ok((_, response)) => Ok(response.data),
+            Err(e) if e.nt_status().is_some_and(is_end_of_pipe) => Ok(vec![]),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl<'client, TransportT: Transport> AsyncRead for NamedPipe<'client, TransportT> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if !this.read_buffer.is_empty() {
+                let amount = this.read_buffer.len().min(buf.remaining());
+                buf.put_slice(&this.read_buffer[..amount]);
+                this.read_buffer.drain(..amount);
+                return Poll::Ready(Ok(()));
+            }
+
+            let started = this.state.is_idle();
+            if started {
+                let count = buf.remaining().min(IO_SIZE) as u32;
+                this.start_read(count);
+            }
+            // An operation we didn't start here was abandoned by its caller. If it was a write
+            // its result is dropped.
+            if let Some(Err(e)) = ready!(this.poll_op(cx)) {
+                return Poll::Ready(Err(e.into()));
+            }
+            if started && this.read_buffer.is_empty() {
+                // End of pipe
+                return Poll::Ready(Ok(()));
+            }
+        }
+    }
+}
+
+impl<'client, TransportT: Transport> AsyncWrite for NamedPipe<'client, TransportT> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        loop {
+            if this.state.is_idle() {
+                this.start_write(buf.to_vec());
+            }
+            match ready!(this.poll_op(cx)) {
+                Some(result) => return Poll::Ready(Ok(result? as usize)),
+                // An abandoned read finished, its data is buffered and now we can write.
+                None => continue,
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match ready!(self.get_mut().poll_op(cx)) {
+            Some(Err(e)) => Poll::Ready(Err(e.into())),
+            _ => Poll::Ready(Ok(())),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_flush(cx)
+    }
+}
@@ -0,0 +1,72 @@
+use std::time::Duration;
+
+/// How requests which fail in a way that may not happen again, see [`Error::is_transient`], are
+/// retried. Only requests which are safe to repeat are retried: reads of files, querying
+/// information and listing directories. A directory listing which timed out isn't retried, the
+/// server may have moved on past the entries in the lost response. Reads of named pipes are never
+/// retried, the message read is gone from the pipe. Anything else fails with the first error.
+///
+/// A request failing because the server disconnected the tree, with NETWORK_NAME_DELETED, is
+/// retried after connecting the tree again. One failing because the connection was lost is
+/// retried on a new connection with a new session, as long as the client has a [`Connector`] to
+/// make it with, otherwise it fails with [`Error::Disconnected`]. The server closes the files open
+/// on the old tree or connection, so requests for those still fail after reconnecting.
+///
+/// [`Error::is_transient`]: crate::Error::is_transient
+/// [`Error::Disconnected`]: crate::Error::Disconnected
+/// [`Connector`]: crate::Connector
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// How many times a request is sent at most, so 1 never retries
+    pub max_attempts: u32,
+    /// How long to wait before the first retry, doubling for each one after
+    pub initial_backoff: Duration,
+    /// The longest to wait before a retry
+    pub max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Fails with the first error.
+    pub fn never() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    /// How long to wait before retry number `retry`, counting from 1.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = 1u32
+            .checked_shl(retry.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+}
+
+#[test]
+fn backoff_doubles_up_to_the_limit() {
+    let policy = RetryPolicy {
+        max_attempts: 10,
+        initial_backoff: Duration::from_millis(100),
+        max_backoff: Duration::from_secs(1),
+    };
+    let backoffs: Vec<_> = (1..=6).map(|retry| policy.backoff(retry)).collect();
+    assert_eq!(
+        backoffs,
+        [100, 200, 400, 800, 1000, 1000].map(Duration::from_millis)
+    );
+    assert_eq!(policy.backoff(100), Duration::from_secs(1));
+    assert_eq!(RetryPolicy::never().max_attempts, 1);
+}
@@ -0,0 +1,529 @@
+//! A minimal DCE/RPC (MS-RPCE) client, enough to bind to an interface on a named pipe and make
+//! calls with NDR encoded arguments.
+
+use crate::{NamedPipe, Result, Transport, IO_SIZE};
+use smb3::Uuid;
+use std::fmt;
+use std::future::Future;
+
+pub mod srvsvc;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The server refused the bind, this holds the provider reason
+    BindRejected(u16),
+    /// The call failed with this fault status
+    Fault(u32),
+    /// The call returned this non-zero WERROR
+    Status(u32),
+    /// A PDU or NDR stub couldn't be decoded
+    Malformed(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BindRejected(reason) => write!(f, "bind rejected with reason {reason}"),
+            Self::Fault(status) => write!(f, "RPC fault {status:#010x}"),
+            Self::Status(status) => write!(f, "RPC call failed with {status:#010x}"),
+            Self::Malformed(m) => write!(f, "malformed RPC data: {m}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+fn malformed(what: &str) -> crate::Error {
+    Error::Malformed(what.into()).into()
+}
+
+/// Something which can carry DCE/RPC PDUs, normally a [`NamedPipe`]
+pub trait RpcTransport {
+    /// Writes the PDUs in `input` without waiting for a reply.
+    fn write(&mut self, input: &[u8]) -> impl Future<Output = Result<()>>;
+
+    /// Writes the PDUs in `input` and returns the beginning of the reply.
+    fn transact(&mut self, input: &[u8]) -> impl Future<Output = Result<Vec<u8>>>;
+
+    /// Reads more of a reply which didn't fit in what [`RpcTransport::transact`] returned.
+    fn read(&mut self) -> impl Future<Output = Result<Vec<u8>>>;
+}
+
+impl<'client, TransportT: Transport> RpcTransport for NamedPipe<'client, TransportT> {
+    async fn write(&mut self, input: &[u8]) -> Result<()> {
+        tokio::io::AsyncWriteExt::write_all(self, input).await?;
+        Ok(())
+    }
+
+    async fn transact(&mut self, input: &[u8]) -> Result<Vec<u8>> {
+        NamedPipe::transact(self, input).await
+    }
+
+    async fn read(&mut self) -> Result<Vec<u8>> {
+        let mut buf = vec![0; IO_SIZE];
+        let amount = tokio::io::AsyncReadExt::read(self, &mut buf).await?;
+        if amount == 0 {
+            return Err(malformed("pipe closed in the middle of a reply"));
+        }
+        buf.truncate(amount);
+        Ok(buf)
+    }
+}
+
+/// So a transport can be bound without giving it up, to close it whatever happens.
+impl<T: RpcTransport> RpcTransport for &mut T {
+    async fn write(&mut self, input: &[u8]) -> Result<()> {
+        (**self).write(input).await
+    }
+
+    async fn transact(&mut self, input: &[u8]) -> Result<Vec<u8>> {
+        (**self).transact(input).await
+    }
+
+    async fn read(&mut self) -> Result<Vec<u8>> {
+        (**self).read().await
+    }
+}
+
+/// Identifies an RPC interface or transfer syntax along with its version
+#[derive(Clone, Debug, PartialEq)]
+pub struct SyntaxId {
+    pub uuid: Uuid,
+    pub version: u16,
+    pub version_minor: u16,
+}
+
+/// The NDR 2.0 transfer syntax
+pub const NDR_SYNTAX: SyntaxId = SyntaxId {
+    uuid: Uuid {
+        data1: 0x8a885d04,
+        data2: 0x1ceb,
+        data3: 0x11c9,
+        data4: [0x9f, 0xe8, 0x08, 0x00, 0x2b, 0x10, 0x48, 0x60],
+    },
+    version: 2,
+    version_minor: 0,
+};
+
+const RPC_VERSION: u8 = 5;
+const PFC_FIRST_FRAG: u8 = 0x01;
+const PFC_LAST_FRAG: u8 = 0x02;
+const DATA_REPRESENTATION: [u8; 4] = [0x10, 0x00, 0x00, 0x00];
+const HEADER_LEN: usize = 16;
+const REQUEST_HEADER_LEN: usize = HEADER_LEN + 8;
+const MAX_FRAG: u16 = 4280;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[repr(u8)]
+enum PacketType {
+    Request = 0,
+    Response = 2,
+    Fault = 3,
+    Bind = 11,
+    BindAck = 12,
+    BindNak = 13,
+}
+
+impl PacketType {
+    fn from_u8(v: u8) -> Option<Self> {
+        [
+            Self::Request,
+            Self::Response,
+            Self::Fault,
+            Self::Bind,
+            Self::BindAck,
+            Self::BindNak,
+        ]
+        .into_iter()
+        .find(|t| *t as u8 == v)
+    }
+}
+
+struct Pdu {
+    packet_type: PacketType,
+    flags: u8,
+    call_id: u32,
+    body: Vec<u8>,
+}
+
+impl Pdu {
+    fn encode(&self) -> Vec<u8> {
+        let mut w = NdrWriter::new();
+        w.write_u8(RPC_VERSION);
+        w.write_u8(0);
+        w.write_u8(self.packet_type as u8);
+        w.write_u8(self.flags);
+        w.write_bytes(&DATA_REPRESENTATION);
+        w.write_u16((HEADER_LEN + self.body.len()) as u16);
+        w.write_u16(0);
+        w.write_u32(self.call_id);
+        w.write_bytes(&self.body);
+        w.into_bytes()
+    }
+
+    /// Decodes a PDU from the front of `data`, if `data` holds a complete one.
+    fn decode(data: &[u8]) -> Result<Option<(Self, usize)>> {
+        if data.len() < HEADER_LEN {
+            return Ok(None);
+        }
+        let mut r = NdrReader::new(data);
+        if r.read_u8()? != RPC_VERSION {
+            return Err(malformed("unsupported RPC version"));
+        }
+        r.read_u8()?;
+        let packet_type =
+            PacketType::from_u8(r.read_u8()?).ok_or_else(|| malformed("unknown packet type"))?;
+        let flags = r.read_u8()?;
+        if r.read_bytes(4)? != DATA_REPRESENTATION {
+            return Err(malformed("unsupported data representation"));
+        }
+        let frag_length = r.read_u16()? as usize;
+        let auth_length = r.read_u16()? as usize;
+        let call_id = r.read_u32()?;
+        if frag_length < HEADER_LEN + auth_length {
+            return Err(malformed("fragment too short"));
+        }
+        if data.len() < frag_length {
+            return Ok(None);
+        }
+        let body = data[HEADER_LEN..frag_length - auth_length].to_vec();
+        let pdu = Self {
+            packet_type,
+            flags,
+            call_id,
+            body,
+        };
+        Ok(Some((pdu, frag_length)))
+    }
+}
+
+/// Writes NDR 2.0 encoded data, aligning primitives to their size.
+pub struct NdrWriter {
+    buf: Vec<u8>,
+    next_referent_id: u32,
+}
+
+impl Default for NdrWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NdrWriter {
+    pub fn new() -> Self {
+        Self {
+            buf: vec![],
+            next_referent_id: 0x00020000,
+        }
+    }
+
+    pub fn align(&mut self, align: usize) {
+        while self.buf.len() % align != 0 {
+            self.buf.push(0);
+        }
+    }
+
+    pub fn write_u8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+
+    pub fn write_u16(&mut self, v: u16) {
+        self.align(2);
+        self.buf.extend(v.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, v: u32) {
+        self.align(4);
+        self.buf.extend(v.to_le_bytes());
+    }
+
+    pub fn write_bytes(&mut self, v: &[u8]) {
+        self.buf.extend(v);
+    }
+
+    /// Writes the referent id of a non-null unique pointer. The pointee has to be written
+    /// after the structure containing the pointer.
+    pub fn write_pointer(&mut self) {
+        let id = self.next_referent_id;
+        self.next_referent_id += 4;
+        self.write_u32(id);
+    }
+
+    pub fn write_null_pointer(&mut self) {
+        self.write_u32(0);
+    }
+
+    /// Writes a conformant and varying null-terminated UTF-16 string.
+    pub fn write_string(&mut self, s: &str) {
+        let chars: Vec<u16> = s.encode_utf16().chain([0]).collect();
+        self.write_u32(chars.len() as u32);
+        self.write_u32(0);
+        self.write_u32(chars.len() as u32);
+        for c in chars {
+            self.write_u16(c);
+        }
+    }
+
+    pub fn write_syntax_id(&mut self, syntax: &SyntaxId) {
+        self.write_u32(syntax.uuid.data1);
+        self.write_u16(syntax.uuid.data2);
+        self.write_u16(syntax.uuid.data3);
+        self.write_bytes(&syntax.uuid.data4);
+        self.write_u16(syntax.version);
+        self.write_u16(syntax.version_minor);
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+/// Reads NDR 2.0 encoded data written like [`NdrWriter`] does.
+pub struct NdrReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> NdrReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    pub fn align(&mut self, align: usize) -> Result<()> {
+        let padding = (align - self.position % align) % align;
+        self.read_bytes(padding)?;
+        Ok(())
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.position..self.position + len)
+            .ok_or_else(|| malformed("unexpected end of data"))?;
+        self.position += len;
+        Ok(bytes)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16> {
+        self.align(2)?;
+        Ok(u16::from_le_bytes(self.read_bytes(2)?.try_into().unwrap()))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32> {
+        self.align(4)?;
+        Ok(u32::from_le_bytes(self.read_bytes(4)?.try_into().unwrap()))
+    }
+
+    /// Reads the referent id of a unique pointer, returning false if it is null.
+    pub fn read_pointer(&mut self) -> Result<bool> {
+        Ok(self.read_u32()? != 0)
+    }
+
+    /// Reads a conformant and varying null-terminated UTF-16 string.
+    pub fn read_string(&mut self) -> Result<String> {
+        let _max_count = self.read_u32()?;
+        let offset = self.read_u32()?;
+        let actual_count = self.read_u32()?;
+        if offset != 0 {
+            return Err(malformed("string with non-zero offset"));
+        }
+        let mut chars = vec![];
+        for _ in 0..actual_count {
+            chars.push(self.read_u16()?);
+        }
+        if chars.last() == Some(&0) {
+            chars.pop();
+        }
+        Ok(String::from_utf16_lossy(&chars))
+    }
+
+    pub fn read_syntax_id(&mut self) -> Result<SyntaxId> {
+        Ok(SyntaxId {
+            uuid: Uuid {
+                data1: self.read_u32()?,
+                data2: self.read_u16()?,
+                data3: self.read_u16()?,
+                data4: self.read_bytes(8)?.try_into().unwrap(),
+            },
+            version: self.read_u16()?,
+            version_minor: self.read_u16()?,
+        })
+    }
+}
+
+/// A DCE/RPC connection bound to one interface.
+pub struct RpcClient<TransportT> {
+    transport: TransportT,
+    next_call_id: u32,
+    max_xmit_frag: u16,
+    max_recv_frag: u16,
+    read_buffer: Vec<u8>,
+}
+
+impl<TransportT> fmt::Debug for RpcClient<TransportT> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RpcClient")
+            .field("next_call_id", &self.next_call_id)
+            .finish_non_exhaustive()
+    }
+}
+
+const CONTEXT_ID: u16 = 0;
+
+impl<TransportT: RpcTransport> RpcClient<TransportT> {
+    /// Binds to `interface` using the NDR transfer syntax.
+    pub async fn bind(transport: TransportT, interface: &SyntaxId) -> Result<Self> {
+        let mut client = Self {
+            transport,
+            next_call_id: 1,
+            max_xmit_frag: MAX_FRAG,
+            max_recv_frag: MAX_FRAG,
+            read_buffer: vec![],
+        };
+
+        let mut w = NdrWriter::new();
+        w.write_u16(MAX_FRAG);
+        w.write_u16(MAX_FRAG);
+        w.write_u32(0); // assoc_group_id
+        w.write_u8(1); // n_context_elem
+        w.write_bytes(&[0; 3]);
+        w.write_u16(CONTEXT_ID);
+        w.write_u8(1); // n_transfer_syn
+        w.write_u8(0);
+        w.write_syntax_id(interface);
+        w.write_syntax_id(&NDR_SYNTAX);
+
+        let call_id = client.next_call_id();
+        let request = Pdu {
+            packet_type: PacketType::Bind,
+            flags: PFC_FIRST_FRAG | PFC_LAST_FRAG,
+            call_id,
+            body: w.into_bytes(),
+        };
+        let reply = client.transact(request.encode(), call_id).await?;
+
+        match reply.packet_type {
+            PacketType::BindAck => {}
+            PacketType::BindNak => {
+                let reason = NdrReader::new(&reply.body).read_u16()?;
+                return Err(Error::BindRejected(reason).into());
+            }
+            _ => return Err(malformed("unexpected reply to bind")),
+        }
+
+        // The offsets used for alignment are relative to the start of the PDU
+        let mut body = vec![0; HEADER_LEN];
+        body.extend(reply.body);
+        let mut r = NdrReader::new(&body);
+        r.read_bytes(HEADER_LEN)?;
+        client.max_xmit_frag = r.read_u16()?.min(MAX_FRAG);
+        client.max_recv_frag = r.read_u16()?.min(MAX_FRAG);
+        r.read_u32()?; // assoc_group_id
+        let sec_addr_len = r.read_u16()?;
+        r.read_bytes(sec_addr_len.into())?;
+        r.align(4)?;
+        let num_results = r.read_u8()?;
+        r.read_bytes(3)?;
+        if num_results < 1 {
+            return Err(malformed("bind ack without results"));
+        }
+        let result = r.read_u16()?;
+        let reason = r.read_u16()?;
+        if result != 0 {
+            return Err(Error::BindRejected(reason).into());
+        }
+        Ok(client)
+    }
+
+    fn next_call_id(&mut self) -> u32 {
+        let call_id = self.next_call_id;
+        self.next_call_id += 1;
+        call_id
+    }
+
+    /// Calls the operation `opnum` with the NDR encoded arguments `stub`, returning the NDR
+    /// encoded results.
+    pub async fn call(&mut self, opnum: u16, stub: &[u8]) -> Result<Vec<u8>> {
+        let call_id = self.next_call_id();
+        let max_stub = usize::from(self.max_xmit_frag) - REQUEST_HEADER_LEN;
+
+        let mut fragments = stub.chunks(max_stub).peekable();
+        let mut flags = PFC_FIRST_FRAG;
+        let mut response = loop {
+            let fragment = fragments.next().unwrap_or(&[]);
+            let last = fragments.peek().is_none();
+            if last {
+                flags |= PFC_LAST_FRAG;
+            }
+
+            let mut w = NdrWriter::new();
+            w.write_u32(stub.len() as u32); // alloc_hint
+            w.write_u16(CONTEXT_ID);
+            w.write_u16(opnum);
+            w.write_bytes(fragment);
+            let request = Pdu {
+                packet_type: PacketType::Request,
+                flags,
+                call_id,
+                body: w.into_bytes(),
+            }
+            .encode();
+
+            if last {
+                break self.transact(request, call_id).await?;
+            }
+            self.transport.write(&request).await?;
+            flags = 0;
+        };
+
+        let mut stub = vec![];
+        loop {
+            match response.packet_type {
+                PacketType::Response => {}
+                PacketType::Fault => {
+                    let mut r = NdrReader::new(&response.body);
+                    r.read_bytes(8)?;
+                    return Err(Error::Fault(r.read_u32()?).into());
+                }
+                _ => return Err(malformed("unexpected reply to request")),
+            }
+            // Skip alloc_hint, p_cont_id, cancel_count and reserved
+            stub.extend(response.body.get(8..).unwrap_or_default());
+            if response.flags & PFC_LAST_FRAG != 0 {
+                break;
+            }
+            response = self.read_pdu(call_id).await?;
+        }
+        Ok(stub)
+    }
+
+    async fn transact(&mut self, request: Vec<u8>, call_id: u32) -> Result<Pdu> {
+        self.read_buffer = self.transport.transact(&request).await?;
+        self.read_pdu(call_id).await
+    }
+
+    async fn read_pdu(&mut self, call_id: u32) -> Result<Pdu> {
+        loop {
+            if let Some((pdu, len)) = Pdu::decode(&self.read_buffer)? {
+                self.read_buffer.drain(..len);
+                if pdu.call_id != call_id {
+                    return Err(malformed("reply for the wrong call"));
+                }
+                return Ok(pdu);
+            }
+            if self.read_buffer.len() > usize::from(self.max_recv_frag) {
+                return Err(malformed("fragment larger than negotiated"));
+            }
+            let more = self.transport.read().await?;
+            self.read_buffer.extend(more);
+        }
+    }
+
+    pub fn into_inner(self) -> TransportT {
+        self.transport
+    }
+}
@@ -0,0 +1,149 @@
+//! The Server Service Remote Protocol (MS-SRVS), served on the "srvsvc" pipe.
+
+use super::{malformed, Error, NdrReader, NdrWriter, Result, RpcClient, RpcTransport, SyntaxId};
+use smb3::Uuid;
+
+pub const PIPE_NAME: &str = "srvsvc";
+
+pub const SRVSVC_SYNTAX: SyntaxId = SyntaxId {
+    uuid: Uuid {
+        data1: 0x4b324fc8,
+        data2: 0x1670,
+        data3: 0x01d3,
+        data4: [0x12, 0x78, 0x5a, 0x47, 0xbf, 0x6e, 0xe1, 0x88],
+    },
+    version: 3,
+    version_minor: 0,
+};
+
+const NETR_SHARE_ENUM: u16 = 15;
+
+const STYPE_MASK: u32 = 0x000000FF;
+const STYPE_TEMPORARY: u32 = 0x40000000;
+const STYPE_SPECIAL: u32 = 0x80000000;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ShareKind {
+    Disk,
+    PrintQueue,
+    Device,
+    Ipc,
+    Other(u32),
+}
+
+impl ShareKind {
+    fn from_type(share_type: u32) -> Self {
+        match share_type & STYPE_MASK {
+            0 => Self::Disk,
+            1 => Self::PrintQueue,
+            2 => Self::Device,
+            3 => Self::Ipc,
+            v => Self::Other(v),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShareInfo {
+    pub name: String,
+    pub kind: ShareKind,
+    /// An administrative share like C$ or IPC$
+    pub special: bool,
+    pub temporary: bool,
+    pub comment: String,
+}
+
+fn encode_share_enum(server_name: Option<&str>) -> Vec<u8> {
+    let mut w = NdrWriter::new();
+    match server_name {
+        Some(server_name) => {
+            w.write_pointer();
+            w.write_string(server_name);
+        }
+        None => w.write_null_pointer(),
+    }
+
+    // InfoStruct, asking for level 1 with an empty container
+    w.write_u32(1);
+    w.write_u32(1);
+    w.write_pointer();
+    w.write_u32(0);
+    w.write_null_pointer();
+
+    // PreferedMaximumLength
+    w.write_u32(u32::MAX);
+
+    // ResumeHandle
+    w.write_pointer();
+    w.write_u32(0);
+    w.into_bytes()
+}
+
+fn decode_share_enum(stub: &[u8]) -> Result<Vec<ShareInfo>> {
+    let mut r = NdrReader::new(stub);
+    let level = r.read_u32()?;
+    let switch = r.read_u32()?;
+    if level != 1 || switch != 1 {
+        return Err(malformed("unexpected share info level"));
+    }
+
+    let mut shares = vec![];
+    let mut entries = vec![];
+    if r.read_pointer()? {
+        let entries_read = r.read_u32()?;
+        if r.read_pointer()? {
+            let max_count = r.read_u32()?;
+            if max_count < entries_read {
+                return Err(malformed("share array shorter than entries read"));
+            }
+            for _ in 0..entries_read {
+                let has_name = r.read_pointer()?;
+                let share_type = r.read_u32()?;
+                let has_comment = r.read_pointer()?;
+                entries.push((has_name, share_type, has_comment));
+            }
+        }
+    }
+    for (has_name, share_type, has_comment) in entries {
+        let name = if has_name {
+            r.read_string()?
+        } else {
+            String::new()
+        };
+        let comment = if has_comment {
+            r.read_string()?
+        } else {
+            String::new()
+        };
+        shares.push(ShareInfo {
+            name,
+            kind: ShareKind::from_type(share_type),
+            special: share_type & STYPE_SPECIAL != 0,
+            temporary: share_type & STYPE_TEMPORARY != 0,
+            comment,
+        });
+    }
+
+    let _total_entries = r.read_u32()?;
+    if r.read_pointer()? {
+        let _resume_handle = r.read_u32()?;
+    }
+    let status = r.read_u32()?;
+    if status != 0 {
+        return Err(Error::Status(status).into());
+    }
+    Ok(shares)
+}
+
+/// Lists every share on the server with NetrShareEnum.
+///
+/// `server_name` is optional, the server ignores it when the call arrives over SMB.
+pub async fn share_enum_all(
+    rpc: &mut RpcClient<impl RpcTransport>,
+    server_name: Option<&str>,
+) -> Result<Vec<ShareInfo>> {
+    let stub = rpc
+        .call(NETR_SHARE_ENUM, &encode_share_enum(server_name))
+        .await?;
+    decode_share_enum(&stub)
+}
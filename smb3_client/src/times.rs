@@ -0,0 +1,104 @@
+use smb3::{FileAttributes, FileBasicInformation, Time};
+use std::time::SystemTime;
+
+/// What to do with one of the times of a file, see [`FileTimes`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum TimeUpdate {
+    /// Leave the time as it is
+    #[default]
+    Unchanged,
+    Set(Time),
+    /// Leave the time as it is, and stop the server updating it for the rest of the open
+    Freeze,
+    /// Let the server update the time again after `Freeze`
+    Unfreeze,
+}
+
+impl TimeUpdate {
+    fn to_time(&self) -> Time {
+        match self {
+            Self::Unchanged => Time::UNCHANGED,
+            Self::Set(time) => time.clone(),
+            Self::Freeze => Time::FROZEN,
+            Self::Unfreeze => Time::UNFROZEN,
+        }
+    }
+}
+
+impl From<Time> for TimeUpdate {
+    fn from(time: Time) -> Self {
+        Self::Set(time)
+    }
+}
+
+impl From<SystemTime> for TimeUpdate {
+    fn from(time: SystemTime) -> Self {
+        Self::Set(time.into())
+    }
+}
+
+/// The times to give a file with [`Client::set_times`](crate::Client::set_times). Any not
+/// mentioned are left as they are.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FileTimes {
+    created: TimeUpdate,
+    accessed: TimeUpdate,
+    modified: TimeUpdate,
+    changed: TimeUpdate,
+}
+
+impl FileTimes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn created(mut self, update: impl Into<TimeUpdate>) -> Self {
+        self.created = update.into();
+        self
+    }
+
+    pub fn accessed(mut self, update: impl Into<TimeUpdate>) -> Self {
+        self.accessed = update.into();
+        self
+    }
+
+    pub fn modified(mut self, update: impl Into<TimeUpdate>) -> Self {
+        self.modified = update.into();
+        self
+    }
+
+    /// The time the file's metadata last changed
+    pub fn changed(mut self, update: impl Into<TimeUpdate>) -> Self {
+        self.changed = update.into();
+        self
+    }
+
+    /// The information to set, with `file_attributes` of zero leaving the attributes unchanged.
+    pub(crate) fn to_basic_information(
+        &self,
+        file_attributes: FileAttributes,
+    ) -> FileBasicInformation {
+        FileBasicInformation {
+            creation_time: self.created.to_time(),
+            last_access_time: self.accessed.to_time(),
+            last_write_time: self.modified.to_time(),
+            change_time: self.changed.to_time(),
+            file_attributes,
+        }
+    }
+}
+
+#[test]
+fn sentinel_times() {
+    let time = Time { intervals: 1234 };
+    let info = FileTimes::new()
+        .modified(time.clone())
+        .accessed(TimeUpdate::Freeze)
+        .changed(TimeUpdate::Unfreeze)
+        .to_basic_information(FileAttributes::empty());
+    assert_eq!(info.creation_time, Time::UNCHANGED);
+    assert_eq!(info.last_access_time, Time { intervals: -1 });
+    assert_eq!(info.last_write_time, time);
+    assert_eq!(info.change_time, Time { intervals: -2 });
+    assert_eq!(info.file_attributes, FileAttributes::empty());
+}
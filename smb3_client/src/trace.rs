@@ -0,0 +1,110 @@
+//! Logging through `tracing`, when the `tracing` feature is enabled. Without it these do nothing.
+//!
+//! Every request gets a `request` span at the `DEBUG` level, from when it is sent until its
+//! response arrives, recording the command, message id, credits, status and latency. Negotiation,
+//! authentication and tree connects are logged at `INFO`. At `TRACE`, under the target
+//! `smb3_client::messages`, the header and body of every message are dumped, except for the
+//! bodies of session setup messages, which hold the authentication tokens.
+
+use smb3::{MessageId, RequestHeader, ResponseHeader};
+
+#[cfg(feature = "tracing")]
+pub(crate) use tracing::{debug, info, trace, warn};
+
+#[cfg(not(feature = "tracing"))]
+mod disabled {
+    macro_rules! nothing {
+        ($($arg:tt)*) => {};
+    }
+    pub(crate) use {nothing as debug, nothing as info, nothing as trace, nothing as warn};
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) use disabled::{debug, info, trace, warn};
+
+/// The target messages are dumped under
+#[cfg(feature = "tracing")]
+pub(crate) const MESSAGES: &str = "smb3_client::messages";
+
+/// What to dump of the body of a `command` message.
+#[cfg(feature = "tracing")]
+pub(crate) fn body<'a>(
+    command: smb3::Command,
+    body: &'a dyn std::fmt::Debug,
+) -> &'a dyn std::fmt::Debug {
+    if command == smb3::Command::SessionSetup {
+        &"<redacted>"
+    } else {
+        body
+    }
+}
+
+/// The spans of the requests waiting for a response.
+#[derive(Default)]
+pub(crate) struct RequestSpans {
+    #[cfg(feature = "tracing")]
+    spans: std::collections::HashMap<MessageId, (tracing::Span, std::time::Instant)>,
+}
+
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+impl RequestSpans {
+    pub(crate) fn sent(&mut self, header: &RequestHeader) {
+        #[cfg(feature = "tracing")]
+        {
+            let span = tracing::debug_span!(
+                "request",
+                command = ?header.command,
+                message_id = header.message_id.0,
+                tree_id = header.tree_id.0,
+                credit_charge = header.credit_charge.0,
+                credits_requested = header.credits_requested.0,
+                status = tracing::field::Empty,
+                credits_granted = tracing::field::Empty,
+                latency_us = tracing::field::Empty,
+            );
+            self.spans
+                .insert(header.message_id, (span, std::time::Instant::now()));
+        }
+    }
+
+    /// The server said it is working on the request in the background.
+    pub(crate) fn pending(&mut self, header: &ResponseHeader) {
+        #[cfg(feature = "tracing")]
+        if let Some((span, _)) = self.spans.get(&header.message_id) {
+            debug!(parent: span, async_id = ?header.async_id(), "pending");
+        }
+    }
+
+    pub(crate) fn responded(&mut self, header: &ResponseHeader) {
+        #[cfg(feature = "tracing")]
+        if let Some((span, sent)) = self.spans.remove(&header.message_id) {
+            let latency_us = sent.elapsed().as_micros() as u64;
+            span.record("status", tracing::field::debug(header.nt_status));
+            span.record("credits_granted", header.credits_granted.0);
+            span.record("latency_us", latency_us);
+            debug!(parent: &span, status = ?header.nt_status, latency_us, "response");
+        }
+    }
+
+    /// No response came in time, the request is being cancelled.
+    pub(crate) fn timed_out(&self, message_id: MessageId) {
+        #[cfg(feature = "tracing")]
+        if let Some((span, sent)) = self.spans.get(&message_id) {
+            warn!(parent: span, waited_ms = sent.elapsed().as_millis() as u64, "timed out");
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn session_setup_bodies_are_redacted() {
+    let token = vec![0x4e, 0x54, 0x4c, 0x4d];
+    assert_eq!(
+        format!("{:?}", body(smb3::Command::SessionSetup, &token)),
+        "\"<redacted>\""
+    );
+    assert_eq!(
+        format!("{:?}", body(smb3::Command::Read, &token)),
+        "[78, 84, 76, 77]"
+    );
+}
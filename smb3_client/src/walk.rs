@@ -0,0 +1,474 @@
+use super::{join_name, path_str, Client, Error, PathError, Result, Transport, Tree, IO_SIZE};
+use smb3::{
+    AccessMask, CloseFlags, CloseRequest, CloseResponse, CreateRequest, CreateResponse,
+    FileAttributes, FileCreateDisposition, FileCreateOptions, FileId,
+    FileIdBothDirectoryInformation, FileInternalInformation, FileShareAccess,
+    HasFileInformationClass as _, ImpersonationLevel, NtStatus, OplockLevel, QueryDirectoryFlags,
+    QueryDirectoryRequest, QueryDirectoryResponse,
+};
+use std::collections::{HashSet, VecDeque};
+
+/// How many directories `Client::walk` lists at once.
+pub const WALK_CONCURRENCY: usize = 16;
+
+/// A file or directory found by [`Client::walk`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct WalkEntry {
+    /// The path relative to the root of the walk, `/` separated.
+    pub path: String,
+    /// How many directories down from the root this is, its direct contents are at depth 1.
+    pub depth: usize,
+    pub info: FileIdBothDirectoryInformation,
+}
+
+impl WalkEntry {
+    pub fn file_name(&self) -> &str {
+        &self.info.file_name
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.info
+            .file_attributes
+            .contains(FileAttributes::DIRECTORY)
+    }
+
+    /// Whether this is a symbolic link, junction or other reparse point.
+    pub fn is_reparse_point(&self) -> bool {
+        self.info
+            .file_attributes
+            .contains(FileAttributes::REPARSE_POINT)
+    }
+}
+
+/// Matches `name` against `pattern`, ignoring case. `?` matches any one character and `*` any
+/// run of them, but neither crosses a `/`, which `**` does.
+fn glob_matches(pattern: &[char], name: &[char]) -> bool {
+    match pattern {
+        [] => name.is_empty(),
+        ['*', '*', rest @ ..] => (0..=name.len()).any(|i| glob_matches(rest, &name[i..])),
+        ['*', rest @ ..] => {
+            let run = name.iter().take_while(|&&c| c != '/').count();
+            (0..=run).any(|i| glob_matches(rest, &name[i..]))
+        }
+        ['?', rest @ ..] => matches!(name, [c, name @ ..] if *c != '/' && glob_matches(rest, name)),
+        [p, rest @ ..] => matches!(
+            name,
+            [c, name @ ..] if c.to_lowercase().eq(p.to_lowercase()) && glob_matches(rest, name)
+        ),
+    }
+}
+
+/// A pattern given to `Walk::include` or `Walk::exclude`.
+struct Glob {
+    pattern: Vec<char>,
+    /// Patterns without a `/` are matched against just the name.
+    whole_path: bool,
+}
+
+impl Glob {
+    fn new(pattern: &str) -> Self {
+        Self {
+            pattern: pattern.chars().collect(),
+            whole_path: pattern.contains('/'),
+        }
+    }
+
+    fn matches(&self, entry: &WalkEntry) -> bool {
+        let name = if self.whole_path {
+            &entry.path
+        } else {
+            entry.file_name()
+        };
+        glob_matches(&self.pattern, &name.chars().collect::<Vec<_>>())
+    }
+}
+
+/// A directory yet to be listed.
+struct PendingDir {
+    tree: Tree,
+    /// The name on the server, or `None` for a link which has to be resolved first.
+    name: Option<String>,
+    path: String,
+    depth: usize,
+}
+
+/// Decides whether to skip what is in a directory, see `Walk::prune`.
+type Prune<'client> = Box<dyn FnMut(&WalkEntry) -> bool + 'client>;
+
+/// A walk of all the files and directories below a directory, see [`Client::walk`].
+pub struct Walk<'client, TransportT> {
+    client: &'client mut Client<TransportT>,
+    /// Any error is returned by the first call to `next_entry`
+    root: std::result::Result<String, PathError>,
+    min_depth: usize,
+    max_depth: usize,
+    follow_links: bool,
+    include: Vec<Glob>,
+    exclude: Vec<Glob>,
+    prune: Option<Prune<'client>>,
+    started: bool,
+    pending: VecDeque<PendingDir>,
+    entries: VecDeque<Result<WalkEntry>>,
+    /// The directories listed so far, to avoid going around in circles following links.
+    visited: HashSet<(Tree, u64)>,
+}
+
+impl<'client, TransportT: Transport> Walk<'client, TransportT> {
+    pub(crate) fn new(
+        client: &'client mut Client<TransportT>,
+        root: std::result::Result<String, PathError>,
+    ) -> Self {
+        Self {
+            client,
+            root,
+            min_depth: 1,
+            max_depth: usize::MAX,
+            follow_links: false,
+            include: vec![],
+            exclude: vec![],
+            prune: None,
+            started: false,
+            pending: VecDeque::new(),
+            entries: VecDeque::new(),
+            visited: HashSet::new(),
+        }
+    }
+
+    /// Don't return entries shallower than `depth`, they are still walked through.
+    pub fn min_depth(mut self, depth: usize) -> Self {
+        self.min_depth = depth;
+        self
+    }
+
+    /// Don't go deeper than `depth`, 1 only lists the root.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Walk into directories behind symbolic links and junctions, rather than just returning the
+    /// link. Directories already walked are skipped, so loops end.
+    pub fn follow_links(mut self, follow_links: bool) -> Self {
+        self.follow_links = follow_links;
+        self
+    }
+
+    /// Only return entries matching one of the `include` patterns, when there are any. It doesn't
+    /// stop directories being walked through. See `exclude` for the pattern syntax.
+    pub fn include(mut self, pattern: &str) -> Self {
+        self.include.push(Glob::new(pattern));
+        self
+    }
+
+    /// Skip entries matching `pattern`, along with everything in them. Patterns without a `/` are
+    /// matched against the name, others against the relative path. `?` matches any character and
+    /// `*` any number of them within one component, `**` can span components. Case is ignored.
+    pub fn exclude(mut self, pattern: &str) -> Self {
+        self.exclude.push(Glob::new(pattern));
+        self
+    }
+
+    /// Skip what is in the directories for which `prune` returns true. The directories
+    /// themselves are still returned.
+    pub fn prune(mut self, prune: impl FnMut(&WalkEntry) -> bool + 'client) -> Self {
+        self.prune = Some(Box::new(prune));
+        self
+    }
+
+    /// The next entry, or `None` once everything has been walked. Directories are walked a level
+    /// at a time, in no particular order within it. A directory that can't be listed gives an
+    /// error, but the walk can carry on past it.
+    pub async fn next_entry(&mut self) -> Result<Option<WalkEntry>> {
+        if !self.started {
+            self.started = true;
+            self.start().await?;
+        }
+        while self.entries.is_empty() && !self.pending.is_empty() {
+            self.fetch().await?;
+        }
+        self.entries.pop_front().transpose()
+    }
+
+    /// Collects all the remaining entries, stopping at the first error.
+    pub async fn collect(mut self) -> Result<Vec<WalkEntry>> {
+        let mut entries = vec![];
+        while let Some(entry) = self.next_entry().await? {
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    async fn start(&mut self) -> Result<()> {
+        let (tree, name, response) = self
+            .client
+            .create_resolved(list_request(self.root.clone()?))
+            .await?;
+        let file_id = response.file_id;
+        if self.follow_links {
+            let internal = self
+                .client
+                .query_info::<FileInternalInformation>(file_id)
+                .await;
+            if let Ok(internal) = &internal {
+                self.visited.insert((tree, internal.index_number));
+            }
+            self.client.close(file_id).await?;
+            internal?;
+        } else {
+            self.client.close(file_id).await?;
+        }
+        if self.max_depth > 0 {
+            self.pending.push_back(PendingDir {
+                tree,
+                name: Some(name),
+                path: String::new(),
+                depth: 0,
+            });
+        }
+        Ok(())
+    }
+
+    /// Lists the next batch of directories on the same tree.
+    async fn fetch(&mut self) -> Result<()> {
+        let tree = self.pending[0].tree;
+        let mut batch = vec![];
+        while batch.len() < WALK_CONCURRENCY {
+            match self.pending.front() {
+                Some(dir) if dir.tree == tree => batch.extend(self.pending.pop_front()),
+                _ => break,
+            }
+        }
+
+        // Links are resolved one by one, and may end up on another tree
+        let mut opened = vec![];
+        let mut to_open = vec![];
+        for mut dir in batch {
+            match &dir.name {
+                Some(name) => {
+                    let name = name.clone();
+                    to_open.push((dir, name))
+                }
+                None => match self.open_link(&dir).await? {
+                    Ok(Some((tree, name, file_id))) => {
+                        dir.tree = tree;
+                        dir.name = Some(name);
+                        opened.push((dir, file_id));
+                    }
+                    Ok(None) => {}
+                    Err(e) => self.entries.push_back(Err(e)),
+                },
+            }
+        }
+        let opens: Vec<Result<CreateResponse>> = self
+            .client
+            .request_all(
+                tree,
+                to_open.iter().map(|(_, name)| list_request(name.clone())),
+            )
+            .await?;
+        for ((dir, _), open) in to_open.into_iter().zip(opens) {
+            match open {
+                Ok(response) => opened.push((dir, response.file_id)),
+                Err(e) => self.entries.push_back(Err(e)),
+            }
+        }
+
+        // Resolved links come first, so the same tree may not be next to itself
+        let mut trees: Vec<Tree> = opened.iter().map(|(dir, _)| dir.tree).collect();
+        let mut seen = HashSet::new();
+        trees.retain(|tree| seen.insert(*tree));
+        for tree in trees {
+            let (dirs, file_ids): (Vec<_>, Vec<_>) = opened
+                .iter()
+                .filter(|(dir, _)| dir.tree == tree)
+                .map(|(dir, file_id)| (dir, *file_id))
+                .unzip();
+            let listings = self.list_all(tree, &file_ids).await;
+            let closes: Vec<Result<CloseResponse>> = self
+                .client
+                .request_all(
+                    tree,
+                    file_ids.iter().map(|&file_id| CloseRequest {
+                        flags: CloseFlags::empty(),
+                        file_id,
+                    }),
+                )
+                .await?;
+            for (dir, listing) in dirs.into_iter().zip(listings?) {
+                match listing {
+                    Ok(listing) => self.add_entries(dir, listing),
+                    Err(e) => self.entries.push_back(Err(e)),
+                }
+            }
+            if let Some(e) = closes.into_iter().find_map(Result::err) {
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Opens the directory behind the link `dir`, giving where it ended up, or `None` if it has
+    /// been walked already.
+    async fn open_link(
+        &mut self,
+        dir: &PendingDir,
+    ) -> Result<Result<Option<(Tree, String, FileId)>>> {
+        let root = self.root.clone()?;
+        let link = path_str(format!("{root}/{}", dir.path))?;
+        let (tree, name, response) = match self.client.create_resolved(list_request(link)).await {
+            Ok(opened) => opened,
+            Err(e @ (Error::NtStatus(_) | Error::Request(_))) => return Ok(Err(e)),
+            Err(e) => return Err(e),
+        };
+        let file_id = response.file_id;
+        match self
+            .client
+            .query_info::<FileInternalInformation>(file_id)
+            .await
+        {
+            Ok(internal) if self.visited.insert((tree, internal.index_number)) => {
+                // Listed and closed on `tree` along with the rest, by the id the server knows
+                let (tree, file_id) = self.client.take_open(file_id);
+                Ok(Ok(Some((tree, name, file_id))))
+            }
+            internal => {
+                self.client.close(file_id).await?;
+                Ok(internal.map(|_| None))
+            }
+        }
+    }
+
+    /// Lists all of the open directories `file_ids` on `tree`, a request for each in flight at
+    /// once.
+    async fn list_all(
+        &mut self,
+        tree: Tree,
+        file_ids: &[FileId],
+    ) -> Result<Vec<Result<Vec<FileIdBothDirectoryInformation>>>> {
+        let mut listings: Vec<_> = file_ids.iter().map(|_| Ok(vec![])).collect();
+        let mut remaining: Vec<usize> = (0..file_ids.len()).collect();
+        while !remaining.is_empty() {
+            let responses: Vec<Result<QueryDirectoryResponse<FileIdBothDirectoryInformation>>> =
+                self.client
+                    .request_all(
+                        tree,
+                        remaining.iter().map(|&i| QueryDirectoryRequest {
+                            file_information_class:
+                                FileIdBothDirectoryInformation::file_information_class(),
+                            flags: QueryDirectoryFlags::empty(),
+                            file_index: 0,
+                            file_id: file_ids[i],
+                            output_buffer_length: IO_SIZE as u32,
+                            search_pattern: "*".into(),
+                        }),
+                    )
+                    .await?;
+            let mut still_remaining = vec![];
+            for (i, response) in remaining.into_iter().zip(responses) {
+                match response {
+                    Ok(response) => {
+                        if let Ok(listing) = &mut listings[i] {
+                            listing.extend(response.entries.into_iter().map(|e| e.body));
+                        }
+                        still_remaining.push(i);
+                    }
+                    Err(e)
+                        if matches!(
+                            e.nt_status(),
+                            Some(NtStatus::NoMoreFiles | NtStatus::NoSuchFile)
+                        ) => {}
+                    Err(e) => listings[i] = Err(e),
+                }
+            }
+            remaining = still_remaining;
+        }
+        Ok(listings)
+    }
+
+    /// Adds what was found in `dir`, queueing the directories in it to be listed.
+    fn add_entries(&mut self, dir: &PendingDir, listing: Vec<FileIdBothDirectoryInformation>) {
+        let depth = dir.depth + 1;
+        for info in listing {
+            if info.file_name == "." || info.file_name == ".." {
+                continue;
+            }
+            let entry = WalkEntry {
+                path: if dir.path.is_empty() {
+                    info.file_name.clone()
+                } else {
+                    format!("{}/{}", dir.path, info.file_name)
+                },
+                depth,
+                info,
+            };
+            if self.exclude.iter().any(|glob| glob.matches(&entry)) {
+                continue;
+            }
+
+            let descend = entry.is_dir()
+                && depth < self.max_depth
+                && (self.follow_links || !entry.is_reparse_point())
+                && !self.prune.as_mut().is_some_and(|prune| prune(&entry));
+            let tree = dir.tree;
+            if descend
+                && (entry.is_reparse_point()
+                    || !self.follow_links
+                    || self.visited.insert((tree, entry.info.file_id)))
+            {
+                let name = (!entry.is_reparse_point())
+                    .then(|| {
+                        dir.name
+                            .as_ref()
+                            .map(|name| join_name(name, entry.file_name()))
+                    })
+                    .flatten();
+                self.pending.push_back(PendingDir {
+                    tree,
+                    name,
+                    path: entry.path.clone(),
+                    depth,
+                });
+            }
+
+            let included =
+                self.include.is_empty() || self.include.iter().any(|glob| glob.matches(&entry));
+            if depth >= self.min_depth && included {
+                self.entries.push_back(Ok(entry));
+            }
+        }
+    }
+}
+
+/// A request opening the directory `name` to list it.
+fn list_request(name: String) -> CreateRequest {
+    CreateRequest {
+        requested_oplock_level: OplockLevel::None,
+        impersonation_level: ImpersonationLevel::Impersonation,
+        desired_access: AccessMask::FILE_LIST_DIRECTORY | AccessMask::FILE_READ_ATTRIBUTES,
+        file_attributes: FileAttributes::empty(),
+        share_access: FileShareAccess::READ | FileShareAccess::WRITE | FileShareAccess::DELETE,
+        create_disposition: FileCreateDisposition::Open,
+        create_options: FileCreateOptions::DIRECTORY_FILE,
+        name,
+        create_contexts: vec![],
+    }
+}
+
+#[test]
+fn glob_patterns() {
+    let matches = |pattern: &str, name: &str| {
+        glob_matches(
+            &pattern.chars().collect::<Vec<_>>(),
+            &name.chars().collect::<Vec<_>>(),
+        )
+    };
+    assert!(matches("*.txt", "a.TXT"));
+    assert!(matches("?.txt", "a.txt"));
+    assert!(!matches("?.txt", "ab.txt"));
+    assert!(!matches("*.txt", "a/b.txt"));
+    assert!(matches("a/*.txt", "a/b.txt"));
+    assert!(matches("**/*.txt", "a/b/c.txt"));
+    assert!(matches("a/**", "a/b/c"));
+    assert!(!matches("b/**", "a/b/c"));
+    assert!(matches("*", ""));
+}
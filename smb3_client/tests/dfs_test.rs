@@ -0,0 +1,134 @@
+// Copyright Remi Bernotavicius
+
+use smb3::{
+    DfsReferralEntry, DfsReferralEntryFlags, DfsReferralHeaderFlags, DfsReferralResponse,
+    DfsReferralTarget, DfsServerType,
+};
+use smb3_client::dfs::ReferralCache;
+use std::time::{Duration, Instant};
+
+fn referrals(dfs_path: &str, targets: &[&str], time_to_live: u32) -> DfsReferralResponse {
+    DfsReferralResponse {
+        path_consumed: (dfs_path.len() * 2) as u16,
+        flags: DfsReferralHeaderFlags::STORAGE_SERVERS,
+        entries: targets
+            .iter()
+            .map(|target| DfsReferralEntry {
+                version: 3,
+                server_type: DfsServerType::NonRoot,
+                flags: DfsReferralEntryFlags::empty(),
+                time_to_live,
+                target: DfsReferralTarget::Path {
+                    dfs_path: dfs_path.into(),
+                    dfs_alternate_path: dfs_path.into(),
+                    network_address: (*target).into(),
+                },
+            })
+            .collect(),
+    }
+}
+
+#[test]
+fn resolve_rewrites_prefix() {
+    let now = Instant::now();
+    let mut cache = ReferralCache::new();
+    cache.insert(
+        "\\srv\\dfs\\link\\file",
+        &referrals("\\srv\\dfs\\link", &["\\a\\share", "\\b\\share\\dir"], 300),
+        now,
+    );
+
+    assert_eq!(
+        cache.resolve("\\srv\\dfs\\link\\file", now),
+        vec!["\\a\\share\\file", "\\b\\share\\dir\\file"]
+    );
+    assert_eq!(
+        cache.resolve("\\SRV\\DFS\\LINK", now),
+        vec!["\\a\\share", "\\b\\share\\dir"]
+    );
+
+    // Only whole components match
+    assert!(cache.resolve("\\srv\\dfs\\linked\\file", now).is_empty());
+    assert!(cache.resolve("\\srv\\dfs\\other", now).is_empty());
+}
+
+#[test]
+fn resolve_prefers_longest_match() {
+    let now = Instant::now();
+    let mut cache = ReferralCache::new();
+    cache.insert(
+        "\\srv\\dfs",
+        &referrals("\\srv\\dfs", &["\\root\\dfs"], 300),
+        now,
+    );
+    cache.insert(
+        "\\srv\\dfs\\link",
+        &referrals("\\srv\\dfs\\link", &["\\a\\share"], 300),
+        now,
+    );
+
+    assert_eq!(
+        cache.resolve("\\srv\\dfs\\link\\file", now),
+        vec!["\\a\\share\\file"]
+    );
+    assert_eq!(
+        cache.resolve("\\srv\\dfs\\file", now),
+        vec!["\\root\\dfs\\file"]
+    );
+}
+
+#[test]
+fn entries_expire() {
+    let now = Instant::now();
+    let mut cache = ReferralCache::new();
+    cache.insert(
+        "\\srv\\dfs\\link",
+        &referrals("\\srv\\dfs\\link", &["\\a\\share"], 10),
+        now,
+    );
+
+    let later = now + Duration::from_secs(11);
+    assert!(cache.resolve("\\srv\\dfs\\link", later).is_empty());
+
+    // A new referral replaces the old targets
+    cache.insert(
+        "\\srv\\dfs\\link",
+        &referrals("\\srv\\dfs\\link", &["\\b\\share"], 10),
+        later,
+    );
+    assert_eq!(cache.resolve("\\srv\\dfs\\link", later), vec!["\\b\\share"]);
+}
+
+#[test]
+fn zero_ttl_entries_resolve_once() {
+    let now = Instant::now();
+    let mut cache = ReferralCache::new();
+    cache.insert(
+        "\\srv\\dfs\\link",
+        &referrals("\\srv\\dfs\\link", &["\\a\\share"], 0),
+        now,
+    );
+
+    assert_eq!(cache.resolve("\\srv\\dfs\\link", now), vec!["\\a\\share"]);
+    let later = now + Duration::from_secs(1);
+    assert!(cache.resolve("\\srv\\dfs\\link", later).is_empty());
+}
+
+#[test]
+fn path_consumed_decides_the_prefix() {
+    let now = Instant::now();
+    let mut cache = ReferralCache::new();
+    // The server only consumed `\srv\dfs\link`, even though the entry names the whole path
+    let mut response = referrals("\\srv\\dfs\\link\\dir", &["\\a\\share"], 300);
+    response.path_consumed = ("\\srv\\dfs\\link".len() * 2) as u16;
+    cache.insert("\\srv\\dfs\\link\\dir\\file", &response, now);
+
+    assert_eq!(
+        cache.resolve("\\srv\\dfs\\link\\dir\\file", now),
+        vec!["\\a\\share\\dir\\file"]
+    );
+    assert_eq!(
+        cache.resolve("\\srv\\dfs\\link\\other", now),
+        vec!["\\a\\share\\other"]
+    );
+}
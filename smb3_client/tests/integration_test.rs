@@ -3,14 +3,23 @@
 use assert_matches::assert_matches;
 use serde::de::DeserializeOwned;
 use smb3::{
-    AccessMask, FileAccessInformation, FileAlignmentInformation, FileAlignmentRequirement,
-    FileAllInformation, FileAttributes, FileBasicInformation, FileEaInformation,
-    FileEndOfFileInformation, FileId, FileInternalInformation, FileMode, FileModeInformation,
-    FileNameInformation, FilePositionInformation, FileStandardInformation, HasFileInformationClass,
-    NtStatus, Time,
+    AccessMask, Command, FileAccessInformation, FileAlignmentInformation, FileAlignmentRequirement,
+    FileAllInformation, FileAttributes, FileBasicInformation, FileDirectoryInformation,
+    FileEaInformation, FileEndOfFileInformation, FileFsAttributeInformation,
+    FileFsFullSizeInformation, FileFsSizeInformation, FileId, FileIdExtdDirectoryInformation,
+    FileInternalInformation, FileMode, FileModeInformation, FileNameInformation,
+    FileNamesInformation, FilePositionInformation, FileStandardInformation, FileSystemAttributes,
+    HasFileInformationClass, NtStatus, QueryDirectoryFlags, RenameFlags, SecurityInformation, Time,
+};
+use smb3_client::{
+    stream_path, Client, Error, FileTimes, InMemoryMetrics, OpenOptions, QueryDirectoryOptions,
+    ShareKind, TimeUpdate, WalkEntry, PORT,
 };
-use smb3_client::{Client, Error, PORT};
 use std::collections::BTreeSet;
+use std::io::{self, SeekFrom};
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::io::{AsyncReadExt as _, AsyncSeekExt as _, AsyncWriteExt as _};
 use tokio::net::TcpStream;
 
 macro_rules! test {
@@ -38,19 +47,40 @@ impl<'machine> Fixture<'machine> {
             .find(|p| p.guest == PORT)
             .unwrap();
         let transport = TcpStream::connect(("127.0.0.1", port.host)).await.unwrap();
-        let client = Client::new(transport, "root", "a", "files").await.unwrap();
+        let client = Client::new(transport, "root", "a", "\\\\127.0.0.1\\files")
+            .await
+            .unwrap();
 
         Self { machine, client }
     }
 
     async fn run(&mut self) {
+        test!(self, compound_test);
+        test!(self, create_dir_test);
+        test!(self, delete_open_test);
         test!(self, delete_test);
+        test!(self, ea_test);
+        test!(self, file_handle_test);
+        test!(self, fs_test);
+        test!(self, hard_link_test);
+        test!(self, list_shares_test);
+        test!(self, metrics_test);
+        test!(self, named_pipe_test);
+        test!(self, open_options_test);
+        test!(self, query_directory_options_test);
         test!(self, query_directory_test_large);
         test!(self, query_directory_test_small);
+        test!(self, query_fs_info_test);
         test!(self, query_info_test);
         test!(self, read_write_test);
+        test!(self, remove_dir_all_test);
+        test!(self, rename_ex_test);
         test!(self, rename_test);
         test!(self, resize_test);
+        test!(self, security_test);
+        test!(self, set_times_test);
+        test!(self, streams_test);
+        test!(self, walk_test);
     }
 
     //  _          _
@@ -67,7 +97,7 @@ impl<'machine> Fixture<'machine> {
         reply.end_of_file
     }
 
-    async fn query_info<Info: DeserializeOwned + HasFileInformationClass>(
+    async fn query_info<Info: DeserializeOwned + HasFileInformationClass + std::fmt::Debug>(
         &mut self,
         file_id: FileId,
     ) -> Info {
@@ -109,6 +139,97 @@ impl<'machine> Fixture<'machine> {
         self.client.close(root).await.unwrap();
     }
 
+    async fn open_options_test(&mut self) {
+        let options = OpenOptions::new().write(true).create(true);
+        let mut file = options.open(&mut self.client, "/a_file").await.unwrap();
+        file.write_all(b"hello").await.unwrap();
+        file.close().await.unwrap();
+
+        let options = OpenOptions::new().append(true);
+        let mut file = options.open(&mut self.client, "/a_file").await.unwrap();
+        file.write_all(b" world").await.unwrap();
+        file.close().await.unwrap();
+
+        let mut file = self.client.open("/a_file").await.unwrap();
+        let mut contents = String::new();
+        file.read_to_string(&mut contents).await.unwrap();
+        assert_eq!(contents, "hello world");
+        assert!(file.write_all(b"no").await.is_err());
+        file.close().await.unwrap();
+
+        let options = OpenOptions::new().write(true).create_new(true);
+        assert_matches!(
+            options
+                .open(&mut self.client, "/a_file")
+                .await
+                .unwrap_err()
+                .nt_status(),
+            Some(NtStatus::ObjectNameCollision)
+        );
+
+        let options = OpenOptions::new().write(true).truncate(true);
+        let file = options.open(&mut self.client, "/a_file").await.unwrap();
+        file.close().await.unwrap();
+        let file_id = self.client.look_up("/a_file").await.unwrap();
+        let info: FileStandardInformation = self.client.query_info(file_id).await.unwrap();
+        assert_eq!(info.end_of_file, 0);
+        self.client.close(file_id).await.unwrap();
+
+        let options = OpenOptions::new().read(true).create(true);
+        assert_matches!(
+            options.open(&mut self.client, "/b_file").await.unwrap_err(),
+            Error::Io(_)
+        );
+    }
+
+    async fn query_directory_options_test(&mut self) {
+        for f in ["a.txt", "b.txt", "c.bin"] {
+            let file_id = self.client.create_file(format!("/{f}")).await.unwrap();
+            self.client.close(file_id).await.unwrap();
+        }
+        let root = self.client.look_up("/").await.unwrap();
+
+        // One entry per request, to go through several requests
+        let options = QueryDirectoryOptions::new()
+            .pattern("*.txt")
+            .flags(QueryDirectoryFlags::RETURN_SINGLE_ENTRY);
+        let mut entries = self
+            .client
+            .query_directory_entries::<FileNamesInformation>(root, options.clone());
+        let mut names = BTreeSet::new();
+        while let Some(entry) = entries.next_entry().await.unwrap() {
+            names.insert(entry.file_name);
+        }
+        assert_eq!(names, BTreeSet::from(["a.txt".into(), "b.txt".into()]));
+
+        // The listing is over until it is restarted
+        let mut entries = self
+            .client
+            .query_directory_entries::<FileNamesInformation>(root, options.clone());
+        assert_eq!(entries.next_entry().await.unwrap(), None);
+
+        let options = options.flags(QueryDirectoryFlags::RESTART_SCANS);
+        let mut entries = self
+            .client
+            .query_directory_entries::<FileDirectoryInformation>(root, options);
+        let mut count = 0;
+        while let Some(entry) = entries.next_entry().await.unwrap() {
+            assert!(entry.file_name.ends_with(".txt"));
+            count += 1;
+        }
+        assert_eq!(count, 2);
+
+        // Nothing matching isn't an error
+        let options = QueryDirectoryOptions::new()
+            .pattern("*.none")
+            .flags(QueryDirectoryFlags::REOPEN);
+        let mut entries = self
+            .client
+            .query_directory_entries::<FileIdExtdDirectoryInformation>(root, options);
+        assert_eq!(entries.next_entry().await.unwrap(), None);
+        self.client.close(root).await.unwrap();
+    }
+
     async fn query_directory_test_small(&mut self) {
         self.query_directory_test_with_dir_size(5).await;
     }
@@ -117,6 +238,25 @@ impl<'machine> Fixture<'machine> {
         self.query_directory_test_with_dir_size(60).await;
     }
 
+    async fn query_fs_info_test(&mut self) {
+        let root = self.client.look_up("/").await.unwrap();
+
+        let size: FileFsSizeInformation = self.client.query_fs_info(root).await.unwrap();
+        assert!(size.total_bytes() > 0);
+        assert!(size.available_bytes() <= size.total_bytes());
+
+        let full_size: FileFsFullSizeInformation = self.client.query_fs_info(root).await.unwrap();
+        assert_eq!(full_size.total_bytes(), size.total_bytes());
+
+        let attributes: FileFsAttributeInformation = self.client.query_fs_info(root).await.unwrap();
+        assert!(attributes
+            .file_system_attributes
+            .contains(FileSystemAttributes::CASE_PRESERVED_NAMES));
+        assert!(attributes.maximum_component_name_length >= 255);
+
+        self.client.close(root).await.unwrap();
+    }
+
     async fn read_write_test(&mut self) {
         let file_id = self.client.create_file("/a_file").await.unwrap();
 
@@ -235,24 +375,417 @@ impl<'machine> Fixture<'machine> {
         self.client.close(file_id).await.unwrap();
     }
 
+    async fn compound_test(&mut self) {
+        self.client.write_file("/a_file", b"hello").await.unwrap();
+        let info: FileStandardInformation = self.client.query_info_path("/a_file").await.unwrap();
+        assert_eq!(info.end_of_file, 5);
+        assert!(!info.directory);
+
+        self.client
+            .rename_path("/a_file", "/b_file", false)
+            .await
+            .unwrap();
+        assert!(!self.client.exists("/a_file").await.unwrap());
+        assert_eq!(self.client.read_file("/b_file").await.unwrap(), b"hello");
+
+        // Failing part way through leaves nothing open
+        self.client.write_file("/c_file", b"").await.unwrap();
+        assert_matches!(
+            self.client
+                .rename_path("/b_file", "/c_file", false)
+                .await
+                .unwrap_err()
+                .nt_status(),
+            Some(NtStatus::ObjectNameCollision)
+        );
+        assert_matches!(
+            self.client
+                .query_info_path::<FileStandardInformation>("/missing")
+                .await
+                .unwrap_err()
+                .nt_status(),
+            Some(NtStatus::ObjectNameNotFound)
+        );
+        self.client.delete("/b_file").await.unwrap();
+        assert!(!self.client.exists("/b_file").await.unwrap());
+
+        let info: FileAllInformation = self.client.query_info_path("/c_file").await.unwrap();
+        assert_eq!(info.name.name, "\\c_file");
+    }
+
+    async fn create_dir_test(&mut self) {
+        self.client.create_dir("/a_dir").await.unwrap();
+        assert_matches!(
+            self.client
+                .create_dir("/a_dir")
+                .await
+                .unwrap_err()
+                .nt_status(),
+            Some(NtStatus::ObjectNameCollision)
+        );
+
+        self.client.create_dir_all("/a_dir/b/c").await.unwrap();
+        self.client.create_dir_all("/a_dir/b/c").await.unwrap();
+        let file_id = self.client.look_up("/a_dir/b/c").await.unwrap();
+        let info: FileStandardInformation = self.client.query_info(file_id).await.unwrap();
+        assert!(info.directory);
+        self.client.close(file_id).await.unwrap();
+
+        assert_matches!(
+            self.client
+                .remove_dir("/a_dir/b")
+                .await
+                .unwrap_err()
+                .nt_status(),
+            Some(NtStatus::DirectoryNotEmpty)
+        );
+        self.client.remove_dir("/a_dir/b/c").await.unwrap();
+        self.client.remove_dir("/a_dir/b").await.unwrap();
+        assert_matches!(
+            self.client
+                .look_up("/a_dir/b")
+                .await
+                .unwrap_err()
+                .nt_status(),
+            Some(NtStatus::ObjectNameNotFound)
+        );
+    }
+
+    async fn delete_open_test(&mut self) {
+        let file_id = self.client.create_file("/a_file").await.unwrap();
+        self.client.close(file_id).await.unwrap();
+
+        // Deleting works while the file is open elsewhere
+        let other = self.client.look_up("/a_file").await.unwrap();
+        let file_id = self
+            .client
+            .open_with_access("/a_file", AccessMask::DELETE)
+            .await
+            .unwrap();
+        self.client.delete_open(file_id).await.unwrap();
+        self.client.close(file_id).await.unwrap();
+
+        // `delete_open` tries POSIX semantics first, which remove the name right away. Servers
+        // without them get a plain delete pending, which keeps the name until the other open
+        // closes too. Either way the file can't be opened any more.
+        assert_matches!(
+            self.client
+                .look_up("/a_file")
+                .await
+                .unwrap_err()
+                .nt_status(),
+            Some(NtStatus::ObjectNameNotFound | NtStatus::DeletePending)
+        );
+        self.client.close(other).await.unwrap();
+        assert_matches!(
+            self.client
+                .look_up("/a_file")
+                .await
+                .unwrap_err()
+                .nt_status(),
+            Some(NtStatus::ObjectNameNotFound)
+        );
+
+        // Clearing delete pending keeps the file
+        let file_id = self.client.create_file("/a_file").await.unwrap();
+        self.client.close(file_id).await.unwrap();
+        let file_id = self
+            .client
+            .open_with_access("/a_file", AccessMask::DELETE)
+            .await
+            .unwrap();
+        self.client.set_delete_pending(file_id, true).await.unwrap();
+        self.client
+            .set_delete_pending(file_id, false)
+            .await
+            .unwrap();
+        self.client.close(file_id).await.unwrap();
+        let file_id = self.client.look_up("/a_file").await.unwrap();
+        self.client.close(file_id).await.unwrap();
+    }
+
     async fn delete_test(&mut self) {
         let file_id = self.client.create_file("/a_file").await.unwrap();
         self.client.close(file_id).await.unwrap();
         self.client.delete("/a_file").await.unwrap();
         assert_matches!(
-            self.client.look_up("/a_file").await.unwrap_err(),
-            Error::NtStatus(NtStatus::ObjectNameNotFound)
+            self.client
+                .look_up("/a_file")
+                .await
+                .unwrap_err()
+                .nt_status(),
+            Some(NtStatus::ObjectNameNotFound)
         );
     }
 
+    async fn ea_test(&mut self) {
+        let file_id = self.client.create_file("/a_file").await.unwrap();
+        self.client.close(file_id).await.unwrap();
+
+        let file_id = self.client.look_up("/a_file").await.unwrap();
+        assert_eq!(self.client.list_eas(file_id).await.unwrap(), vec![]);
+        assert_eq!(self.client.get_ea(file_id, "color").await.unwrap(), None);
+
+        self.client.set_ea(file_id, "color", b"blue").await.unwrap();
+        self.client.set_ea(file_id, "size", b"12").await.unwrap();
+        assert_eq!(
+            self.client.get_ea(file_id, "color").await.unwrap(),
+            Some(b"blue".to_vec())
+        );
+
+        let names: BTreeSet<_> = self
+            .client
+            .list_eas(file_id)
+            .await
+            .unwrap()
+            .iter()
+            .map(|ea| ea.name().to_lowercase())
+            .collect();
+        assert_eq!(names, BTreeSet::from(["color".into(), "size".into()]));
+
+        self.client.remove_ea(file_id, "color").await.unwrap();
+        assert_eq!(self.client.get_ea(file_id, "color").await.unwrap(), None);
+        self.client.close(file_id).await.unwrap();
+    }
+
+    async fn file_handle_test(&mut self) {
+        let file_id = self.client.create_file("/a_file").await.unwrap();
+        let mut file = self.client.file(file_id);
+        file.write_all(b"hello world").await.unwrap();
+        file.flush().await.unwrap();
+
+        assert_eq!(file.seek(SeekFrom::Start(6)).await.unwrap(), 6);
+        let mut contents = String::new();
+        file.read_to_string(&mut contents).await.unwrap();
+        assert_eq!(contents, "world");
+
+        assert_eq!(file.seek(SeekFrom::End(-5)).await.unwrap(), 6);
+        file.write_all(b"there").await.unwrap();
+        assert_eq!(file.seek(SeekFrom::Current(-11)).await.unwrap(), 0);
+        let mut contents = vec![];
+        tokio::io::copy(&mut file, &mut contents).await.unwrap();
+        assert_eq!(contents, b"hello there");
+        assert!(file.seek(SeekFrom::Current(-12)).await.is_err());
+        file.close().await.unwrap();
+
+        // Dropping it closes it too, so it can be deleted
+        let file = self.client.open("/a_file").await.unwrap();
+        drop(file);
+        self.client.delete("/a_file").await.unwrap();
+        assert_matches!(
+            self.client
+                .look_up("/a_file")
+                .await
+                .unwrap_err()
+                .nt_status(),
+            Some(NtStatus::ObjectNameNotFound)
+        );
+    }
+
+    async fn fs_test(&mut self) {
+        assert!(!self.client.exists("/a_file").await.unwrap());
+        self.client.write_file("/a_file", b"hello").await.unwrap();
+        assert!(self.client.exists("/a_file").await.unwrap());
+        assert_eq!(self.client.read_file("/a_file").await.unwrap(), b"hello");
+
+        // Writing again replaces what was there
+        self.client.write_file("/a_file", b"hi").await.unwrap();
+        assert_eq!(self.client.read_file("/a_file").await.unwrap(), b"hi");
+
+        let metadata = self.client.metadata("/a_file").await.unwrap();
+        assert_eq!(metadata.len(), 2);
+        assert!(metadata.is_file());
+        assert!(metadata.modified().unwrap() > SystemTime::UNIX_EPOCH);
+
+        let data: Vec<u8> = (0..200_000).map(|i| i as u8).collect();
+        self.client.write_file("/big_file", &data).await.unwrap();
+        assert_eq!(
+            self.client.copy("/big_file", "/a_file").await.unwrap(),
+            data.len() as u64
+        );
+        assert_eq!(self.client.read_file("/a_file").await.unwrap(), data);
+
+        self.client.create_dir("/a_dir").await.unwrap();
+        assert!(self.client.metadata("/a_dir").await.unwrap().is_dir());
+        let names: BTreeSet<_> = self
+            .client
+            .read_dir("/")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|e| e.file_name)
+            .collect();
+        assert_eq!(
+            names,
+            BTreeSet::from_iter(["a_dir", "a_file", "big_file"].map(String::from))
+        );
+
+        // A failure part way through still closes the file, so it can be deleted
+        assert_matches!(
+            self.client
+                .copy("/a_dir", "/b_file")
+                .await
+                .unwrap_err()
+                .nt_status(),
+            Some(_)
+        );
+        let error = self.client.read_file("/missing").await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        let Error::Request(request) = &error else {
+            panic!("unexpected error {error}");
+        };
+        assert_eq!(request.status, NtStatus::ObjectNameNotFound);
+        assert_eq!(request.command, Command::Create);
+        assert_eq!(request.path.as_deref(), Some("missing"));
+        self.client.remove_dir_all("/a_dir").await.unwrap();
+        assert!(!self.client.exists("/a_dir").await.unwrap());
+    }
+
+    async fn hard_link_test(&mut self) {
+        let file_id = self.client.create_file("/a_file").await.unwrap();
+        self.client
+            .write(file_id, 0, b"hello".to_vec())
+            .await
+            .unwrap();
+        self.client
+            .hard_link(file_id, "/b_file", false)
+            .await
+            .unwrap();
+
+        // Linking over an existing name needs `replace`
+        assert_matches!(
+            self.client
+                .hard_link(file_id, "/b_file", false)
+                .await
+                .unwrap_err()
+                .nt_status(),
+            Some(NtStatus::ObjectNameCollision)
+        );
+        self.client
+            .hard_link(file_id, "/b_file", true)
+            .await
+            .unwrap();
+
+        let info: FileStandardInformation = self.client.query_info(file_id).await.unwrap();
+        assert_eq!(info.number_of_links, 2);
+        self.client.close(file_id).await.unwrap();
+
+        let file_id = self.client.look_up("/b_file").await.unwrap();
+        let mut data = vec![];
+        self.client.read_all(file_id, &mut data).await.unwrap();
+        assert_eq!(data, b"hello");
+        self.client.close(file_id).await.unwrap();
+    }
+
+    async fn list_shares_test(&mut self) {
+        let shares = self.client.list_shares().await.unwrap();
+        let files = shares.iter().find(|s| s.name == "files").unwrap();
+        assert_eq!(files.kind, ShareKind::Disk);
+        assert!(!files.special);
+        let ipc = shares.iter().find(|s| s.name == "IPC$").unwrap();
+        assert_eq!(ipc.kind, ShareKind::Ipc);
+
+        // The pipe is closed again, so the client is still usable
+        let file_id = self.client.create_file("/a_file").await.unwrap();
+        self.client.close(file_id).await.unwrap();
+    }
+
+    async fn metrics_test(&mut self) {
+        let data = vec![7; 150_000];
+        self.client.write_file("/a_file", &data).await.unwrap();
+
+        let metrics = Arc::new(InMemoryMetrics::new());
+        self.client.set_metrics(metrics.clone());
+        assert_eq!(self.client.read_file("/a_file").await.unwrap(), data);
+
+        // Three reads of data and one at the end of the file
+        let counters = metrics.connection("127.0.0.1");
+        assert_eq!(counters.requests(Command::Read), 4);
+        assert_eq!(counters.requests(Command::Create), 1);
+        assert_eq!(counters.requests(Command::Close), 1);
+        assert_eq!(counters.latencies[&Command::Read].len(), 4);
+        assert_eq!(counters.errors.get(&NtStatus::EndOfFile), Some(&1));
+        assert!(counters.bytes_read > data.len() as u64);
+        assert_eq!(counters.timeouts, 0);
+    }
+
+    async fn named_pipe_test(&mut self) {
+        self.client.wait_pipe("srvsvc", None).await.unwrap();
+        let pipe = self.client.open_pipe("srvsvc").await.unwrap();
+        pipe.close().await.unwrap();
+
+        assert_matches!(
+            self.client
+                .open_pipe("not_a_pipe")
+                .await
+                .unwrap_err()
+                .nt_status(),
+            Some(NtStatus::ObjectNameNotFound)
+        );
+    }
+
+    async fn remove_dir_all_test(&mut self) {
+        for dir in ["/a_dir/b", "/a_dir/c/d"] {
+            self.client.create_dir_all(dir).await.unwrap();
+            for i in 0..40 {
+                let file_id = self
+                    .client
+                    .create_file(format!("{dir}/file{i}"))
+                    .await
+                    .unwrap();
+                self.client.close(file_id).await.unwrap();
+            }
+        }
+
+        self.client.remove_dir_all("/a_dir").await.unwrap();
+        assert_matches!(
+            self.client.look_up("/a_dir").await.unwrap_err().nt_status(),
+            Some(NtStatus::ObjectNameNotFound)
+        );
+
+        // The client is still in step with the server afterwards
+        let file_id = self.client.create_file("/a_file").await.unwrap();
+        self.client.close(file_id).await.unwrap();
+    }
+
+    async fn rename_ex_test(&mut self) {
+        let file_id = self.client.create_file("/b_file").await.unwrap();
+        self.client.close(file_id).await.unwrap();
+
+        let file_id = self.client.create_file("/a_file").await.unwrap();
+        self.client.write(file_id, 0, b"a".to_vec()).await.unwrap();
+        assert_matches!(
+            self.client
+                .rename_ex(file_id, "/b_file", RenameFlags::empty())
+                .await
+                .unwrap_err()
+                .nt_status(),
+            Some(NtStatus::ObjectNameCollision)
+        );
+        self.client
+            .rename_ex(file_id, "/b_file", RenameFlags::REPLACE_IF_EXISTS)
+            .await
+            .unwrap();
+        self.client.close(file_id).await.unwrap();
+
+        let file_id = self.client.look_up("/b_file").await.unwrap();
+        let info: FileStandardInformation = self.client.query_info(file_id).await.unwrap();
+        assert_eq!(info.end_of_file, 1);
+        self.client.close(file_id).await.unwrap();
+    }
+
     async fn rename_test(&mut self) {
         let file_id = self.client.create_file("/a_file").await.unwrap();
         self.client.rename(file_id, "/b_file").await.unwrap();
         self.client.close(file_id).await.unwrap();
 
         assert_matches!(
-            self.client.look_up("/a_file").await.unwrap_err(),
-            Error::NtStatus(NtStatus::ObjectNameNotFound)
+            self.client
+                .look_up("/a_file")
+                .await
+                .unwrap_err()
+                .nt_status(),
+            Some(NtStatus::ObjectNameNotFound)
         );
         let file_id = self.client.look_up("/b_file").await.unwrap();
         self.client.close(file_id).await.unwrap();
@@ -265,6 +798,165 @@ impl<'machine> Fixture<'machine> {
         assert_eq!(info.end_of_file, 10000);
         self.client.close(file_id).await.unwrap();
     }
+
+    async fn security_test(&mut self) {
+        let file_id = self.client.create_file("/a_file").await.unwrap();
+        self.client.close(file_id).await.unwrap();
+
+        let info =
+            SecurityInformation::OWNER | SecurityInformation::GROUP | SecurityInformation::DACL;
+        let descriptor = self.client.get_security("/a_file", info).await.unwrap();
+        assert!(descriptor.owner.is_some());
+        assert!(descriptor.group.is_some());
+        assert!(descriptor.dacl.is_some());
+
+        // Writing back the same DACL leaves it unchanged
+        self.client
+            .set_security("/a_file", &descriptor, SecurityInformation::DACL)
+            .await
+            .unwrap();
+        let after = self.client.get_security("/a_file", info).await.unwrap();
+        assert_eq!(after.dacl, descriptor.dacl);
+        assert_eq!(after.to_sddl(), descriptor.to_sddl());
+    }
+
+    async fn streams_test(&mut self) {
+        let file_id = self.client.create_file("/a_file").await.unwrap();
+        self.client
+            .write(file_id, 0, b"hello".to_vec())
+            .await
+            .unwrap();
+        self.client.close(file_id).await.unwrap();
+
+        let streams = self.client.list_streams("/a_file").await.unwrap();
+        assert_eq!(streams.len(), 1);
+        assert!(streams[0].is_default());
+        assert_eq!(streams[0].stream_size, 5);
+
+        // The default stream can also be named explicitly
+        let file_id = self
+            .client
+            .look_up(stream_path("/a_file", ":$DATA").unwrap())
+            .await
+            .unwrap();
+        let mut data = vec![];
+        self.client.read_all(file_id, &mut data).await.unwrap();
+        assert_eq!(data, b"hello");
+        self.client.close(file_id).await.unwrap();
+    }
+
+    async fn walk_test(&mut self) {
+        self.client.create_dir_all("/w/a/b").await.unwrap();
+        self.client.create_dir_all("/w/skip/c").await.unwrap();
+        for f in ["/w/1.txt", "/w/a/2.txt", "/w/a/b/3.dat", "/w/skip/4.txt"] {
+            let file_id = self.client.create_file(f).await.unwrap();
+            self.client.close(file_id).await.unwrap();
+        }
+
+        let paths = |entries: Vec<WalkEntry>| -> BTreeSet<String> {
+            entries.into_iter().map(|e| e.path).collect()
+        };
+        let entries = self.client.walk("/w").collect().await.unwrap();
+        assert_eq!(
+            paths(entries),
+            BTreeSet::from_iter(
+                [
+                    "1.txt",
+                    "a",
+                    "a/2.txt",
+                    "a/b",
+                    "a/b/3.dat",
+                    "skip",
+                    "skip/4.txt",
+                    "skip/c"
+                ]
+                .map(String::from)
+            )
+        );
+
+        let entries = self.client.walk("/w").max_depth(1).collect().await.unwrap();
+        assert_eq!(
+            paths(entries),
+            BTreeSet::from_iter(["1.txt", "a", "skip"].map(String::from))
+        );
+
+        let entries = self
+            .client
+            .walk("/w")
+            .include("*.txt")
+            .exclude("skip")
+            .collect()
+            .await
+            .unwrap();
+        assert_eq!(
+            paths(entries),
+            BTreeSet::from_iter(["1.txt", "a/2.txt"].map(String::from))
+        );
+
+        let entries = self
+            .client
+            .walk("/w")
+            .min_depth(2)
+            .prune(|e| e.path == "a")
+            .collect()
+            .await
+            .unwrap();
+        assert_eq!(
+            paths(entries),
+            BTreeSet::from_iter(["skip/4.txt", "skip/c"].map(String::from))
+        );
+        let entry = self
+            .client
+            .walk("/w")
+            .include("3.dat")
+            .collect()
+            .await
+            .unwrap()
+            .remove(0);
+        assert_eq!(entry.depth, 3);
+        assert!(!entry.is_dir());
+    }
+
+    async fn set_times_test(&mut self) {
+        let file_id = self.client.create_file("/a_file").await.unwrap();
+        let before: FileBasicInformation = self.client.query_info(file_id).await.unwrap();
+
+        let modified = Time {
+            intervals: 0x01d9fb8c14a5ee40,
+        };
+        self.client
+            .set_times(file_id, FileTimes::new().modified(modified.clone()))
+            .await
+            .unwrap();
+        let after: FileBasicInformation = self.client.query_info(file_id).await.unwrap();
+        assert_eq!(after.last_write_time, modified);
+        assert_eq!(after.creation_time, before.creation_time);
+
+        // Frozen times aren't moved by writes
+        self.client
+            .set_times(file_id, FileTimes::new().modified(TimeUpdate::Freeze))
+            .await
+            .unwrap();
+        self.client.write(file_id, 0, b"a".to_vec()).await.unwrap();
+        let after: FileBasicInformation = self.client.query_info(file_id).await.unwrap();
+        assert_eq!(after.last_write_time, modified);
+
+        self.client
+            .set_attributes(file_id, FileAttributes::HIDDEN)
+            .await
+            .unwrap();
+        let after: FileBasicInformation = self.client.query_info(file_id).await.unwrap();
+        assert!(after.file_attributes.contains(FileAttributes::HIDDEN));
+        assert_eq!(after.last_write_time, modified);
+
+        self.client
+            .set_attributes(file_id, FileAttributes::empty())
+            .await
+            .unwrap();
+        let after: FileBasicInformation = self.client.query_info(file_id).await.unwrap();
+        assert!(!after.file_attributes.contains(FileAttributes::HIDDEN));
+        self.client.close(file_id).await.unwrap();
+    }
 }
 
 #[tokio::main]
@@ -0,0 +1,257 @@
+// Copyright Remi Bernotavicius
+
+use assert_matches::assert_matches;
+use smb3_client::rpc::{self, srvsvc, RpcClient, RpcTransport};
+use smb3_client::{Error, Result, ShareInfo, ShareKind};
+use std::collections::VecDeque;
+
+/// Stands in for a server, recording what is written and handing back canned replies.
+#[derive(Default)]
+struct CannedTransport {
+    written: Vec<Vec<u8>>,
+    replies: VecDeque<Vec<u8>>,
+}
+
+impl RpcTransport for CannedTransport {
+    async fn write(&mut self, input: &[u8]) -> Result<()> {
+        self.written.push(input.into());
+        Ok(())
+    }
+
+    async fn transact(&mut self, input: &[u8]) -> Result<Vec<u8>> {
+        self.written.push(input.into());
+        Ok(self.replies.pop_front().unwrap())
+    }
+
+    async fn read(&mut self) -> Result<Vec<u8>> {
+        Ok(self.replies.pop_front().unwrap())
+    }
+}
+
+fn utf16(s: &str) -> Vec<u8> {
+    s.encode_utf16().flat_map(u16::to_le_bytes).collect()
+}
+
+fn bind_request() -> Vec<u8> {
+    [
+        &[0x05, 0x00, 0x0b, 0x03][..], // version, minor version, bind, first + last
+        &[0x10, 0x00, 0x00, 0x00],     // data representation
+        &[0x48, 0x00, 0x00, 0x00],     // frag_length, auth_length
+        &[0x01, 0x00, 0x00, 0x00],     // call_id
+        &[0xb8, 0x10, 0xb8, 0x10],     // max_xmit_frag, max_recv_frag
+        &[0x00, 0x00, 0x00, 0x00],     // assoc_group_id
+        &[0x01, 0x00, 0x00, 0x00],     // n_context_elem, reserved
+        &[0x00, 0x00, 0x01, 0x00],     // context id, n_transfer_syn, reserved
+        &[0xc8, 0x4f, 0x32, 0x4b],     // srvsvc uuid
+        &[0x70, 0x16, 0xd3, 0x01],     // ..
+        &[0x12, 0x78, 0x5a, 0x47],     // ..
+        &[0xbf, 0x6e, 0xe1, 0x88],     // ..
+        &[0x03, 0x00, 0x00, 0x00],     // version 3.0
+        &[0x04, 0x5d, 0x88, 0x8a],     // NDR uuid
+        &[0xeb, 0x1c, 0xc9, 0x11],     // ..
+        &[0x9f, 0xe8, 0x08, 0x00],     // ..
+        &[0x2b, 0x10, 0x48, 0x60],     // ..
+        &[0x02, 0x00, 0x00, 0x00],     // version 2.0
+    ]
+    .concat()
+}
+
+fn bind_ack() -> Vec<u8> {
+    [
+        &[0x05, 0x00, 0x0c, 0x03][..], // version, minor version, bind_ack, first + last
+        &[0x10, 0x00, 0x00, 0x00],     // data representation
+        &[0x44, 0x00, 0x00, 0x00],     // frag_length, auth_length
+        &[0x01, 0x00, 0x00, 0x00],     // call_id
+        &[0xb8, 0x10, 0xb8, 0x10],     // max_xmit_frag, max_recv_frag
+        &[0x53, 0x53, 0x00, 0x00],     // assoc_group_id
+        &[0x0d, 0x00],                 // sec_addr length
+        b"\\PIPE\\srvsvc\0",           // sec_addr
+        &[0x00],                       // padding
+        &[0x01, 0x00, 0x00, 0x00],     // n_results, reserved
+        &[0x00, 0x00, 0x00, 0x00],     // result, reason
+        &[0x04, 0x5d, 0x88, 0x8a],     // NDR uuid
+        &[0xeb, 0x1c, 0xc9, 0x11],     // ..
+        &[0x9f, 0xe8, 0x08, 0x00],     // ..
+        &[0x2b, 0x10, 0x48, 0x60],     // ..
+        &[0x02, 0x00, 0x00, 0x00],     // version 2.0
+    ]
+    .concat()
+}
+
+fn share_enum_request() -> Vec<u8> {
+    [
+        &[0x05, 0x00, 0x00, 0x03][..], // version, minor version, request, first + last
+        &[0x10, 0x00, 0x00, 0x00],     // data representation
+        &[0x50, 0x00, 0x00, 0x00],     // frag_length, auth_length
+        &[0x02, 0x00, 0x00, 0x00],     // call_id
+        &[0x38, 0x00, 0x00, 0x00],     // alloc_hint
+        &[0x00, 0x00, 0x0f, 0x00],     // context id, opnum
+        &[0x00, 0x00, 0x02, 0x00],     // ServerName referent id
+        &[0x04, 0x00, 0x00, 0x00],     // max count
+        &[0x00, 0x00, 0x00, 0x00],     // offset
+        &[0x04, 0x00, 0x00, 0x00],     // actual count
+        &utf16("\\\\S\0"),             // ServerName
+        &[0x01, 0x00, 0x00, 0x00],     // Level
+        &[0x01, 0x00, 0x00, 0x00],     // switch
+        &[0x04, 0x00, 0x02, 0x00],     // container referent id
+        &[0x00, 0x00, 0x00, 0x00],     // EntriesRead
+        &[0x00, 0x00, 0x00, 0x00],     // Buffer (null)
+        &[0xff, 0xff, 0xff, 0xff],     // PreferedMaximumLength
+        &[0x08, 0x00, 0x02, 0x00],     // ResumeHandle referent id
+        &[0x00, 0x00, 0x00, 0x00],     // ResumeHandle
+    ]
+    .concat()
+}
+
+fn share_enum_response_stub() -> Vec<u8> {
+    [
+        &[0x01, 0x00, 0x00, 0x00][..], // Level
+        &[0x01, 0x00, 0x00, 0x00],     // switch
+        &[0x00, 0x00, 0x02, 0x00],     // container referent id
+        &[0x02, 0x00, 0x00, 0x00],     // EntriesRead
+        &[0x04, 0x00, 0x02, 0x00],     // Buffer referent id
+        &[0x02, 0x00, 0x00, 0x00],     // max count
+        &[0x08, 0x00, 0x02, 0x00],     // [0] netname referent id
+        &[0x03, 0x00, 0x00, 0x80],     // [0] type (IPC | SPECIAL)
+        &[0x0c, 0x00, 0x02, 0x00],     // [0] remark referent id
+        &[0x10, 0x00, 0x02, 0x00],     // [1] netname referent id
+        &[0x00, 0x00, 0x00, 0x00],     // [1] type (DISKTREE)
+        &[0x14, 0x00, 0x02, 0x00],     // [1] remark referent id
+        &[0x05, 0x00, 0x00, 0x00],     // max count
+        &[0x00, 0x00, 0x00, 0x00],     // offset
+        &[0x05, 0x00, 0x00, 0x00],     // actual count
+        &utf16("IPC$\0"),              // [0] netname
+        &[0x00, 0x00],                 // padding
+        &[0x0c, 0x00, 0x00, 0x00],     // max count
+        &[0x00, 0x00, 0x00, 0x00],     // offset
+        &[0x0c, 0x00, 0x00, 0x00],     // actual count
+        &utf16("IPC Service\0"),       // [0] remark
+        &[0x07, 0x00, 0x00, 0x00],     // max count
+        &[0x00, 0x00, 0x00, 0x00],     // offset
+        &[0x07, 0x00, 0x00, 0x00],     // actual count
+        &utf16("public\0"),            // [1] netname
+        &[0x00, 0x00],                 // padding
+        &[0x01, 0x00, 0x00, 0x00],     // max count
+        &[0x00, 0x00, 0x00, 0x00],     // offset
+        &[0x01, 0x00, 0x00, 0x00],     // actual count
+        &utf16("\0"),                  // [1] remark
+        &[0x00, 0x00],                 // padding
+        &[0x02, 0x00, 0x00, 0x00],     // TotalEntries
+        &[0x18, 0x00, 0x02, 0x00],     // ResumeHandle referent id
+        &[0x00, 0x00, 0x00, 0x00],     // ResumeHandle
+        &[0x00, 0x00, 0x00, 0x00],     // WERROR
+    ]
+    .concat()
+}
+
+fn response_fragment(flags: u8, call_id: u8, alloc_hint: usize, stub: &[u8]) -> Vec<u8> {
+    let frag_length = (24 + stub.len()) as u16;
+    [
+        &[0x05, 0x00, 0x02, flags][..], // version, minor version, response, flags
+        &[0x10, 0x00, 0x00, 0x00],      // data representation
+        &frag_length.to_le_bytes(),     // frag_length
+        &[0x00, 0x00],                  // auth_length
+        &[call_id, 0x00, 0x00, 0x00],   // call_id
+        &(alloc_hint as u32).to_le_bytes(), // alloc_hint
+        &[0x00, 0x00, 0x00, 0x00],      // context id, cancel count, reserved
+        stub,
+    ]
+    .concat()
+}
+
+#[tokio::test]
+async fn bind_and_share_enum() {
+    let stub = share_enum_response_stub();
+    assert_eq!(stub.len(), 168);
+
+    // The reply is split into two fragments, and the second doesn't fit in the transact reply
+    let (first, second) = stub.split_at(80);
+    let first = response_fragment(0x01, 2, stub.len(), first);
+    let second = response_fragment(0x02, 2, stub.len(), second);
+    let mut reply = first;
+    reply.extend(&second[..10]);
+
+    let transport = CannedTransport {
+        replies: [bind_ack(), reply, second[10..].to_vec()].into(),
+        ..Default::default()
+    };
+
+    let mut rpc = RpcClient::bind(transport, &srvsvc::SRVSVC_SYNTAX)
+        .await
+        .unwrap();
+    let shares = srvsvc::share_enum_all(&mut rpc, Some("\\\\S"))
+        .await
+        .unwrap();
+    assert_eq!(
+        shares,
+        vec![
+            ShareInfo {
+                name: "IPC$".into(),
+                kind: ShareKind::Ipc,
+                special: true,
+                temporary: false,
+                comment: "IPC Service".into(),
+            },
+            ShareInfo {
+                name: "public".into(),
+                kind: ShareKind::Disk,
+                special: false,
+                temporary: false,
+                comment: "".into(),
+            },
+        ]
+    );
+
+    let transport = rpc.into_inner();
+    assert_eq!(
+        transport.written,
+        vec![bind_request(), share_enum_request()]
+    );
+    assert!(transport.replies.is_empty());
+}
+
+#[tokio::test]
+async fn bind_nak() {
+    let nak = [
+        &[0x05, 0x00, 0x0d, 0x03][..], // version, minor version, bind_nak, first + last
+        &[0x10, 0x00, 0x00, 0x00],     // data representation
+        &[0x12, 0x00, 0x00, 0x00],     // frag_length, auth_length
+        &[0x01, 0x00, 0x00, 0x00],     // call_id
+        &[0x04, 0x00],                 // provider reject reason
+    ]
+    .concat();
+    let transport = CannedTransport {
+        replies: [nak].into(),
+        ..Default::default()
+    };
+    assert_matches!(
+        RpcClient::bind(transport, &srvsvc::SRVSVC_SYNTAX).await,
+        Err(Error::Rpc(rpc::Error::BindRejected(4)))
+    );
+}
+
+#[tokio::test]
+async fn fault() {
+    let fault = [
+        &[0x05, 0x00, 0x03, 0x03][..], // version, minor version, fault, first + last
+        &[0x10, 0x00, 0x00, 0x00],     // data representation
+        &[0x20, 0x00, 0x00, 0x00],     // frag_length, auth_length
+        &[0x02, 0x00, 0x00, 0x00],     // call_id
+        &[0x00, 0x00, 0x00, 0x00],     // alloc_hint
+        &[0x00, 0x00, 0x00, 0x00],     // context id, cancel count, reserved
+        &[0x05, 0x00, 0x00, 0x00],     // status (access denied)
+        &[0x00, 0x00, 0x00, 0x00],     // reserved
+    ]
+    .concat();
+    let transport = CannedTransport {
+        replies: [bind_ack(), fault].into(),
+        ..Default::default()
+    };
+    let mut rpc = RpcClient::bind(transport, &srvsvc::SRVSVC_SYNTAX)
+        .await
+        .unwrap();
+    assert_matches!(
+        srvsvc::share_enum_all(&mut rpc, None).await,
+        Err(Error::Rpc(rpc::Error::Fault(5)))
+    );
+}